@@ -0,0 +1,43 @@
+use bft_lib::base_types::{Duration, NodeTime};
+
+#[cfg(test)]
+#[path = "tests/coalescer_tests.rs"]
+pub mod coalescer_tests;
+
+/// Skip the notifications that repeat the latest one sent to the same destinations within
+/// `window`. Notifications are compared by their serialized bytes, hence a notification
+/// reflecting any progress of the node (e.g. a new QC or round) is always sent.
+pub struct NotificationCoalescer<Destinations> {
+    window: Duration,
+    /// Time, destinations, and bytes of the latest notification sent.
+    latest: Option<(NodeTime, Destinations, Vec<u8>)>,
+}
+
+impl<Destinations: PartialEq> NotificationCoalescer<Destinations> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            latest: None,
+        }
+    }
+
+    /// Whether to send a notification to the given destinations at time `clock`. If so, the
+    /// notification is registered as the latest one.
+    pub fn should_send(
+        &mut self,
+        clock: NodeTime,
+        destinations: Destinations,
+        bytes: &[u8],
+    ) -> bool {
+        if let Some((time, latest_destinations, latest_bytes)) = &self.latest {
+            if clock < *time + self.window
+                && *latest_destinations == destinations
+                && latest_bytes.as_slice() == bytes
+            {
+                return false;
+            }
+        }
+        self.latest = Some((clock, destinations, bytes.to_vec()));
+        true
+    }
+}
@@ -13,6 +13,36 @@ pub struct Parameters {
     pub delta: Duration,
     pub gamma: f64,
     pub lambda: f64,
+    /// Maximal number of data-sync requests awaiting a response.
+    #[serde(default = "Parameters::default_max_outstanding_requests")]
+    pub max_outstanding_requests: usize,
+    /// Delay after which a data-sync request without response is no longer outstanding.
+    #[serde(default = "Parameters::default_request_timeout")]
+    pub request_timeout: Duration,
+    /// Delay during which notifications identical to the latest one sent are skipped.
+    #[serde(default = "Parameters::default_coalesce_window")]
+    pub coalesce_window: Duration,
+    /// Maximal delay between two updates of the node, even when no update is scheduled.
+    #[serde(default = "Parameters::default_max_idle_interval")]
+    pub max_idle_interval: Duration,
+}
+
+impl Parameters {
+    fn default_max_outstanding_requests() -> usize {
+        4
+    }
+
+    fn default_request_timeout() -> Duration {
+        Duration(1_000)
+    }
+
+    fn default_coalesce_window() -> Duration {
+        Duration(50)
+    }
+
+    fn default_max_idle_interval() -> Duration {
+        Duration(10_000)
+    }
 }
 
 impl Default for Parameters {
@@ -22,6 +52,10 @@ impl Default for Parameters {
             delta: Duration(5_000),
             gamma: 500.0,
             lambda: 100.0,
+            max_outstanding_requests: Self::default_max_outstanding_requests(),
+            request_timeout: Self::default_request_timeout(),
+            coalesce_window: Self::default_coalesce_window(),
+            max_idle_interval: Self::default_max_idle_interval(),
         }
     }
 }
@@ -66,10 +100,8 @@ impl Committee {
     }
 
     pub fn quorum_threshold(&self) -> Stake {
-        // If N = 3f + 1 + k (0 <= k < 3)
-        // then (2 N + 3) / 3 = 2f + 1 + (2k + 2)/3 = 2f + 1 + k = N - f
         let total_votes: Stake = self.authorities.values().map(|x| x.stake).sum();
-        2 * total_votes / 3 + 1
+        bft_lib::configuration::quorum_threshold(total_votes as usize) as Stake
     }
 
     pub fn address(&self, name: &PublicKey) -> Option<SocketAddr> {
@@ -1,6 +1,6 @@
-use crate::config::Committee;
+use crate::config::{Committee, Parameters};
 use crate::context::Context;
-use crate::core::{ConsensusMessage, CoreDriver};
+use crate::core::{ConsensusMessage, CoreDriver, NodeObserver};
 use async_trait::async_trait;
 use bft_lib::interfaces::{ConsensusNode, DataSyncNode};
 use bft_lib::smr_context::SmrContext;
@@ -21,13 +21,18 @@ pub const CHANNEL_CAPACITY: usize = 1_000;
 pub struct Consensus;
 
 impl Consensus {
+    /// Spawn the consensus engine. Must run within a multi-threaded runtime since signing blocks
+    /// the current thread. The `observer`, if any, is called after every update of the node.
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn<Node, Notification, Request, Response>(
         name: PublicKey,
         committee: Committee,
+        parameters: Parameters,
         signature_service: SignatureService,
         store: Store,
         rx_mempool: Receiver<Payload>,
-        //tx_commit: Sender<dyn CommitCertificate<State>>, //  doesn't have a size known at compile-time
+        tx_commit: Sender<Payload>,
+        observer: Option<NodeObserver<Node>>,
     ) where
         Node: ConsensusNode<Context>
             + Send
@@ -61,11 +66,13 @@ impl Consensus {
         CoreDriver::<Node, Notification, Request, Response>::spawn(
             name,
             committee,
+            parameters,
             signature_service,
             store,
             rx_consensus,
             rx_mempool,
-            //tx_commit
+            tx_commit,
+            observer,
         );
 
         info!("Consensus engine successfully booted");
@@ -5,12 +5,13 @@ use bft_lib::smr_context::*;
 use crypto::{Digest, PublicKey, Signature, SignatureService};
 use ed25519_dalek::Digest as _;
 use ed25519_dalek::Sha512;
-use futures::executor::block_on;
+use log::warn;
 use mempool::Payload;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto as _;
 use store::Store;
+use tokio::sync::mpsc::Sender;
 
 pub struct Context {
     name: PublicKey,
@@ -18,6 +19,10 @@ pub struct Context {
     store: Store,
     signature_service: SignatureService,
     pub buffer: VecDeque<Command>,
+    /// Commands of the states executed so far and not yet committed or discarded.
+    executed: HashMap<State, Command>,
+    /// Where to deliver the commands of committed states, if anywhere.
+    tx_commit: Option<Sender<Command>>,
 }
 
 impl Context {
@@ -33,8 +38,16 @@ impl Context {
             store,
             signature_service,
             buffer: VecDeque::new(),
+            executed: HashMap::new(),
+            tx_commit: None,
         }
     }
+
+    /// Deliver the command of each committed state to the given channel.
+    pub fn with_commit_sender(mut self, tx_commit: Sender<Command>) -> Self {
+        self.tx_commit = Some(tx_commit);
+        self
+    }
 }
 
 // TODO: remove (see comment in SmrContext)
@@ -99,31 +112,63 @@ impl CommandFetcher<Command> for Context {
         // Note: If we return None, LibraBFT-v2 will not propose the block.
         Some(self.buffer.pop_front().unwrap_or_default())
     }
+
+    fn peek(&self) -> Option<Command> {
+        Some(self.buffer.front().cloned().unwrap_or_default())
+    }
+
+    fn has_pending_commands(&self) -> bool {
+        !self.buffer.is_empty()
+    }
 }
 
 // TODO: Remove 'block_on'.
 impl CommandExecutor<Author, State, Command> for Context {
     fn compute(
         &mut self,
-        _base_state: &State,
-        _command: Command,
+        base_state: &State,
+        command: Command,
         _time: NodeTime,
+        _author: Author,
         _previous_author: Option<Author>,
         _previous_voters: Vec<Author>,
     ) -> Option<State> {
         // NOTE: This is called before voting (it is a good time to verify the commands).
-        // Nothing to do here.
-        Some(State::default())
+        // The new state only chains the commands so that we can find them again on commit.
+        let mut hasher = Sha512::new();
+        hasher.update(base_state.to_le_bytes());
+        hasher.update(&command);
+        let state = State::from_le_bytes(hasher.finalize()[..8].try_into().unwrap());
+        self.executed.insert(state, command);
+        Some(state)
     }
 }
 
-impl StateFinalizer<State> for Context {
-    fn commit(&mut self, _state: &State, _certificate: Option<&dyn CommitCertificate<State>>) {
+impl StateFinalizer<Author, State> for Context {
+    fn commit(
+        &mut self,
+        state: &State,
+        _certificate: Option<&dyn CommitCertificate<Author, State>>,
+    ) {
         // NOTE: Certificates come in the right order and only once.
         // TODO: Send commit certificate out to application layer.
+        let command = match self.executed.remove(state) {
+            Some(command) => command,
+            None => {
+                warn!("Committed state {} was never executed", state);
+                return;
+            }
+        };
+        if let Some(tx_commit) = &self.tx_commit {
+            if let Err(e) = tx_commit.try_send(command) {
+                warn!("Failed to deliver committed command: {}", e);
+            }
+        }
     }
 
-    fn discard(&mut self, _state: &State) {}
+    fn discard(&mut self, state: &State) {
+        self.executed.remove(state);
+    }
 
     fn last_committed_state(&self) -> State {
         State::default()
@@ -145,6 +190,14 @@ impl EpochReader<Author, State> for Context {
             .collect();
         EpochConfiguration::new(voting_rights)
     }
+
+    fn epoch_boundary_state(&self, epoch_id: EpochId) -> Option<State> {
+        if epoch_id == EpochId(self.committee.epoch as usize) {
+            Some(State::default())
+        } else {
+            None
+        }
+    }
 }
 
 impl CryptographicModule for Context {
@@ -172,9 +225,8 @@ impl CryptographicModule for Context {
         self.name
     }
 
-    // TODO [issue #8]: Make async to enable HSM implementations.
-    fn sign(&mut self, hash: Self::HashValue) -> Self::Signature {
-        block_on(self.signature_service.request_signature(hash))
+    fn sign(&mut self, hash: Self::HashValue) -> AsyncResult<'_, Self::Signature> {
+        Box::pin(async move { Ok(self.signature_service.request_signature(hash).await) })
     }
 }
 
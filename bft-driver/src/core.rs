@@ -1,5 +1,7 @@
-use crate::config::Committee;
+use crate::coalescer::NotificationCoalescer;
+use crate::config::{Committee, Parameters};
 use crate::context::Context;
+use crate::limiter::RequestLimiter;
 use crate::timer::Timer;
 use bft_lib::base_types::NodeTime;
 use bft_lib::interfaces::{ConsensusNode, DataSyncNode, NodeUpdateActions};
@@ -14,7 +16,10 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::fmt::Debug;
 use std::time::{SystemTime, UNIX_EPOCH};
 use store::Store;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// Callback run on the node after each of its updates, e.g. to report its progress.
+pub type NodeObserver<Node> = Box<dyn FnMut(&Node) + Send>;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ConsensusMessage<Notification, Request, Response> {
@@ -36,11 +41,15 @@ pub struct CoreDriver<Node, Notification, Request, Response> {
     committee: Committee,
     rx_consensus: Receiver<ConsensusMessage<Notification, Request, Response>>,
     rx_mempool: Receiver<Payload>,
-    //tx_commit: Sender<CommitCertificate>,
     node: Node,
     context: Context,
+    observer: Option<NodeObserver<Node>>,
     timer: Timer,
+    /// Maximal delay in milliseconds between two updates of the node.
+    max_idle_interval: u64,
     network: SimpleSender,
+    request_limiter: RequestLimiter,
+    notification_coalescer: NotificationCoalescer<Option<Vec<PublicKey>>>,
 }
 
 impl<Node, Notification, Request, Response> CoreDriver<Node, Notification, Request, Response>
@@ -59,17 +68,26 @@ where
     pub fn spawn(
         name: PublicKey,
         committee: Committee,
+        parameters: Parameters,
         signature_service: SignatureService,
         store: Store,
         rx_consensus: Receiver<ConsensusMessage<Notification, Request, Response>>,
         rx_mempool: Receiver<Payload>,
-        //tx_commit: Sender<CommitCertificate>,
+        tx_commit: Sender<Payload>,
+        observer: Option<NodeObserver<Node>>,
     ) {
-        let mut context = Context::new(name, committee.clone(), store, signature_service);
+        let mut context = Context::new(name, committee.clone(), store, signature_service)
+            .with_commit_sender(tx_commit);
         let node = block_on(Node::load_node(&mut context, Self::local_time()))
             .expect("Failed to load node");
 
         let timer = Timer::new(100); // Bootstrap the timer.
+        let request_limiter = RequestLimiter::new(
+            parameters.max_outstanding_requests,
+            parameters.request_timeout,
+        );
+        let notification_coalescer = NotificationCoalescer::new(parameters.coalesce_window);
+        let max_idle_interval = std::cmp::max(parameters.max_idle_interval.0, 0) as u64;
 
         tokio::spawn(async move {
             Self {
@@ -77,17 +95,29 @@ where
                 committee,
                 rx_consensus,
                 rx_mempool,
-                //tx_commit,
                 context,
                 node,
+                observer,
                 timer,
+                max_idle_interval,
                 network: SimpleSender::new(),
+                request_limiter,
+                notification_coalescer,
             }
             .run()
             .await;
         });
     }
 
+    /// Execute one step of the node. Records created by the node wait for the signature
+    /// service.
+    fn update_node(&mut self, clock: NodeTime) -> NodeUpdateActions<Context> {
+        let (node, context) = (&mut self.node, &mut self.context);
+        // Let the runtime move other tasks (e.g. the signature service) off this thread, which
+        // would otherwise deadlock. This requires a multi-threaded runtime.
+        tokio::task::block_in_place(|| node.update_node(context, clock))
+    }
+
     fn local_time() -> NodeTime {
         NodeTime(
             SystemTime::now()
@@ -127,6 +157,9 @@ where
             .save_node(&mut self.context)
             .await
             .expect("Failed to save node state");
+        if let Some(observer) = &mut self.observer {
+            observer(&self.node);
+        }
 
         let notification = self.node.create_notification(&self.context);
         let message = ConsensusMessage::DataSyncNotification {
@@ -134,11 +167,30 @@ where
             notification,
         };
 
-        if actions.should_broadcast {
-            self.transmit(&message, None).await;
+        let destinations = if actions.should_broadcast {
+            None
         } else {
-            for receiver in actions.should_send {
-                self.transmit(&message, Some(&receiver)).await;
+            Some(actions.should_send)
+        };
+        let has_destinations = !matches!(&destinations, Some(receivers) if receivers.is_empty());
+        if has_destinations {
+            // Skip notifications that repeat the latest one, e.g. after back-to-back updates.
+            let bytes = bincode::serialize(&message).expect("Failed to serialize core message");
+            if self.notification_coalescer.should_send(
+                Self::local_time(),
+                destinations.clone(),
+                &bytes,
+            ) {
+                match destinations {
+                    None => self.transmit(&message, None).await,
+                    Some(receivers) => {
+                        for receiver in receivers {
+                            self.transmit(&message, Some(&receiver)).await;
+                        }
+                    }
+                }
+            } else {
+                debug!("Skipping redundant {:?}", message);
             }
         }
 
@@ -152,7 +204,12 @@ where
             self.transmit(&message, None).await;
         }
 
-        self.timer.reset(actions.next_scheduled_update.0 as u64);
+        // Wake up at the next scheduled update, or after some idle time if none is scheduled.
+        self.timer.reset_at(
+            actions.next_scheduled_update,
+            Self::local_time(),
+            self.max_idle_interval,
+        );
     }
 
     /// Main reactor loop.
@@ -163,11 +220,19 @@ where
                 Some(message) = self.rx_consensus.recv() => {
                     match message {
                         ConsensusMessage::DataSyncNotification{sender, notification} => {
-                            let request = self.node.handle_notification(&mut self.context, notification).await;
-                            let actions = self.node.update_node(&mut self.context, Self::local_time());
+                            let request = self.node.handle_notification(&mut self.context, sender, notification, Self::local_time()).await;
+                            let actions = self.update_node(Self::local_time());
                             if let Some(request) = request {
-                                let message = ConsensusMessage::DataSyncRequest{sender: self.name, request};
-                                self.transmit(&message, Some(&sender)).await;
+                                if self.request_limiter.try_acquire(Self::local_time()) {
+                                    let message = ConsensusMessage::DataSyncRequest{sender: self.name, request};
+                                    self.transmit(&message, Some(&sender)).await;
+                                } else {
+                                    debug!(
+                                        "Skipping {:?}: {} requests are outstanding",
+                                        request,
+                                        self.request_limiter.outstanding_requests()
+                                    );
+                                }
                             }
                             self.process_node_actions(actions).await
                         },
@@ -177,9 +242,10 @@ where
                             self.transmit(&message, Some(&sender)).await
                         },
                         ConsensusMessage::DataSyncResponse{response} => {
+                            self.request_limiter.release();
                             let clock = Self::local_time();
                             self.node.handle_response(&mut self.context, response, clock).await;
-                            let actions = self.node.update_node(&mut self.context, clock);
+                            let actions = self.update_node(clock);
                             self.process_node_actions(actions).await
                         },
                     }
@@ -189,7 +255,7 @@ where
                 },
                 () = &mut self.timer => {
                     let clock = Self::local_time();
-                    let actions = self.node.update_node(&mut self.context, clock);
+                    let actions = self.update_node(clock);
                     self.process_node_actions(actions).await
                 }
             }
@@ -1,9 +1,12 @@
+mod coalescer;
 mod config;
 mod consensus;
 mod context;
 mod core;
+mod limiter;
 mod timer;
 
 pub use crate::config::{Committee, Parameters};
 pub use crate::consensus::Consensus;
 pub use crate::context::Context;
+pub use crate::core::NodeObserver;
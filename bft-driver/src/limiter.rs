@@ -0,0 +1,50 @@
+use bft_lib::base_types::{Duration, NodeTime};
+use std::collections::VecDeque;
+
+#[cfg(test)]
+#[path = "tests/limiter_tests.rs"]
+pub mod limiter_tests;
+
+/// Bound the number of data-sync requests awaiting a response. Responses do not identify
+/// their request, hence any response resolves the oldest outstanding request. Requests without
+/// response stop counting after `timeout`.
+pub struct RequestLimiter {
+    max_outstanding_requests: usize,
+    timeout: Duration,
+    /// Expiration times of the outstanding requests, oldest first.
+    deadlines: VecDeque<NodeTime>,
+}
+
+impl RequestLimiter {
+    pub fn new(max_outstanding_requests: usize, timeout: Duration) -> Self {
+        Self {
+            max_outstanding_requests,
+            timeout,
+            deadlines: VecDeque::new(),
+        }
+    }
+
+    /// Register a new request sent at time `clock`, unless too many requests are outstanding.
+    pub fn try_acquire(&mut self, clock: NodeTime) -> bool {
+        while let Some(deadline) = self.deadlines.front() {
+            if *deadline > clock {
+                break;
+            }
+            self.deadlines.pop_front();
+        }
+        if self.deadlines.len() >= self.max_outstanding_requests {
+            return false;
+        }
+        self.deadlines.push_back(clock + self.timeout);
+        true
+    }
+
+    /// Resolve the oldest outstanding request, if any.
+    pub fn release(&mut self) {
+        self.deadlines.pop_front();
+    }
+
+    pub fn outstanding_requests(&self) -> usize {
+        self.deadlines.len()
+    }
+}
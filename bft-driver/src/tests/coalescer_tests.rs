@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn coalesce_identical_notifications() {
+    let mut coalescer = NotificationCoalescer::new(Duration(100));
+    let notification = vec![1u8, 2, 3];
+    // Back-to-back updates produce the same notification.
+    let sent = (0..10)
+        .filter(|i| coalescer.should_send(NodeTime(*i), None, &notification))
+        .count();
+    assert_eq!(sent, 1);
+
+    // Progress of the node, or other destinations, are never delayed.
+    assert!(coalescer.should_send(NodeTime(10), None, &[1, 2, 4]));
+    assert!(coalescer.should_send(NodeTime(11), Some(0), &[1, 2, 4]));
+    assert!(!coalescer.should_send(NodeTime(12), Some(0), &[1, 2, 4]));
+
+    // The same notification is sent again after the window.
+    assert!(coalescer.should_send(NodeTime(111), Some(0), &[1, 2, 4]));
+}
@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn bounded_outstanding_requests() {
+    let mut limiter = RequestLimiter::new(3, Duration(100));
+    // Many "you are behind" notifications arrive in a burst.
+    let granted = (0..50)
+        .filter(|i| {
+            let granted = limiter.try_acquire(NodeTime(*i));
+            assert!(limiter.outstanding_requests() <= 3);
+            granted
+        })
+        .count();
+    assert_eq!(granted, 3);
+
+    // A response frees a slot.
+    limiter.release();
+    assert!(limiter.try_acquire(NodeTime(50)));
+    assert!(!limiter.try_acquire(NodeTime(51)));
+
+    // Requests without response eventually time out.
+    assert!(limiter.try_acquire(NodeTime(101)));
+    assert_eq!(limiter.outstanding_requests(), 3);
+}
@@ -7,3 +7,23 @@ async fn schedule() {
     timer.await;
     assert!(now.elapsed().as_millis() > 95);
 }
+
+#[tokio::test]
+async fn idle_wakeup() {
+    let mut timer = Timer::new(100);
+    let now = Instant::now();
+    // Nothing is scheduled but the timer still fires after the maximal idle interval.
+    timer.reset_at(NodeTime::never(), NodeTime(1_000), 200);
+    (&mut timer).await;
+    let elapsed = now.elapsed().as_millis();
+    assert!(elapsed > 195);
+    assert!(elapsed < 1_000);
+
+    // Sooner deadlines are unaffected.
+    let now = Instant::now();
+    timer.reset_at(NodeTime(1_050), NodeTime(1_000), 200);
+    timer.await;
+    let elapsed = now.elapsed().as_millis();
+    assert!(elapsed > 45);
+    assert!(elapsed < 195);
+}
@@ -1,3 +1,4 @@
+use bft_lib::base_types::NodeTime;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -22,6 +23,14 @@ impl Timer {
             .as_mut()
             .reset(Instant::now() + Duration::from_millis(duration));
     }
+
+    /// Reset the timer to expire at the absolute time `deadline`, given the current time
+    /// `clock`, but no later than `max_idle_interval` milliseconds from now. This guarantees
+    /// periodic wakeups when nothing is scheduled (i.e. `deadline` is `NodeTime::never()`).
+    pub fn reset_at(&mut self, deadline: NodeTime, clock: NodeTime, max_idle_interval: u64) {
+        let delay = std::cmp::max(deadline.0.saturating_sub(clock.0), 0) as u64;
+        self.reset(std::cmp::min(delay, max_idle_interval));
+    }
 }
 
 impl Future for Timer {
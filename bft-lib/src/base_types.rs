@@ -32,7 +32,7 @@ impl EpochId {
         if self.0 == 0 {
             None
         } else {
-            Some(EpochId(self.0))
+            Some(EpochId(self.0 - 1))
         }
     }
 }
@@ -1,15 +1,36 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 // SPDX-License-Identifier: Apache-2.0
 
+use anyhow::{bail, Result};
 use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256StarStar;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, hash::Hash};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+};
 
 #[cfg(test)]
 #[path = "unit_tests/configuration_tests.rs"]
 mod configuration_tests;
 
+/// Minimal weight of a quorum among nodes of the given total weight. Any two quorums
+/// intersect in at least `validity_threshold(total_votes)`, hence in at least one honest node.
+pub fn quorum_threshold(total_votes: usize) -> usize {
+    // If N = 3f + 1 + k (0 <= k < 3)
+    // then (2 N + 3) / 3 = 2f + 1 + (2k + 2)/3 = 2f + 1 + k = N - f
+    2 * total_votes / 3 + 1
+}
+
+/// Minimal weight of a set of nodes that contains at least one honest node.
+pub fn validity_threshold(total_votes: usize) -> usize {
+    // If N = 3f + 1 + k (0 <= k < 3)
+    // then (N + 2) / 3 = f + 1 + k/3 = f + 1
+    (total_votes + 2) / 3
+}
+
 /// Represent BFT permissions during an epoch. NOTE: The order of the nodes is recorded
 /// and will influence leader selections based on `pick_author`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -19,23 +40,95 @@ pub struct EpochConfiguration<Author: Hash> {
     authors: Vec<(Author, usize)>,
     voting_rights: HashMap<Author, usize>,
     total_votes: usize,
+    /// Total voting rights of the authors up to each position (included), in order.
+    cumulative_votes: Vec<usize>,
+    /// Same as `authors` and `cumulative_votes` for the authors sorted by content.
+    sorted_authors: Vec<(Author, usize)>,
+    sorted_cumulative_votes: Vec<usize>,
+    /// Hash of the sorted authors and their voting rights.
+    content_digest: u64,
+}
+
+/// Total voting rights of the given authors up to each position (included).
+fn cumulative_sums<Author>(authors: &[(Author, usize)]) -> Vec<usize> {
+    authors
+        .iter()
+        .scan(0, |sum, (_, v)| {
+            *sum += *v;
+            Some(*sum)
+        })
+        .collect()
+}
+
+/// Find the first position whose cumulative votes exceed the target, using a binary search.
+fn position_at(cumulative_votes: &[usize], target: usize) -> usize {
+    let (mut low, mut high) = (0, cumulative_votes.len());
+    while low < high {
+        let middle = (low + high) / 2;
+        if cumulative_votes[middle] > target {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    low
 }
 
 impl<Author> EpochConfiguration<Author>
 where
-    Author: Hash + Eq + Clone,
+    Author: Hash + Eq + Clone + Ord,
 {
     /// Create a new epoch.
+    /// Panics if the committee is empty or if the total voting rights are zero, since no
+    /// quorum or leader can be defined in that case.
     pub fn new(authors: Vec<(Author, usize)>) -> Self {
+        assert!(
+            !authors.is_empty(),
+            "An epoch configuration requires at least one author"
+        );
         let voting_rights = authors.iter().cloned().collect();
-        let total_votes = authors.iter().map(|(_, v)| *v).sum();
+        let cumulative_votes = cumulative_sums(&authors);
+        let total_votes = *cumulative_votes.last().unwrap();
+        assert!(
+            total_votes > 0,
+            "An epoch configuration requires a positive total of voting rights"
+        );
+        let mut sorted_authors = authors.clone();
+        sorted_authors.sort();
+        let sorted_cumulative_votes = cumulative_sums(&sorted_authors);
+        let mut hasher = DefaultHasher::new();
+        sorted_authors.hash(&mut hasher);
         EpochConfiguration {
             authors,
             voting_rights,
             total_votes,
+            cumulative_votes,
+            sorted_authors,
+            sorted_cumulative_votes,
+            content_digest: hasher.finish(),
         }
     }
 
+    /// Same as `pick_author` but only depends on the content of the committee, not on the
+    /// order in which authors were inserted: the seed is hashed together with the sorted
+    /// committee, which is then searched in sorted order.
+    pub fn pick_author_by_content(&self, seed: u64) -> Author {
+        let mut hasher = DefaultHasher::new();
+        self.content_digest.hash(&mut hasher);
+        seed.hash(&mut hasher);
+        let mut rng = Xoshiro256StarStar::seed_from_u64(hasher.finish());
+        let position = position_at(
+            &self.sorted_cumulative_votes,
+            rng.gen_range(0..self.total_votes),
+        );
+        self.sorted_authors[position].0.clone()
+    }
+}
+
+impl<Author> EpochConfiguration<Author>
+where
+    Author: Hash + Eq + Clone,
+{
     pub fn weight(&self, author: &Author) -> usize {
         *self.voting_rights.get(author).unwrap_or(&0)
     }
@@ -50,28 +143,64 @@ where
     }
 
     pub fn quorum_threshold(&self) -> usize {
-        // If N = 3f + 1 + k (0 <= k < 3)
-        // then (2 N + 3) / 3 = 2f + 1 + (2k + 2)/3 = 2f + 1 + k = N - f
-        2 * self.total_votes / 3 + 1
+        quorum_threshold(self.total_votes)
+    }
+
+    /// Maximal weight of faulty nodes that the committee tolerates.
+    pub fn max_faulty_weight(&self) -> usize {
+        // If N = 3f + 1 + k (0 <= k < 3) then N - (N - f) = f
+        self.total_votes - self.quorum_threshold()
+    }
+
+    pub fn is_quorum(&self, weight: usize) -> bool {
+        weight >= self.quorum_threshold()
     }
 
     pub fn validity_threshold(&self) -> usize {
-        // If N = 3f + 1 + k (0 <= k < 3)
-        // then (N + 2) / 3 = f + 1 + k/3 = f + 1
-        (self.total_votes + 2) / 3
+        validity_threshold(self.total_votes)
     }
 
-    // TODO: this function is linear-time in the number of nodes.
+    /// Pick an author with a probability proportional to its voting rights. Takes a
+    /// logarithmic time in the number of authors.
     pub fn pick_author(&self, seed: u64) -> Author {
         let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
-        let mut target = rng.gen_range(0..self.total_votes);
-        for (author, votes) in &self.authors {
-            if *votes > target {
-                return author.clone();
-            }
-            target -= *votes;
+        self.author_at_cumulative_position(rng.gen_range(0..self.total_votes))
+    }
+
+    /// Author owning the vote at position `index` modulo the total voting rights, where the
+    /// votes of each author are consecutive and follow the order of the authors.
+    pub fn author_at(&self, index: usize) -> Author {
+        self.author_at_cumulative_position(index % self.total_votes)
+    }
+
+    /// Author owning the vote at position `target` (less than the total voting rights).
+    fn author_at_cumulative_position(&self, target: usize) -> Author {
+        self.authors[position_at(&self.cumulative_votes, target)]
+            .0
+            .clone()
+    }
+}
+
+impl<Author> EpochConfiguration<Author>
+where
+    Author: Hash + Eq + Clone + Ord + Serialize + DeserializeOwned,
+{
+    /// Write the committee to a JSON file, as the ordered list of authors and their voting
+    /// rights.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = serde_json::to_string_pretty(&self.authors)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Read a committee written by `to_file`. The order of the authors is preserved.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path)?;
+        let authors: Vec<(Author, usize)> = serde_json::from_slice(&data)?;
+        if authors.iter().map(|(_, v)| *v).sum::<usize>() == 0 {
+            bail!("A committee requires a positive total of voting rights");
         }
-        unreachable!()
+        Ok(Self::new(authors))
     }
 }
 
@@ -2,32 +2,92 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    base_types::{Duration, EpochId, Round},
     simulated_context::Author,
-    simulator::{ActiveRound, Event, GlobalTime, Simulator},
+    simulator::{
+        ActiveEpoch, ActiveRound, CommitGap, CommittedCommandCount, Event, ForkRate, GlobalTime,
+        Simulator, ViewChangeLatencies,
+    },
 };
-use std::{fs, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Parameters of a simulation, written to `run_metadata.json` so that the run can be reproduced
+/// from its data files.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub seed: u64,
+    pub nodes: usize,
+    pub delay_mean: f64,
+    pub delay_variance: f64,
+    pub delta: Option<Duration>,
+    pub gamma: Option<f64>,
+    pub lambda: Option<f64>,
+}
+
+/// Activity of the network during an interval of time.
+#[derive(Clone, Default)]
+struct ThroughputBucket {
+    notifications: usize,
+    requests: usize,
+    responses: usize,
+    /// Highest number of commands committed by a node at the end of the interval.
+    committed_commands: usize,
+}
 
 pub struct DataWriter {
     data_files_path: String,
     nodes_len: usize,
     // Variables for monitoring round switches
-    max_round_per_node: Vec<usize>,
-    nodes_round_switch: Vec<Vec<(usize, GlobalTime)>>,
+    max_round_per_node: Vec<(EpochId, usize)>,
+    nodes_round_switch: Vec<Vec<(EpochId, usize, GlobalTime)>>,
+    // Variables for monitoring the gap between certified and committed rounds
+    nodes_commit_gap: Vec<Vec<(usize, GlobalTime)>>,
+    // Variables for monitoring the latency of view changes
+    nodes_view_changes: Vec<Vec<(Round, Duration, GlobalTime)>>,
+    view_changes_seen_per_node: Vec<usize>,
+    // Variables for monitoring discarded states and fork rates
+    nodes_fork_rates: Vec<Vec<(usize, f64, GlobalTime)>>,
+    // Variables for monitoring commit latencies
+    highest_commit_per_node: Vec<(EpochId, Round)>,
+    commit_times: BTreeMap<(EpochId, Round), Vec<Option<GlobalTime>>>,
+    // Variables for monitoring the throughput, indexed by intervals of `bucket_size`
+    bucket_size: GlobalTime,
+    throughput: BTreeMap<i64, ThroughputBucket>,
     message_counter: usize, // Counts the number of messages
 }
 
 impl DataWriter {
-    pub fn new(nodes_num: usize, path: String) -> DataWriter {
+    pub fn new(
+        nodes_num: usize,
+        path: String,
+        metadata: &RunMetadata,
+        bucket_size: GlobalTime,
+    ) -> DataWriter {
         let data_writer = DataWriter {
             nodes_len: nodes_num,
-            max_round_per_node: vec![0; nodes_num],
+            max_round_per_node: vec![(EpochId(0), 0); nodes_num],
             nodes_round_switch: vec![Vec::new(); nodes_num],
+            nodes_commit_gap: vec![Vec::new(); nodes_num],
+            nodes_view_changes: vec![Vec::new(); nodes_num],
+            view_changes_seen_per_node: vec![0; nodes_num],
+            nodes_fork_rates: vec![Vec::new(); nodes_num],
+            highest_commit_per_node: vec![(EpochId(0), Round(0)); nodes_num],
+            commit_times: BTreeMap::new(),
+            bucket_size,
+            throughput: BTreeMap::new(),
             data_files_path: path,
             message_counter: 0,
         };
         if !Path::new(&data_writer.data_files_path).exists() {
             fs::create_dir(&data_writer.data_files_path).expect("could not create result dir");
         }
+        let file = fs::File::create(format!(
+            "{}/{}",
+            data_writer.data_files_path, "run_metadata.json"
+        ))
+        .expect("could not create metadata file");
+        serde_json::to_writer_pretty(file, metadata).expect("Writing did not succeed");
         data_writer
     }
 
@@ -36,26 +96,142 @@ impl DataWriter {
         simulator: &Simulator<State, Context, Notification, Request, Response>,
         clock: &GlobalTime,
     ) where
-        State: ActiveRound,
+        State: ActiveRound + ActiveEpoch,
     {
         for node_num in 0..self.nodes_len {
             let node = simulator.simulated_node(Author(node_num));
-            let node_round = node.active_round().0;
-            if node_round > *self.max_round_per_node.get(node_num).unwrap() {
+            // Rounds start over at each epoch.
+            let node_round = (node.active_epoch(), node.active_round().0);
+            if node_round > self.max_round_per_node[node_num] {
                 self.max_round_per_node[node_num] = node_round;
-                self.nodes_round_switch[node_num].push((node_round, *clock))
+                self.nodes_round_switch[node_num].push((node_round.0, node_round.1, *clock))
+            }
+        }
+    }
+
+    pub fn update_commit_gap<State, Context, Notification, Request, Response>(
+        &mut self,
+        simulator: &Simulator<State, Context, Notification, Request, Response>,
+        clock: &GlobalTime,
+    ) where
+        State: CommitGap,
+    {
+        for node_num in 0..self.nodes_len {
+            let node = simulator.simulated_node(Author(node_num));
+            let commit_gap = node.commit_gap();
+            let series = &mut self.nodes_commit_gap[node_num];
+            if series.last().map(|x| x.0) != Some(commit_gap) {
+                series.push((commit_gap, *clock))
+            }
+        }
+    }
+
+    pub fn update_view_changes<State, Context, Notification, Request, Response>(
+        &mut self,
+        simulator: &Simulator<State, Context, Notification, Request, Response>,
+        clock: &GlobalTime,
+    ) where
+        State: ViewChangeLatencies,
+    {
+        for node_num in 0..self.nodes_len {
+            let node = simulator.simulated_node(Author(node_num));
+            let latencies = node.view_change_latencies();
+            let seen = &mut self.view_changes_seen_per_node[node_num];
+            if latencies.len() < *seen {
+                // A new epoch has started.
+                *seen = 0;
+            }
+            for (round, latency) in &latencies[*seen..] {
+                self.nodes_view_changes[node_num].push((*round, *latency, *clock));
             }
+            *seen = latencies.len();
+        }
+    }
+
+    pub fn update_fork_rates<State, Context, Notification, Request, Response>(
+        &mut self,
+        simulator: &Simulator<State, Context, Notification, Request, Response>,
+        clock: &GlobalTime,
+    ) where
+        Context: ForkRate,
+    {
+        for node_num in 0..self.nodes_len {
+            let context = simulator.simulated_node(Author(node_num)).context();
+            let discarded = context.discarded_state_count();
+            let series = &mut self.nodes_fork_rates[node_num];
+            if series.last().map(|x| x.0) != Some(discarded) {
+                series.push((discarded, context.fork_rate(), *clock))
+            }
+        }
+    }
+
+    /// Record that `author` has committed `round` of `epoch_id` at time `clock`. Only the first
+    /// commit of a round is kept for each node.
+    pub fn record_commit(
+        &mut self,
+        author: Author,
+        epoch_id: EpochId,
+        round: Round,
+        clock: GlobalTime,
+    ) {
+        let highest_commit = &mut self.highest_commit_per_node[author.0];
+        if *highest_commit == (epoch_id, round) {
+            return;
+        }
+        *highest_commit = (epoch_id, round);
+        if round == Round(0) {
+            // Nothing was committed yet in this epoch.
+            return;
+        }
+        let nodes_len = self.nodes_len;
+        let time = &mut self
+            .commit_times
+            .entry((epoch_id, round))
+            .or_insert_with(|| vec![None; nodes_len])[author.0];
+        if time.is_none() {
+            *time = Some(clock);
         }
     }
 
+    fn throughput_bucket(&mut self, clock: &GlobalTime) -> &mut ThroughputBucket {
+        self.throughput
+            .entry(clock.0 / self.bucket_size.0)
+            .or_default()
+    }
+
+    pub fn update_committed_commands<State, Context, Notification, Request, Response>(
+        &mut self,
+        simulator: &Simulator<State, Context, Notification, Request, Response>,
+        clock: &GlobalTime,
+    ) where
+        Context: CommittedCommandCount,
+    {
+        let committed_commands = (0..self.nodes_len)
+            .map(|node_num| {
+                simulator
+                    .simulated_node(Author(node_num))
+                    .context()
+                    .committed_command_count()
+            })
+            .max()
+            .unwrap_or(0);
+        let bucket = self.throughput_bucket(clock);
+        bucket.committed_commands = std::cmp::max(bucket.committed_commands, committed_commands);
+    }
+
     pub fn add_message_counter<Notification, Request, Response>(
         &mut self,
         event: &Event<Notification, Request, Response>,
+        clock: &GlobalTime,
     ) {
+        let bucket = self.throughput_bucket(clock);
         match event {
-            Event::UpdateTimerEvent { author: _ } => {}
-            _ => self.message_counter += 1,
+            Event::UpdateTimerEvent { .. } | Event::RestartEvent { .. } => return,
+            Event::DataSyncNotifyEvent { .. } => bucket.notifications += 1,
+            Event::DataSyncRequestEvent { .. } => bucket.requests += 1,
+            Event::DataSyncResponseEvent { .. } => bucket.responses += 1,
         }
+        self.message_counter += 1;
     }
 
     pub fn write_to_file(&self) {
@@ -63,26 +239,113 @@ impl DataWriter {
             csv::Writer::from_path(format!("{}/{}", self.data_files_path, "round_switches.txt"))
                 .unwrap();
 
-        // CSV of the round switch
-        let headers: Vec<_> = (0..self.nodes_len).collect();
-        let headers: Vec<String> = headers
+        // CSV of the round switch, with one row per epoch and round
+        let headers: Vec<String> = ["epoch", "round"]
             .iter()
-            .map(|x| format!("node {}", x.to_string()))
+            .map(|x| x.to_string())
+            .chain((0..self.nodes_len).map(|x| format!("node {}", x)))
             .collect();
         wtr.serialize(&headers).expect("writing did not succeed");
 
-        let max_round = *self.max_round_per_node.iter().max().unwrap() as i32;
-        for round_num in 0..max_round {
-            let mut time_row: Vec<Option<i64>> = Vec::new();
-            for node_num in 0..self.nodes_len {
-                let time = self.nodes_round_switch[node_num]
-                    .iter()
-                    .find(|&x| x.0 == round_num as usize);
-                match time {
-                    Some(time) => time_row.push(Some((time.1).0)),
-                    None => time_row.push(None),
-                };
+        let mut round_switches = BTreeMap::new();
+        for (node_num, switches) in self.nodes_round_switch.iter().enumerate() {
+            for (epoch_id, round, time) in switches {
+                round_switches
+                    .entry((epoch_id.0, *round))
+                    .or_insert_with(|| vec![None; self.nodes_len])[node_num] = Some(time.0);
             }
+        }
+        for ((epoch_id, round), times) in round_switches {
+            let time_row: Vec<Option<i64>> = [Some(epoch_id as i64), Some(round as i64)]
+                .iter()
+                .copied()
+                .chain(times)
+                .collect();
+            wtr.serialize(time_row).expect("Writing did not succeed");
+        }
+
+        // CSV of the commit gap changes
+        let mut wtr =
+            csv::Writer::from_path(format!("{}/{}", self.data_files_path, "commit_gaps.txt"))
+                .unwrap();
+        wtr.serialize(("node", "time", "commit gap"))
+            .expect("Writing did not succeed");
+        for node_num in 0..self.nodes_len {
+            for (commit_gap, time) in &self.nodes_commit_gap[node_num] {
+                wtr.serialize((node_num, time.0, commit_gap))
+                    .expect("Writing did not succeed");
+            }
+        }
+
+        // CSV of the view change latencies
+        let mut wtr =
+            csv::Writer::from_path(format!("{}/{}", self.data_files_path, "view_changes.txt"))
+                .unwrap();
+        wtr.serialize(("node", "time", "round", "latency"))
+            .expect("Writing did not succeed");
+        for node_num in 0..self.nodes_len {
+            for (round, latency, time) in &self.nodes_view_changes[node_num] {
+                wtr.serialize((node_num, time.0, round.0, latency.0))
+                    .expect("Writing did not succeed");
+            }
+        }
+
+        // CSV of the fork rates
+        let mut wtr =
+            csv::Writer::from_path(format!("{}/{}", self.data_files_path, "fork_rates.txt"))
+                .unwrap();
+        wtr.serialize(("node", "time", "discarded states", "fork rate"))
+            .expect("Writing did not succeed");
+        for node_num in 0..self.nodes_len {
+            for (discarded, fork_rate, time) in &self.nodes_fork_rates[node_num] {
+                wtr.serialize((node_num, time.0, discarded, fork_rate))
+                    .expect("Writing did not succeed");
+            }
+        }
+
+        // CSV of the throughput, including the intervals without any event
+        let mut wtr =
+            csv::Writer::from_path(format!("{}/{}", self.data_files_path, "throughput.csv"))
+                .unwrap();
+        wtr.serialize((
+            "time",
+            "notifications",
+            "requests",
+            "responses",
+            "committed commands",
+        ))
+        .expect("Writing did not succeed");
+        let last_bucket = self.throughput.keys().next_back().copied().unwrap_or(-1);
+        let mut committed_commands = 0;
+        for index in 0..=last_bucket {
+            let bucket = self.throughput.get(&index).cloned().unwrap_or_default();
+            committed_commands = std::cmp::max(committed_commands, bucket.committed_commands);
+            wtr.serialize((
+                index * self.bucket_size.0,
+                bucket.notifications,
+                bucket.requests,
+                bucket.responses,
+                committed_commands,
+            ))
+            .expect("Writing did not succeed");
+        }
+
+        // CSV of the commit times, with one row per epoch and round
+        let mut wtr =
+            csv::Writer::from_path(format!("{}/{}", self.data_files_path, "commit_latency.csv"))
+                .unwrap();
+        let headers: Vec<String> = ["epoch", "round"]
+            .iter()
+            .map(|x| x.to_string())
+            .chain((0..self.nodes_len).map(|x| format!("node {}", x)))
+            .collect();
+        wtr.serialize(&headers).expect("Writing did not succeed");
+        for ((epoch_id, round), times) in &self.commit_times {
+            let time_row: Vec<Option<i64>> = [Some(epoch_id.0 as i64), Some(round.0 as i64)]
+                .iter()
+                .copied()
+                .chain(times.iter().map(|time| time.map(|time| time.0)))
+                .collect();
             wtr.serialize(time_row).expect("Writing did not succeed");
         }
 
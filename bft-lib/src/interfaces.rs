@@ -51,7 +51,7 @@ pub trait ConsensusNode<Context: SmrContext>: Sized {
 
 // -- BEGIN FILE data_sync_node --
 /// Network event handlers of a consensus node.
-pub trait DataSyncNode<Context> {
+pub trait DataSyncNode<Context: SmrContext> {
     type Notification;
     type Request;
     type Response;
@@ -69,11 +69,13 @@ pub trait DataSyncNode<Context> {
         request: Self::Request,
     ) -> Async<'a, Self::Response>;
 
-    /// Receiver role: accept or refuse a notification.
+    /// Receiver role: accept or refuse a notification from the given sender.
     fn handle_notification<'a>(
         &'a mut self,
         context: &'a mut Context,
+        sender: Context::Author,
         notification: Self::Notification,
+        clock: NodeTime,
     ) -> Async<'a, Option<Self::Request>>;
 
     /// Receiver role: receive data.
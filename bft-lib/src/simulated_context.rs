@@ -1,26 +1,39 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{base_types::*, configuration::EpochConfiguration, smr_context::*};
+use crate::{
+    base_types::*,
+    configuration::EpochConfiguration,
+    simulator::{
+        ApproximateMemory, CommittedCommandCount, DynamicCommittee, ForkRate, LocalClock,
+        SafetyMonitor, SafetyMonitoring, SignatureCount,
+    },
+    smr_context::*,
+};
 use anyhow::ensure;
 use futures::future;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use rand::SeedableRng;
+use rand_distr::{Distribution, Exp};
+use rand_xoshiro::Xoshiro256StarStar;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
     fmt::Debug,
     hash::{Hash, Hasher},
+    sync::Arc,
 };
 
 #[cfg(test)]
 #[path = "unit_tests/simulated_context_tests.rs"]
 mod simulated_context_tests;
 
-#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
 pub struct Author(pub usize);
 
 #[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Serialize, Deserialize, Default)]
-pub struct Signature(pub usize, pub u64);
+pub struct Signature(pub usize, pub SimulatedHashValue);
 
 #[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
 pub struct HashValue(pub u64);
@@ -32,24 +45,106 @@ pub struct State(pub u64);
 pub struct Command {
     pub proposer: Author,
     pub index: usize,
+    pub payload: Vec<u8>,
+}
+
+/// Produce the commands fetched by simulated nodes.
+pub trait CommandGenerator: Send + Sync {
+    /// Generate the command number `index` of the given author.
+    fn generate(&self, author: Author, index: usize) -> Command;
+}
+
+/// Default generator: commands carry no payload.
+#[derive(Clone, Debug, Default)]
+pub struct IndexedCommandGenerator;
+
+/// Generator of commands with pseudo-random payloads of size at most `max_payload_size`.
+#[derive(Clone, Debug)]
+pub struct VariableSizeCommandGenerator {
+    pub max_payload_size: usize,
+}
+
+/// How simulated nodes verify the signatures of the records they receive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignatureVerification {
+    /// Verify every signature as soon as a record is received.
+    Eager,
+    /// Accept all signatures without checking them. Only meant for simulations where all nodes
+    /// are honest.
+    Skip,
+}
+
+/// Pseudo-random arrival of transactions in the mempool of a node.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransactionArrival {
+    /// Average number of transactions arriving per unit of (local) time.
+    pub arrival_rate: f64,
+}
+
+/// Deterministic Poisson process deciding when transactions arrive in a mempool.
+#[derive(Clone, Debug, PartialEq)]
+struct ArrivalProcess {
+    arrival_rate: f64,
+    rng: Xoshiro256StarStar,
+    /// Time of the next arrival.
+    next_arrival_time: f64,
+    /// Number of transactions that arrived but were not fetched yet.
+    arrived_transactions: usize,
 }
 
+// The arrival rate is checked to be a positive number.
+impl Eq for ArrivalProcess {}
+
+impl ArrivalProcess {
+    fn new(arrival_rate: f64, seed: u64) -> Self {
+        let mut process = ArrivalProcess {
+            arrival_rate,
+            rng: Xoshiro256StarStar::seed_from_u64(seed),
+            next_arrival_time: 0.0,
+            arrived_transactions: 0,
+        };
+        process.next_arrival_time = process.sample_interval();
+        process
+    }
+
+    fn sample_interval(&mut self) -> f64 {
+        Exp::new(self.arrival_rate).unwrap().sample(&mut self.rng)
+    }
+
+    /// Count the transactions that arrived until the given time.
+    fn update(&mut self, clock: NodeTime) {
+        while self.next_arrival_time <= clock.0 as f64 {
+            self.arrived_transactions += 1;
+            self.next_arrival_time += self.sample_interval();
+        }
+    }
+}
+
+/// Reference to a command generator shared between contexts.
+#[derive(Clone)]
+struct SharedCommandGenerator(Arc<dyn CommandGenerator>);
+
 #[derive(Eq, PartialEq, Clone, Debug, Hash, Serialize, Deserialize)]
 pub struct SimulatedLedgerState {
+    /// Number of commands per epoch. This is part of the replicated state so that all nodes
+    /// agree on epoch boundaries.
+    max_command_per_epoch: usize,
     /// All the executed commands and theirs consensus times of execution.
     /// TODO: use linked lists with sharing
     execution_history: Vec<(Command, NodeTime)>,
 }
 
 impl SimulatedLedgerState {
-    fn new() -> SimulatedLedgerState {
+    fn new(max_command_per_epoch: usize) -> SimulatedLedgerState {
         SimulatedLedgerState {
+            max_command_per_epoch,
             execution_history: Vec::new(),
         }
     }
 
     fn key(&self) -> State {
         let mut hasher = DefaultHasher::new();
+        self.max_command_per_epoch.hash(&mut hasher);
         self.execution_history.hash(&mut hasher);
         State(hasher.finish())
     }
@@ -59,7 +154,9 @@ impl SimulatedLedgerState {
     }
 
     fn happened_just_before(&self, other: &SimulatedLedgerState) -> bool {
-        if self.execution_history.len() + 1 != other.execution_history.len() {
+        if self.max_command_per_epoch != other.max_command_per_epoch
+            || self.execution_history.len() + 1 != other.execution_history.len()
+        {
             return false;
         }
         for i in 0..self.execution_history.len() {
@@ -69,6 +166,64 @@ impl SimulatedLedgerState {
         }
         true
     }
+
+    fn approximate_memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.execution_history.len() * std::mem::size_of::<(Command, NodeTime)>()
+    }
+}
+
+impl CommandGenerator for IndexedCommandGenerator {
+    fn generate(&self, author: Author, index: usize) -> Command {
+        Command {
+            proposer: author,
+            index,
+            payload: Vec::new(),
+        }
+    }
+}
+
+impl CommandGenerator for VariableSizeCommandGenerator {
+    fn generate(&self, author: Author, index: usize) -> Command {
+        let mut hasher = DefaultHasher::new();
+        (author, index).hash(&mut hasher);
+        let seed = hasher.finish();
+        let size = (seed % (self.max_payload_size as u64 + 1)) as usize;
+        let payload = (0..size)
+            .map(|i| (seed.rotate_left(i as u32) & 0xff) as u8)
+            .collect();
+        Command {
+            proposer: author,
+            index,
+            payload,
+        }
+    }
+}
+
+impl Debug for SharedCommandGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SharedCommandGenerator")
+    }
+}
+
+impl PartialEq for SharedCommandGenerator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(
+            Arc::as_ptr(&self.0) as *const u8,
+            Arc::as_ptr(&other.0) as *const u8,
+        )
+    }
+}
+
+impl Eq for SharedCommandGenerator {}
+
+/// Digest of a value using FNV-1a over its BCS serialization. Unlike `DefaultHasher`, this is
+/// stable across platforms and compiler versions.
+pub(crate) fn stable_digest<T: Serialize + ?Sized>(value: &T) -> u64 {
+    let bytes = bcs::to_bytes(value).expect("serializing simulated values should not fail");
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |digest, byte| {
+        (digest ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -76,10 +231,37 @@ pub struct SimulatedContext {
     author: Author,
     database: HashMap<String, Vec<u8>>,
     num_nodes: usize,
-    max_command_per_epoch: usize,
     next_fetched_command_index: usize,
+    command_generator: SharedCommandGenerator,
+    /// Whether to reject commands that were not fetched by the author of the block.
+    check_command_proposer: bool,
+    /// Whether to report that no commands are pending.
+    empty_mempool: bool,
+    /// Whether to never produce any command.
+    frozen_commands: bool,
+    /// When set, commands are only produced after they arrived in the mempool.
+    transaction_arrival: Option<ArrivalProcess>,
+    signature_verification: SignatureVerification,
+    /// Whether to check that commit certificates are signed by a quorum.
+    verify_commit_certificate: bool,
+    /// Number of signatures verified so far.
+    verified_signature_count: Cell<usize>,
+    /// Number of signatures produced so far.
+    signature_count: usize,
+    /// Committed states delivered with a commit certificate, and the signers of the certificate.
+    commit_audit_log: Vec<(State, Vec<Author>)>,
+    /// Number of executed states discarded so far.
+    discarded_state_count: usize,
+    /// Number of states delivered by each call to `commit_batch`.
+    commit_batch_sizes: Vec<usize>,
+    /// Voting rights of the initial committee.
+    voting_rights: Vec<(Author, usize)>,
+    /// Voting rights of the committee from the given epochs on.
+    reconfigurations: BTreeMap<EpochId, Vec<(Author, usize)>>,
     last_committed_ledger_state: SimulatedLedgerState,
     pending_ledger_states: HashMap<State, SimulatedLedgerState>,
+    /// Cross-check of the commits of all nodes, if any.
+    safety_monitor: Option<SafetyMonitor>,
 }
 
 impl SimulatedContext {
@@ -88,17 +270,157 @@ impl SimulatedContext {
             author,
             database: HashMap::new(),
             num_nodes,
-            max_command_per_epoch,
             next_fetched_command_index: 0,
-            last_committed_ledger_state: SimulatedLedgerState::new(),
+            command_generator: SharedCommandGenerator(Arc::new(IndexedCommandGenerator)),
+            check_command_proposer: false,
+            empty_mempool: false,
+            frozen_commands: false,
+            transaction_arrival: None,
+            signature_verification: SignatureVerification::Eager,
+            verify_commit_certificate: false,
+            verified_signature_count: Cell::new(0),
+            signature_count: 0,
+            commit_audit_log: Vec::new(),
+            discarded_state_count: 0,
+            commit_batch_sizes: Vec::new(),
+            voting_rights: (0..num_nodes).map(|index| (Author(index), 1)).collect(),
+            reconfigurations: BTreeMap::new(),
+            last_committed_ledger_state: SimulatedLedgerState::new(max_command_per_epoch),
             pending_ledger_states: HashMap::new(),
+            safety_monitor: None,
+        }
+    }
+
+    /// Use the given voting rights for the initial committee instead of one vote per node. All
+    /// the contexts of a simulation must use the same weights.
+    pub fn with_weights(mut self, voting_rights: Vec<(Author, usize)>) -> Self {
+        assert!(
+            voting_rights.iter().any(|(_, weight)| *weight > 0),
+            "The committee must have voting rights"
+        );
+        self.voting_rights = voting_rights;
+        self
+    }
+
+    /// Use the given voting rights from `first_epoch` on, until the next reconfiguration, if
+    /// any. Authors missing from `voting_rights` are removed from the committee. All the
+    /// contexts of a simulation must use the same reconfigurations.
+    pub fn with_reconfiguration(
+        mut self,
+        first_epoch: EpochId,
+        voting_rights: Vec<(Author, usize)>,
+    ) -> Self {
+        self.reconfigure(first_epoch, voting_rights);
+        self
+    }
+
+    /// Reject the execution of commands whose proposer is not the author of the block. This
+    /// surfaces nodes proposing commands that they did not fetch themselves.
+    pub fn with_command_proposer_check(mut self, check_command_proposer: bool) -> Self {
+        self.check_command_proposer = check_command_proposer;
+        self
+    }
+
+    /// Use the given generator to produce the commands fetched by this node.
+    pub fn with_command_generator<G: CommandGenerator + 'static>(mut self, generator: G) -> Self {
+        self.command_generator = SharedCommandGenerator(Arc::new(generator));
+        self
+    }
+
+    /// Report that no commands are pending, as if the mempool was empty. Fetched commands then
+    /// play the role of empty payloads.
+    pub fn with_empty_mempool(mut self, empty_mempool: bool) -> Self {
+        self.empty_mempool = empty_mempool;
+        self
+    }
+
+    /// Stop producing commands altogether, as if the mempool was empty forever. Nodes that are
+    /// configured to never propose empty blocks then only advance rounds by timeouts.
+    pub fn with_frozen_commands(mut self, frozen_commands: bool) -> Self {
+        self.frozen_commands = frozen_commands;
+        self
+    }
+
+    /// Only produce commands for the transactions that arrived so far, following a Poisson
+    /// process seeded by the author of this node. Proposals then fail when no transaction is
+    /// available.
+    pub fn with_transaction_arrival(mut self, arrival: TransactionArrival) -> Self {
+        assert!(
+            arrival.arrival_rate > 0.0,
+            "The arrival rate of transactions must be positive"
+        );
+        self.transaction_arrival = Some(ArrivalProcess::new(
+            arrival.arrival_rate,
+            self.author.0 as u64,
+        ));
+        self
+    }
+
+    /// Choose how to verify signatures. Skipping verifications saves work in large honest-only
+    /// simulations but lets forged records through.
+    pub fn with_signature_verification(
+        mut self,
+        signature_verification: SignatureVerification,
+    ) -> Self {
+        if signature_verification == SignatureVerification::Skip {
+            warn!(
+                "{:?} Signature verification is DISABLED: forged records will be accepted",
+                self.author
+            );
         }
+        self.signature_verification = signature_verification;
+        self
+    }
+
+    /// Check that the certificates passed to `commit` are signed by a quorum of the epoch of
+    /// the committed state, instead of trusting the record store. Violations panic.
+    pub fn with_commit_certificate_verification(mut self, verify_commit_certificate: bool) -> Self {
+        self.verify_commit_certificate = verify_commit_certificate;
+        self
+    }
+
+    /// Number of signatures verified so far by this context.
+    pub fn verified_signature_count(&self) -> usize {
+        self.verified_signature_count.get()
+    }
+
+    /// Whether the given state is committed or pending execution.
+    pub fn knows_state(&self, state: &State) -> bool {
+        self.get_ledger_state(state).is_some()
     }
 
     pub fn committed_history(&self) -> &Vec<(Command, NodeTime)> {
         &self.last_committed_ledger_state.execution_history
     }
 
+    /// Commands committed so far, in order.
+    pub fn committed_commands(&self) -> impl Iterator<Item = &Command> {
+        self.committed_history().iter().map(|(command, _)| command)
+    }
+
+    /// Execution time of the committed command at the given position, if any.
+    pub fn commit_time(&self, index: usize) -> Option<NodeTime> {
+        self.committed_history().get(index).map(|(_, time)| *time)
+    }
+
+    /// Digest of the committed history, i.e. all the committed commands and their times.
+    /// Unlike the `DefaultHasher` behind `State` values, this digest is stable across
+    /// platforms and compiler versions.
+    pub fn history_digest(&self) -> u64 {
+        stable_digest(self.committed_history())
+    }
+
+    /// Committed states that came with a commit certificate, together with the signers of the
+    /// certificate.
+    pub fn commit_audit_log(&self) -> &[(State, Vec<Author>)] {
+        &self.commit_audit_log
+    }
+
+    /// Number of states delivered by each batch of commits so far.
+    pub fn commit_batch_sizes(&self) -> &[usize] {
+        &self.commit_batch_sizes
+    }
+
     fn get_ledger_state(&self, state: &State) -> Option<&SimulatedLedgerState> {
         if state == &self.last_committed_ledger_state.key() {
             Some(&self.last_committed_ledger_state)
@@ -115,13 +437,41 @@ impl SmrTypes for SimulatedContext {
 
 impl CommandFetcher<Command> for SimulatedContext {
     fn fetch(&mut self) -> Option<Command> {
-        let command = Command {
-            proposer: self.author,
-            index: self.next_fetched_command_index,
-        };
+        if self.frozen_commands {
+            return None;
+        }
+        if let Some(arrival) = &mut self.transaction_arrival {
+            if arrival.arrived_transactions == 0 {
+                return None;
+            }
+            arrival.arrived_transactions -= 1;
+        }
+        let command = self
+            .command_generator
+            .0
+            .generate(self.author, self.next_fetched_command_index);
         self.next_fetched_command_index += 1;
         Some(command)
     }
+
+    fn peek(&self) -> Option<Command> {
+        if self.frozen_commands
+            || matches!(&self.transaction_arrival, Some(arrival) if arrival.arrived_transactions == 0)
+        {
+            return None;
+        }
+        Some(
+            self.command_generator
+                .0
+                .generate(self.author, self.next_fetched_command_index),
+        )
+    }
+
+    fn has_pending_commands(&self) -> bool {
+        !self.empty_mempool
+            && !self.frozen_commands
+            && !matches!(&self.transaction_arrival, Some(arrival) if arrival.arrived_transactions == 0)
+    }
 }
 
 impl CommandExecutor<Author, State, Command> for SimulatedContext {
@@ -130,9 +480,17 @@ impl CommandExecutor<Author, State, Command> for SimulatedContext {
         base_state: &State,
         command: Command,
         time: NodeTime,
+        author: Author,
         _previous_author: Option<Author>,
         _previous_voters: Vec<Author>,
     ) -> Option<State> {
+        if self.check_command_proposer && command.proposer != author {
+            error!(
+                "{:?}{:?} Refusing to execute {:?} proposed by {:?}",
+                self.author, time, command, author
+            );
+            return None;
+        }
         match self.get_ledger_state(base_state) {
             Some(ledger_state) => {
                 let mut new_ledger_state = ledger_state.clone();
@@ -157,8 +515,12 @@ impl CommandExecutor<Author, State, Command> for SimulatedContext {
     }
 }
 
-impl StateFinalizer<State> for SimulatedContext {
-    fn commit(&mut self, state: &State, certificate: Option<&dyn CommitCertificate<State>>) {
+impl StateFinalizer<Author, State> for SimulatedContext {
+    fn commit(
+        &mut self,
+        state: &State,
+        certificate: Option<&dyn CommitCertificate<Author, State>>,
+    ) {
         info!("{:?} Delivering commit for state: {:?}", self.author, state);
         let ledger_state = self
             .pending_ledger_states
@@ -173,52 +535,206 @@ impl StateFinalizer<State> for SimulatedContext {
             .last_committed_ledger_state
             .happened_just_before(&ledger_state));
         if let Some(qc) = certificate {
+            if self.verify_commit_certificate {
+                // The committed state was executed during the epoch of the previous state.
+                let configuration = self.configuration(&self.last_committed_state());
+                let signers: BTreeSet<_> = qc.signers().into_iter().collect();
+                assert!(
+                    configuration.is_quorum(configuration.count_votes(&signers)),
+                    "Commit certificate for state {:?} is not signed by a quorum: {:?}",
+                    state,
+                    signers
+                );
+            }
             if let Some(state2) = qc.committed_state() {
                 assert_eq!(state, state2);
                 info!(
                     "{:?} Received commit certificate for state: {:?}",
                     self.author, state
                 );
+                self.commit_audit_log.push((state.clone(), qc.signers()));
+            }
+        }
+        if let Some(monitor) = &self.safety_monitor {
+            let height = ledger_state.execution_history.len();
+            if let Err(violation) = monitor.record_commit(self.author, height, state.clone()) {
+                panic!("{}", violation);
             }
         }
         self.last_committed_ledger_state = ledger_state
     }
 
+    fn commit_batch(
+        &mut self,
+        states: &[(Round, State)],
+        commit_certificate: Option<&dyn CommitCertificate<Author, State>>,
+    ) {
+        self.commit_batch_sizes.push(states.len());
+        if let Some(((_, last_state), states)) = states.split_last() {
+            for (_, state) in states {
+                self.commit(state, None);
+            }
+            self.commit(last_state, commit_certificate);
+        }
+    }
+
     fn discard(&mut self, state: &State) {
         debug!("{:?} Discarding state: {:?}", self.author, state);
         self.pending_ledger_states
             .remove(state)
             .expect("Discarded states should be known");
+        self.discarded_state_count += 1;
     }
 
     fn last_committed_state(&self) -> State {
         self.last_committed_ledger_state.key()
     }
+
+    fn export_checkpoint(&self) -> Vec<u8> {
+        bcs::to_bytes(&self.last_committed_ledger_state)
+            .expect("Serialization should not fail for ledger states")
+    }
+
+    fn import_checkpoint(&mut self, bytes: &[u8]) -> Result<()> {
+        self.last_committed_ledger_state = bcs::from_bytes(bytes)?;
+        // Pending states were computed on top of the previous ledger.
+        self.pending_ledger_states.clear();
+        Ok(())
+    }
+}
+
+impl LocalClock for SimulatedContext {
+    fn update_local_clock(&mut self, clock: NodeTime) {
+        if let Some(arrival) = &mut self.transaction_arrival {
+            arrival.update(clock);
+        }
+    }
+}
+
+impl ForkRate for SimulatedContext {
+    fn discarded_state_count(&self) -> usize {
+        self.discarded_state_count
+    }
+
+    fn fork_rate(&self) -> f64 {
+        let commit_count = self.committed_history().len();
+        if commit_count == 0 {
+            return 0.0;
+        }
+        self.discarded_state_count as f64 / commit_count as f64
+    }
+}
+
+impl CommittedCommandCount for SimulatedContext {
+    fn committed_command_count(&self) -> usize {
+        self.committed_history().len()
+    }
+}
+
+impl SignatureCount for SimulatedContext {
+    fn signature_count(&self) -> usize {
+        self.signature_count
+    }
+}
+
+impl SafetyMonitoring for SimulatedContext {
+    fn set_safety_monitor(&mut self, monitor: SafetyMonitor) {
+        self.safety_monitor = Some(monitor);
+    }
+}
+
+impl DynamicCommittee for SimulatedContext {
+    fn next_epoch_id(&self) -> EpochId {
+        let ledger_state = &self.last_committed_ledger_state;
+        EpochId(ledger_state.execution_history.len() / ledger_state.max_command_per_epoch + 1)
+    }
+
+    fn committee_at(&self, epoch_id: EpochId) -> Vec<(Author, usize)> {
+        self.reconfigurations
+            .range(..=epoch_id)
+            .next_back()
+            .map(|(_, voting_rights)| voting_rights.clone())
+            .unwrap_or_else(|| self.voting_rights.clone())
+    }
+
+    fn reconfigure(&mut self, first_epoch: EpochId, voting_rights: Vec<(Author, usize)>) {
+        assert!(
+            voting_rights.iter().any(|(_, weight)| *weight > 0),
+            "The committee must have voting rights"
+        );
+        self.reconfigurations.insert(first_epoch, voting_rights);
+    }
+}
+
+impl ApproximateMemory for SimulatedContext {
+    fn approximate_memory(&self) -> usize {
+        self.pending_ledger_states
+            .values()
+            .map(|ledger_state| std::mem::size_of::<State>() + ledger_state.approximate_memory())
+            .sum::<usize>()
+            + self.last_committed_ledger_state.approximate_memory()
+    }
 }
 
 impl EpochReader<Author, State> for SimulatedContext {
     fn read_epoch_id(&self, state: &State) -> EpochId {
-        let num_commands = self
+        let ledger_state = self
             .get_ledger_state(state)
-            .expect("Read states should be known")
-            .execution_history
-            .len();
-        EpochId(num_commands / self.max_command_per_epoch)
+            .expect("Read states should be known");
+        EpochId(ledger_state.execution_history.len() / ledger_state.max_command_per_epoch)
     }
 
-    fn configuration(&self, _state: &State) -> EpochConfiguration<Author> {
-        // We do not simulate changes in the voting rights yet.
-        let mut voting_rights = Vec::new();
-        for index in 0..self.num_nodes {
-            voting_rights.push((Author(index), 1));
-        }
+    fn configuration(&self, state: &State) -> EpochConfiguration<Author> {
+        // Voting rights only change with reconfigurations.
+        let voting_rights = match self.get_ledger_state(state) {
+            Some(ledger_state) => self.committee_at(EpochId(
+                ledger_state.execution_history.len() / ledger_state.max_command_per_epoch,
+            )),
+            None => self.voting_rights.clone(),
+        };
         EpochConfiguration::new(voting_rights)
     }
+
+    fn epoch_boundary_state(&self, epoch_id: EpochId) -> Option<State> {
+        let max_command_per_epoch = self.last_committed_ledger_state.max_command_per_epoch;
+        let num_commands = epoch_id.0 * max_command_per_epoch;
+        let history = &self.last_committed_ledger_state.execution_history;
+        if num_commands > history.len() {
+            return None;
+        }
+        let ledger_state = SimulatedLedgerState {
+            max_command_per_epoch,
+            execution_history: history[..num_commands].to_vec(),
+        };
+        Some(ledger_state.key())
+    }
 }
 
+/// Hash values of the simulated context. The feature `wide-hash` makes them 128-bit wide so that
+/// collisions stay negligible during long simulations.
+#[cfg(not(feature = "wide-hash"))]
+pub type SimulatedHashValue = u64;
+#[cfg(feature = "wide-hash")]
+pub type SimulatedHashValue = u128;
+
 #[derive(Default)]
 pub struct SimulatedHasher(std::collections::hash_map::DefaultHasher);
 
+impl SimulatedHasher {
+    #[cfg(not(feature = "wide-hash"))]
+    fn finish(&self) -> SimulatedHashValue {
+        self.0.finish()
+    }
+
+    #[cfg(feature = "wide-hash")]
+    fn finish(&self) -> SimulatedHashValue {
+        // Derive the upper bits from an extended copy of the hasher.
+        let mut hasher = self.0.clone();
+        hasher.write_u8(0xff);
+        (u128::from(hasher.finish()) << 64) | u128::from(self.0.finish())
+    }
+}
+
 impl std::io::Write for SimulatedHasher {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.0.write(buf);
@@ -233,12 +749,12 @@ impl CryptographicModule for SimulatedContext {
     type Hasher = SimulatedHasher;
     type Author = Author;
     type Signature = Signature;
-    type HashValue = u64;
+    type HashValue = SimulatedHashValue;
 
     fn hash(&self, message: &dyn Signable<Self::Hasher>) -> Self::HashValue {
         let mut hasher = SimulatedHasher::default();
         message.write(&mut hasher);
-        hasher.0.finish()
+        hasher.finish()
     }
 
     fn verify(
@@ -247,6 +763,11 @@ impl CryptographicModule for SimulatedContext {
         hash: Self::HashValue,
         signature: Self::Signature,
     ) -> Result<()> {
+        if self.signature_verification == SignatureVerification::Skip {
+            return Ok(());
+        }
+        self.verified_signature_count
+            .set(self.verified_signature_count.get() + 1);
         ensure!(author.0 == signature.0, "Unexpected signer in signature");
         ensure!(hash == signature.1, "Unexpected hash in signature");
         Ok(())
@@ -256,8 +777,9 @@ impl CryptographicModule for SimulatedContext {
         self.author
     }
 
-    fn sign(&mut self, hash: Self::HashValue) -> Self::Signature {
-        Signature(self.author.0, hash)
+    fn sign(&mut self, hash: Self::HashValue) -> AsyncResult<'_, Self::Signature> {
+        self.signature_count += 1;
+        Box::pin(future::ready(Ok(Signature(self.author.0, hash))))
     }
 }
 
@@ -2,18 +2,29 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    base_types::{Duration, NodeTime, Round},
+    base_types::{Duration, EpochId, NodeTime, Round},
     data_writer::DataWriter,
     interfaces::{ConsensusNode, DataSyncNode, NodeUpdateActions},
-    simulated_context::Author,
+    simulated_context::{stable_digest, Author, State},
     smr_context::SmrContext,
 };
 use futures::executor::block_on;
 use log::{debug, trace};
-use rand::{prelude::SliceRandom, SeedableRng};
+use rand::{prelude::SliceRandom, Rng, SeedableRng};
 use rand_distr::{Distribution, LogNormal};
 use rand_xoshiro::Xoshiro256StarStar;
-use std::{collections::BinaryHeap, fmt::Debug};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    fmt::{self, Debug},
+    fs,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+pub use crate::data_writer::RunMetadata;
 
 #[cfg(test)]
 #[path = "unit_tests/simulator_tests.rs"]
@@ -26,40 +37,200 @@ mod simulator_tests;
 pub struct Simulator<Node, Context, Notification, Request, Response> {
     clock: GlobalTime,
     network_delay: RandomDelay,
+    /// Delay distributions of the network messages, by link.
+    link_delays: LinkDelayMatrix,
+    /// Smallest and largest delays of the messages sent so far over each edge.
+    link_delay_ranges: BTreeMap<(Author, Author), (Duration, Duration)>,
+    /// Global stabilization time (GST) and bound on the network delays after it, if any.
+    synchrony: Option<(GlobalTime, Duration)>,
+    /// Largest delay of the network messages sent after GST.
+    max_network_delay_after_gst: Option<Duration>,
     pending_events: BinaryHeap<ScheduledEvent<Event<Notification, Request, Response>>>,
     nodes: Vec<SimulatedNode<Node, Context>>,
     event_count: usize,
     rng: Xoshiro256StarStar,
+    proposal_withholders: HashSet<Author>,
+    /// File where to write the trace of the events processed so far, if recording.
+    trace_recording: Option<(PathBuf, Vec<TraceEntry>)>,
+    /// Recorded events to replay, by creation stamp.
+    replayed_trace: BTreeMap<usize, TraceEntry>,
+    /// First processed event that differs from the replayed trace, with the recorded one.
+    trace_divergence: Option<(TraceEntry, TraceEntry)>,
+    /// Connectivity of the network over time.
+    partition: Partition,
+    /// Intervals during which nodes are crashed.
+    crash_schedule: CrashSchedule,
+    /// Number of nodes restarted from their storage so far.
+    restart_count: usize,
+    /// Misbehaviors injected on behalf of some nodes.
+    byzantine_behaviors: HashMap<Author, ByzantineBehavior>,
+    /// Number of notifications sent with a conflicting proposal so far.
+    equivocation_count: usize,
+    /// Extra delay of the notifications carrying a new commit certificate.
+    commit_certificate_delay: Duration,
+    /// Epoch and round of the last commit certificate sent over each edge
+    /// `(origin, destination)`.
+    sent_commit_certificates: BTreeMap<(Author, Author), (EpochId, Round)>,
+    /// Time needed by a node to produce one signature.
+    signing_delay: Duration,
+    /// Nodes marked as faulty by the fault-injection methods.
+    faulty_authors: BTreeSet<Author>,
+    message_schedule: MessageSchedule,
+    edge_message_counts: BTreeMap<(Author, Author), usize>,
+    /// Whether to serialize network messages to measure their sizes.
+    message_size_accounting: bool,
+    /// Number of bytes sent so far over each edge, if sizes are measured.
+    bytes_sent: BTreeMap<(Author, Author), usize>,
+    follower_to_follower_message_count: usize,
+    /// Number of network messages lost so far.
+    lost_message_count: usize,
+    /// Parameters of the run, written next to the data files.
+    run_metadata: RunMetadata,
+    /// Length of the time intervals used to aggregate throughput data.
+    throughput_bucket_size: GlobalTime,
 }
 
 /// Simulated global clock
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct GlobalTime(pub i64);
 
+/// Compare the faults injected in a run with the faults tolerated by the protocol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FaultReport {
+    /// Maximal weight of faulty nodes tolerated by the committee.
+    pub tolerated_f: usize,
+    /// Total weight of the nodes marked as faulty.
+    pub actual_faulty_weight: usize,
+    /// Whether the run stayed within the fault model.
+    pub safe: bool,
+}
+
+/// Summarize whether a run made progress, so that liveness can be asserted in tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LivenessReport {
+    /// Whether at least one node committed a command.
+    pub committed_any: bool,
+    /// Highest active round among all nodes.
+    pub final_round: Round,
+    /// Nodes that did not commit any command.
+    pub stalled_nodes: Vec<Author>,
+}
+
 /// A distribution that produces random delays.
 #[derive(Copy, Clone, Debug)]
 pub struct RandomDelay {
     distribution: LogNormal<f64>,
+    mean: f64,
+    variance: f64,
+    /// Probability that a message is lost instead of delayed.
+    loss_probability: f64,
+}
+
+/// Delay distributions of the network links `(origin, destination)`, so that some links can
+/// be made persistently slower or faster than others.
+#[derive(Clone, Debug)]
+pub struct LinkDelayMatrix {
+    default_delay: RandomDelay,
+    links: BTreeMap<(Author, Author), RandomDelay>,
+}
+
+/// How the simulator schedules the startup of nodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StartupMode {
+    /// Each node starts after an independent random delay.
+    Randomized,
+    /// All nodes start at the same time.
+    Simultaneous,
+    /// Nodes start one after another, separated by the given spacing.
+    Sequential { spacing: Duration },
+}
+
+/// Misbehaviors that the simulator can inject on behalf of a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByzantineBehavior {
+    /// Send a conflicting proposal for the same round to half of the receivers of each
+    /// notification carrying a proposal of the node.
+    Equivocate,
+    /// Never deliver the proposals of the node (see `Simulator::withhold_proposals`).
+    SilentLeader,
+    /// Remove the votes of the node from its notifications.
+    VoteWithholding,
+}
+
+/// Intervals of time during which nodes are down, i.e. crashed. Each interval `(start, end)`
+/// includes `start` but not `end`, when the node restarts from its storage.
+#[derive(Clone, Debug, Default)]
+pub struct CrashSchedule {
+    down_intervals: BTreeMap<Author, Vec<(GlobalTime, GlobalTime)>>,
+}
+
+/// Time-varying connectivity of the network. Each split disconnects a group of nodes from the
+/// other nodes during an interval of time `(start, end)` that includes `start` but not `end`.
+#[derive(Clone, Debug, Default)]
+pub struct Partition {
+    splits: Vec<(BTreeSet<Author>, GlobalTime, GlobalTime)>,
+}
+
+/// An event processed by the simulator, as recorded in a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub scheduled_time: GlobalTime,
+    pub kind: usize,
+    /// Sender of the network message, if any.
+    pub sender: Option<Author>,
+    /// Node that processes the event.
+    pub receiver: Author,
+    pub creation_stamp: usize,
+}
+
+/// How the simulator addresses the messages of nodes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageSchedule {
+    /// Nodes send messages as requested by their update actions and data-sync handlers.
+    Default,
+    /// Nodes that are not the leader of their active round (aka followers) only send messages
+    /// to this leader, while leaders only broadcast. This models protocols with a linear
+    /// communication complexity.
+    TwoPhase,
+    /// Broadcasts only reach the given number of other nodes, chosen at random for each
+    /// broadcast, in addition to the nodes explicitly requested. This models sparse gossip.
+    PartialBroadcast { fanout: usize },
 }
 
 /// An event inserted in the binary heap.
-/// Every event must have a unique `creation_stamp`.
+/// Every event must have a unique `creation_stamp`. Unlike the `creation_stamp`, the
+/// content stamp only depends on the scheduled time and the content of the event, hence it
+/// identifies the event across runs regardless of the order of insertion. It is computed
+/// lazily, when needed to break a tie.
 struct ScheduledEvent<Event> {
     scheduled_time: GlobalTime,
     creation_stamp: usize,
+    content_stamp: Cell<Option<u64>>,
     event: Event,
 }
 
+/// A deterministic noise on the clock read by a node, uniformly distributed in
+/// `[-max_jitter, max_jitter]`.
+#[derive(Clone, Debug)]
+struct ClockJitter {
+    max_jitter: Duration,
+    rng: Xoshiro256StarStar,
+}
+
 #[derive(Debug)]
 pub struct SimulatedNode<Node, Context> {
     startup_time: GlobalTime,
     ignore_scheduled_updates_until: GlobalTime,
+    disconnected_until: GlobalTime,
+    clock_jitter: Option<ClockJitter>,
+    /// Number of signatures of the node already accounted for by the signing delay.
+    signature_count: usize,
     node: Node,
     context: Context,
 }
 
 /// An event to be scheduled and processed by the simulator.
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
 pub enum Event<Notification, Request, Response> {
     DataSyncNotifyEvent {
         receiver: Author,
@@ -79,12 +250,238 @@ pub enum Event<Notification, Request, Response> {
     UpdateTimerEvent {
         author: Author,
     },
+    /// Reload the node from its storage at the end of a crash.
+    RestartEvent {
+        author: Author,
+    },
 }
 
 // TODO: the notion of round is specific to some BFT protocols => rename and/or generalize?
 /// Trait to help visualizing rounds in a simulator.
 pub trait ActiveRound {
     fn active_round(&self) -> Round;
+
+    /// Round of the highest commit delivered so far.
+    fn highest_committed_round(&self) -> Round;
+}
+
+/// Trait to help visualizing epochs in a simulator.
+pub trait ActiveEpoch {
+    fn active_epoch(&self) -> EpochId;
+}
+
+/// Trait to help monitoring the progress of commits in a simulator.
+pub trait CommitGap {
+    /// Number of rounds between the highest certified round and the highest committed round.
+    fn commit_gap(&self) -> usize;
+}
+
+/// Trait to help shaping the communication between nodes in a simulator.
+pub trait ActiveLeader {
+    type Author;
+
+    /// Leader of the active round, if any.
+    fn active_leader(&self) -> Option<Self::Author>;
+}
+
+/// Trait to help monitoring the cost of view changes in a simulator.
+pub trait ViewChangeLatencies {
+    /// For each round of the current epoch abandoned after a timeout certificate, the time
+    /// between entering the round and forming the certificate.
+    fn view_change_latencies(&self) -> Vec<(Round, Duration)>;
+}
+
+/// Trait to let the context of a node follow the local clock in a simulator.
+pub trait LocalClock {
+    /// Called with the local clock of the node before each update.
+    fn update_local_clock(&mut self, clock: NodeTime);
+}
+
+/// Trait to help measuring how often speculative branches are abandoned in a simulator.
+pub trait ForkRate {
+    /// Number of executed states discarded so far.
+    fn discarded_state_count(&self) -> usize;
+
+    /// Number of discarded states per committed state.
+    fn fork_rate(&self) -> f64;
+}
+
+/// Trait to help checking that a run made progress in a simulator.
+pub trait CommittedCommandCount {
+    /// Number of commands committed so far.
+    fn committed_command_count(&self) -> usize;
+}
+
+/// Trait to let a simulator extend the committee of future epochs.
+pub trait DynamicCommittee {
+    /// Epoch following the one of the latest committed state.
+    fn next_epoch_id(&self) -> EpochId;
+
+    /// Voting rights of the committee of the given epoch.
+    fn committee_at(&self, epoch_id: EpochId) -> Vec<(Author, usize)>;
+
+    /// Use the given voting rights from `first_epoch` on, until the next reconfiguration, if
+    /// any.
+    fn reconfigure(&mut self, first_epoch: EpochId, voting_rights: Vec<(Author, usize)>);
+}
+
+/// Trait to help modeling the cost of signing (e.g. with an HSM) in a simulator.
+pub trait SignatureCount {
+    /// Number of signatures produced so far.
+    fn signature_count(&self) -> usize;
+}
+
+/// Trait to let a simulator cross-check the commits of all nodes.
+pub trait SafetyMonitoring {
+    /// Report the commits of `self` to the given monitor, which panics on disagreements.
+    fn set_safety_monitor(&mut self, monitor: SafetyMonitor);
+}
+
+/// Commits of all the nodes of a simulation, shared by their contexts to check that nodes agree
+/// on the committed ledger. Since every commit executes exactly one command, commits are
+/// compared by height in the ledger, which is consistent across rounds and epochs.
+#[derive(Clone, Default)]
+pub struct SafetyMonitor(Arc<Mutex<SafetyMonitorState>>);
+
+#[derive(Default)]
+struct SafetyMonitorState {
+    /// First state committed at each height (starting from 1), with its author.
+    commits: Vec<(State, Author)>,
+    /// Number of commits checked so far.
+    commit_count: usize,
+}
+
+/// Two nodes committed different states at the same height of the ledger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SafetyViolation {
+    pub height: usize,
+    pub first_commit: (Author, State),
+    pub second_commit: (Author, State),
+}
+
+impl SafetyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `author` committed `state` at the given height of the ledger and check that
+    /// no other node committed a different state at the same height.
+    pub fn record_commit(
+        &self,
+        author: Author,
+        height: usize,
+        state: State,
+    ) -> std::result::Result<(), SafetyViolation> {
+        assert!(height > 0, "The initial state is not committed");
+        let mut monitor = self.0.lock().unwrap();
+        monitor.commit_count += 1;
+        match monitor.commits.get(height - 1) {
+            Some((first_state, first_author)) => {
+                if *first_state != state {
+                    return Err(SafetyViolation {
+                        height,
+                        first_commit: (*first_author, first_state.clone()),
+                        second_commit: (author, state),
+                    });
+                }
+            }
+            None => {
+                // Nodes commit heights in increasing order, hence the first node to commit a
+                // height has committed all the previous ones.
+                assert_eq!(
+                    monitor.commits.len() + 1,
+                    height,
+                    "Commits must be recorded in order"
+                );
+                monitor.commits.push((state, author));
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest height of the ledger committed by any node.
+    pub fn highest_height(&self) -> usize {
+        self.0.lock().unwrap().commits.len()
+    }
+
+    /// Number of commits checked so far.
+    pub fn commit_count(&self) -> usize {
+        self.0.lock().unwrap().commit_count
+    }
+}
+
+impl Debug for SafetyMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SafetyMonitor")
+    }
+}
+
+impl PartialEq for SafetyMonitor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SafetyMonitor {}
+
+impl fmt::Display for SafetyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Safety violation at height {}: {:?} committed {:?} but {:?} committed {:?}",
+            self.height,
+            self.first_commit.0,
+            self.first_commit.1,
+            self.second_commit.0,
+            self.second_commit.1
+        )
+    }
+}
+
+/// Trait to help monitoring the memory used by nodes in a simulator.
+pub trait ApproximateMemory {
+    /// Approximate number of bytes held by the dynamic data structures of `self`.
+    fn approximate_memory(&self) -> usize;
+}
+
+/// Trait to help injecting faults on the proposals carried by notifications in a simulator.
+pub trait ProposalAuthor {
+    type Author;
+
+    /// Author of the proposed block carried by `self`, if any.
+    fn proposal_author(&self) -> Option<Self::Author>;
+}
+
+/// Trait to help analyzing the fairness of leader election in a simulator.
+pub trait CommittedLeaders {
+    type Author;
+
+    /// Epoch, round, and author of the blocks committed so far.
+    fn committed_leaders(&self) -> Vec<(EpochId, Round, Self::Author)>;
+}
+
+/// Trait to help delaying the messages that are critical to commits in a simulator.
+pub trait CommitCertificateCarrier {
+    /// Epoch and round of the commit certificate carried by `self`, if any.
+    fn commit_certificate(&self) -> Option<(EpochId, Round)>;
+}
+
+/// Trait to help injecting Byzantine behaviors on notifications in a simulator.
+pub trait ByzantineNotification<Context>: Sized {
+    /// Copy of `self` where the proposed block, if any, is replaced by a different block for
+    /// the same round, signed with `context`.
+    fn equivocate(&self, context: &mut Context) -> Option<Self>;
+
+    /// Copy of `self` without the vote of its sender.
+    fn without_vote(&self) -> Self;
+}
+
+/// Trait to help measuring the effectiveness of data synchronization in a simulator.
+pub trait KnownRecords {
+    type RecordHash: Eq + Hash;
+
+    /// Hashes of the records (e.g. blocks and QCs) currently known by `self`.
+    fn known_records(&self) -> HashSet<Self::RecordHash>;
 }
 
 impl std::ops::Add<Duration> for GlobalTime {
@@ -102,8 +499,109 @@ impl RandomDelay {
         let sigma = f64::sqrt(f64::ln(1.0 + variance / (mean * mean)));
         RandomDelay {
             distribution: LogNormal::new(mu, sigma).unwrap(),
+            mean,
+            variance,
+            loss_probability: 0.0,
         }
     }
+
+    /// Same as `new` but messages are also lost with the given probability.
+    pub fn with_loss(mean: f64, variance: f64, loss_probability: f64) -> RandomDelay {
+        assert!(
+            (0.0..=1.0).contains(&loss_probability),
+            "Loss probability must be between 0 and 1"
+        );
+        RandomDelay {
+            loss_probability,
+            ..RandomDelay::new(mean, variance)
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    pub fn loss_probability(&self) -> f64 {
+        self.loss_probability
+    }
+}
+
+impl CrashSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Crash `author` at time `start` and restart it at time `end`.
+    pub fn with_crash(mut self, author: Author, start: GlobalTime, end: GlobalTime) -> Self {
+        assert!(start < end, "Crashes must end after they start");
+        self.down_intervals
+            .entry(author)
+            .or_default()
+            .push((start, end));
+        self
+    }
+
+    /// Whether `author` is down at time `clock`.
+    pub fn is_down(&self, author: Author, clock: GlobalTime) -> bool {
+        self.down_intervals
+            .get(&author)
+            .into_iter()
+            .flatten()
+            .any(|(start, end)| *start <= clock && clock < *end)
+    }
+}
+
+impl Partition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disconnect the nodes of `group` from the other nodes from `start` until `end`.
+    pub fn with_split(
+        mut self,
+        group: BTreeSet<Author>,
+        start: GlobalTime,
+        end: GlobalTime,
+    ) -> Self {
+        assert!(start < end, "Splits must end after they start");
+        self.splits.push((group, start, end));
+        self
+    }
+
+    /// Whether the link from `sender` to `receiver` is up at time `clock`.
+    pub fn is_link_up(&self, sender: Author, receiver: Author, clock: GlobalTime) -> bool {
+        self.splits.iter().all(|(group, start, end)| {
+            clock < *start || clock >= *end || group.contains(&sender) == group.contains(&receiver)
+        })
+    }
+}
+
+impl LinkDelayMatrix {
+    /// Use the same distribution for all links.
+    pub fn uniform(delay: RandomDelay) -> Self {
+        LinkDelayMatrix {
+            default_delay: delay,
+            links: BTreeMap::new(),
+        }
+    }
+
+    /// Use a specific distribution for the link from `origin` to `destination`.
+    pub fn with_link(mut self, origin: Author, destination: Author, delay: RandomDelay) -> Self {
+        self.links.insert((origin, destination), delay);
+        self
+    }
+
+    /// Distribution of the delays from `origin` to `destination`.
+    pub fn delay(&self, origin: Author, destination: Author) -> RandomDelay {
+        self.links
+            .get(&(origin, destination))
+            .copied()
+            .unwrap_or(self.default_delay)
+    }
 }
 
 impl GlobalTime {
@@ -127,6 +625,16 @@ impl GlobalTime {
 }
 
 impl<Notification, Request, Response> Event<Notification, Request, Response> {
+    /// Origin and destination of a network message.
+    fn message_endpoints(&self) -> Option<(Author, Author)> {
+        match self {
+            Event::DataSyncRequestEvent {
+                sender, receiver, ..
+            } => Some((*receiver, *sender)),
+            _ => self.endpoints(),
+        }
+    }
+
     fn kind(&self) -> usize {
         use Event::*;
         match self {
@@ -134,29 +642,117 @@ impl<Notification, Request, Response> Event<Notification, Request, Response> {
             DataSyncRequestEvent { .. } => 1,
             DataSyncResponseEvent { .. } => 2,
             UpdateTimerEvent { .. } => 3,
+            RestartEvent { .. } => 4,
+        }
+    }
+
+    /// Node that processes the event.
+    fn processing_node(&self) -> Author {
+        use Event::*;
+        match self {
+            // Requests are answered by the `sender` of the data.
+            DataSyncRequestEvent { sender, .. } => *sender,
+            DataSyncNotifyEvent { receiver, .. } | DataSyncResponseEvent { receiver, .. } => {
+                *receiver
+            }
+            UpdateTimerEvent { author } | RestartEvent { author } => *author,
+        }
+    }
+
+    /// Sender and receiver of a network event.
+    fn endpoints(&self) -> Option<(Author, Author)> {
+        use Event::*;
+        match self {
+            DataSyncNotifyEvent {
+                sender, receiver, ..
+            }
+            | DataSyncRequestEvent {
+                sender, receiver, ..
+            }
+            | DataSyncResponseEvent {
+                sender, receiver, ..
+            } => Some((*sender, *receiver)),
+            UpdateTimerEvent { .. } | RestartEvent { .. } => None,
+        }
+    }
+}
+
+impl<Notification, Request, Response> Event<Notification, Request, Response>
+where
+    Notification: Serialize,
+    Request: Serialize,
+    Response: Serialize,
+{
+    /// Size in bytes of the serialized content of a network message.
+    fn message_size(&self) -> usize {
+        use Event::*;
+        let size = match self {
+            DataSyncNotifyEvent { notification, .. } => bcs::serialized_size(notification),
+            DataSyncRequestEvent { request, .. } => bcs::serialized_size(request),
+            DataSyncResponseEvent { response, .. } => bcs::serialized_size(response),
+            UpdateTimerEvent { .. } | RestartEvent { .. } => Ok(0),
+        };
+        size.expect("serializing simulated events should not fail")
+    }
+}
+
+impl<Notification, Request, Response> ScheduledEvent<Event<Notification, Request, Response>>
+where
+    Notification: Serialize,
+    Request: Serialize,
+    Response: Serialize,
+{
+    fn new(
+        scheduled_time: GlobalTime,
+        creation_stamp: usize,
+        event: Event<Notification, Request, Response>,
+    ) -> Self {
+        ScheduledEvent {
+            scheduled_time,
+            creation_stamp,
+            content_stamp: Cell::new(None),
+            event,
+        }
+    }
+
+    fn content_stamp(&self) -> u64 {
+        match self.content_stamp.get() {
+            Some(stamp) => stamp,
+            None => {
+                let stamp = stable_digest(&(self.scheduled_time.0, &self.event));
+                self.content_stamp.set(Some(stamp));
+                stamp
+            }
         }
     }
 }
 
 impl<Notification, Request, Response> PartialOrd
     for ScheduledEvent<Event<Notification, Request, Response>>
+where
+    Notification: Serialize,
+    Request: Serialize,
+    Response: Serialize,
 {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(&other))
     }
 }
 
-impl<Notification, Request, Response> Ord
-    for ScheduledEvent<Event<Notification, Request, Response>>
+impl<Notification, Request, Response> Ord for ScheduledEvent<Event<Notification, Request, Response>>
+where
+    Notification: Serialize,
+    Request: Serialize,
+    Response: Serialize,
 {
     // std::collections::BinaryHeap is a max heap.
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (
-            other.scheduled_time,
-            self.event.kind(),
-            other.creation_stamp,
-        )
-            .cmp(&(self.scheduled_time, other.event.kind(), self.creation_stamp))
+        (other.scheduled_time, self.event.kind())
+            .cmp(&(self.scheduled_time, other.event.kind()))
+            // Break ties by content first, so that the order of equal events does not depend
+            // on the order of insertion.
+            .then_with(|| other.content_stamp().cmp(&self.content_stamp()))
+            .then_with(|| other.creation_stamp.cmp(&self.creation_stamp))
     }
 }
 
@@ -168,17 +764,53 @@ impl<Event> PartialEq for ScheduledEvent<Event> {
 
 impl<Event> Eq for ScheduledEvent<Event> {}
 
+impl ClockJitter {
+    fn apply(&mut self, clock: NodeTime) -> NodeTime {
+        let max_jitter = self.max_jitter.0;
+        NodeTime(clock.0 + self.rng.gen_range(-max_jitter..=max_jitter))
+    }
+}
+
 impl<Node, Context> SimulatedNode<Node, Context>
 where
     Node: ConsensusNode<Context>,
-    Context: SmrContext,
+    Context: SmrContext + LocalClock,
 {
     fn update(&mut self, global_clock: GlobalTime) -> NodeUpdateActions<Context> {
-        let local_clock = global_clock.to_node_time(self.startup_time);
+        let mut local_clock = global_clock.to_node_time(self.startup_time);
+        if let Some(clock_jitter) = &mut self.clock_jitter {
+            local_clock = clock_jitter.apply(local_clock);
+        }
+        self.context.update_local_clock(local_clock);
         self.node.update_node(&mut self.context, local_clock)
     }
 }
 
+impl<Node, Context> SimulatedNode<Node, Context> {
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn startup_time(&self) -> GlobalTime {
+        self.startup_time
+    }
+}
+
+impl<Node, Context> SimulatedNode<Node, Context>
+where
+    Node: ApproximateMemory,
+    Context: ApproximateMemory,
+{
+    /// Approximate memory used by the node and its context.
+    pub fn approximate_memory(&self) -> usize {
+        self.node.approximate_memory() + self.context.approximate_memory()
+    }
+}
+
 impl<Node, Context> ActiveRound for SimulatedNode<Node, Context>
 where
     Node: ActiveRound,
@@ -186,6 +818,37 @@ where
     fn active_round(&self) -> Round {
         self.node.active_round()
     }
+
+    fn highest_committed_round(&self) -> Round {
+        self.node.highest_committed_round()
+    }
+}
+
+impl<Node, Context> ActiveEpoch for SimulatedNode<Node, Context>
+where
+    Node: ActiveEpoch,
+{
+    fn active_epoch(&self) -> EpochId {
+        self.node.active_epoch()
+    }
+}
+
+impl<Node, Context> ViewChangeLatencies for SimulatedNode<Node, Context>
+where
+    Node: ViewChangeLatencies,
+{
+    fn view_change_latencies(&self) -> Vec<(Round, Duration)> {
+        self.node.view_change_latencies()
+    }
+}
+
+impl<Node, Context> CommitGap for SimulatedNode<Node, Context>
+where
+    Node: CommitGap,
+{
+    fn commit_gap(&self) -> usize {
+        self.node.commit_gap()
+    }
 }
 
 impl<Node, Context, Notification, Request, Response>
@@ -193,14 +856,16 @@ impl<Node, Context, Notification, Request, Response>
 where
     Node: ConsensusNode<Context>,
     Context: SmrContext,
-    Notification: Debug,
-    Request: Debug,
-    Response: Debug,
+    Notification: Serialize + Debug,
+    Request: Serialize + Debug,
+    Response: Serialize + Debug,
 {
     pub fn new<F>(
         rng_seed: u64,
         num_nodes: usize,
         network_delay: RandomDelay,
+        startup_mode: StartupMode,
+        byzantine_behaviors: HashMap<Author, ByzantineBehavior>,
         context_factory: F,
     ) -> Simulator<Node, Context, Notification, Request, Response>
     where
@@ -214,7 +879,13 @@ where
             .map(|index| {
                 let author = Author(index);
                 let mut context = context_factory(author, num_nodes);
-                let startup_time = clock.add_delay(&mut rng, network_delay) + Duration(1);
+                let startup_time = match startup_mode {
+                    StartupMode::Randomized => clock.add_delay(&mut rng, network_delay),
+                    StartupMode::Simultaneous => clock,
+                    StartupMode::Sequential { spacing } => {
+                        clock + Duration(spacing.0 * index as i64)
+                    }
+                } + Duration(1);
                 let node_time = NodeTime(0);
                 let scheduled_time = GlobalTime::from_node_time(node_time, startup_time);
                 let event = Event::UpdateTimerEvent { author };
@@ -225,28 +896,163 @@ where
                     event,
                     scheduled_time
                 );
-                pending_events.push(ScheduledEvent {
-                    scheduled_time,
-                    creation_stamp: event_count,
-                    event,
-                });
+                pending_events.push(ScheduledEvent::new(scheduled_time, event_count, event));
                 event_count += 1;
                 SimulatedNode {
                     startup_time,
                     ignore_scheduled_updates_until: startup_time + Duration(-1),
+                    disconnected_until: GlobalTime(-1),
+                    clock_jitter: None,
+                    signature_count: 0,
                     node,
                     context,
                 }
             })
             .collect();
+        let faulty_authors = byzantine_behaviors.keys().cloned().collect();
+        let proposal_withholders = byzantine_behaviors
+            .iter()
+            .filter(|(_, behavior)| **behavior == ByzantineBehavior::SilentLeader)
+            .map(|(author, _)| *author)
+            .collect();
         Simulator {
             clock,
             network_delay,
+            link_delays: LinkDelayMatrix::uniform(network_delay),
+            link_delay_ranges: BTreeMap::new(),
+            synchrony: None,
+            max_network_delay_after_gst: None,
             pending_events,
             nodes,
             event_count,
             rng,
+            proposal_withholders,
+            trace_recording: None,
+            replayed_trace: BTreeMap::new(),
+            trace_divergence: None,
+            partition: Partition::new(),
+            crash_schedule: CrashSchedule::new(),
+            restart_count: 0,
+            byzantine_behaviors,
+            equivocation_count: 0,
+            commit_certificate_delay: Duration(0),
+            sent_commit_certificates: BTreeMap::new(),
+            signing_delay: Duration(0),
+            faulty_authors,
+            message_schedule: MessageSchedule::Default,
+            edge_message_counts: BTreeMap::new(),
+            message_size_accounting: false,
+            bytes_sent: BTreeMap::new(),
+            follower_to_follower_message_count: 0,
+            lost_message_count: 0,
+            run_metadata: RunMetadata {
+                seed: rng_seed,
+                nodes: num_nodes,
+                delay_mean: network_delay.mean(),
+                delay_variance: network_delay.variance(),
+                delta: None,
+                gamma: None,
+                lambda: None,
+            },
+            throughput_bucket_size: GlobalTime(100),
+        }
+    }
+
+    /// Add an observer node that starts now. Observers receive all the broadcasts of the other
+    /// nodes, but the context given here must exclude `author` from the epoch configurations
+    /// (i.e. give it no voting rights) so that the observer never counts towards quorums.
+    /// Authors are indices, hence `author` must be the next available one.
+    pub fn add_observer(&mut self, author: Author, mut context: Context) {
+        assert_eq!(
+            author.0,
+            self.nodes.len(),
+            "Observers must be added with the next available author"
+        );
+        let startup_time = self.clock + Duration(1);
+        let node_time = NodeTime(0);
+        let node = block_on(Node::load_node(&mut context, node_time))
+            .expect("loading nodes in simulator should not fail");
+        self.nodes.push(SimulatedNode {
+            startup_time,
+            ignore_scheduled_updates_until: startup_time + Duration(-1),
+            disconnected_until: GlobalTime(-1),
+            clock_jitter: None,
+            signature_count: 0,
+            node,
+            context,
+        });
+        let scheduled_time = GlobalTime::from_node_time(node_time, startup_time);
+        self.schedule_event(scheduled_time, Event::UpdateTimerEvent { author });
+    }
+
+    /// Add a validator that starts at `join_time` from the initial state of the given context
+    /// (i.e. with an empty store) and catches up with the network through data-sync. The new
+    /// node is granted one vote by a reconfiguration at the epoch following the latest epoch
+    /// committed by any node, so that the quorums of past and current epochs are unchanged.
+    /// Reconfigurations already scheduled after that epoch are left as is. Authors are
+    /// indices, hence `author` must be the next available one. Returns the first epoch of the
+    /// new node.
+    pub fn add_late_joiner(
+        &mut self,
+        author: Author,
+        join_time: GlobalTime,
+        mut context: Context,
+    ) -> EpochId
+    where
+        Context: DynamicCommittee,
+    {
+        assert_eq!(
+            author.0,
+            self.nodes.len(),
+            "Late joiners must be added with the next available author"
+        );
+        assert!(
+            join_time > self.clock,
+            "Late joiners must join in the future"
+        );
+        let first_epoch = self
+            .nodes
+            .iter()
+            .map(|node| node.context.next_epoch_id())
+            .chain(std::iter::once(context.next_epoch_id()))
+            .max()
+            .unwrap();
+        let mut voting_rights = context.committee_at(first_epoch);
+        voting_rights.push((author, 1));
+        for node in &mut self.nodes {
+            node.context.reconfigure(first_epoch, voting_rights.clone());
         }
+        context.reconfigure(first_epoch, voting_rights);
+        let node_time = NodeTime(0);
+        let node = block_on(Node::load_node(&mut context, node_time))
+            .expect("loading nodes in simulator should not fail");
+        self.nodes.push(SimulatedNode {
+            startup_time: join_time,
+            ignore_scheduled_updates_until: join_time + Duration(-1),
+            // Messages sent to the node before it joins are lost.
+            disconnected_until: join_time + Duration(-1),
+            clock_jitter: None,
+            signature_count: 0,
+            node,
+            context,
+        });
+        let scheduled_time = GlobalTime::from_node_time(node_time, join_time);
+        self.schedule_event(scheduled_time, Event::UpdateTimerEvent { author });
+        first_epoch
+    }
+
+    /// Crash and restart nodes according to `crash_schedule`. While a node is down, its timers
+    /// and the messages it receives are dropped. When it restarts, the node is reloaded from the
+    /// storage of its context, hence it loses any state that was not saved.
+    pub fn with_crash_schedule(mut self, crash_schedule: CrashSchedule) -> Self {
+        for (author, intervals) in &crash_schedule.down_intervals {
+            self.faulty_authors.insert(*author);
+            for (_, end) in intervals {
+                self.schedule_event(*end, Event::RestartEvent { author: *author });
+            }
+        }
+        self.crash_schedule = crash_schedule;
+        self
     }
 
     fn schedule_event(
@@ -254,19 +1060,15 @@ where
         scheduled_time: GlobalTime,
         event: Event<Notification, Request, Response>,
     ) {
+        let scheduled_time = match self.replayed_trace.get(&self.event_count) {
+            Some(entry) => entry.scheduled_time,
+            None => scheduled_time,
+        };
         trace!("Scheduling event {:?} for {:?}", event, scheduled_time);
-        self.pending_events.push(ScheduledEvent {
-            scheduled_time,
-            creation_stamp: self.event_count,
-            event,
-        });
+        self.pending_events
+            .push(ScheduledEvent::new(scheduled_time, self.event_count, event));
         self.event_count += 1;
     }
-
-    fn schedule_network_event(&mut self, event: Event<Notification, Request, Response>) {
-        let scheduled_time = self.clock.add_delay(&mut self.rng, self.network_delay);
-        self.schedule_event(scheduled_time, event);
-    }
 }
 
 impl<Node, Context, Notification, Request, Response>
@@ -279,20 +1081,462 @@ impl<Node, Context, Notification, Request, Response>
     fn simulated_node_mut(&mut self, author: Author) -> &mut SimulatedNode<Node, Context> {
         self.nodes.get_mut(author.0).unwrap()
     }
+
+    /// Drop all the network messages sent or received by `author` until the given time
+    /// (included).
+    pub fn disconnect_until(&mut self, author: Author, time: GlobalTime) {
+        self.faulty_authors.insert(author);
+        self.simulated_node_mut(author).disconnected_until = time;
+    }
+
+    /// Choose how the messages of nodes are addressed.
+    pub fn with_message_schedule(mut self, message_schedule: MessageSchedule) -> Self {
+        self.message_schedule = message_schedule;
+        self
+    }
+
+    /// Draw the delays of network messages from a distribution specific to each link, instead
+    /// of the single distribution given to `new`.
+    pub fn with_link_delays(mut self, link_delays: LinkDelayMatrix) -> Self {
+        self.link_delays = link_delays;
+        self
+    }
+
+    /// Smallest and largest delays of the network messages sent so far over each edge
+    /// `(origin, destination)`.
+    pub fn link_delay_ranges(&self) -> &BTreeMap<(Author, Author), (Duration, Duration)> {
+        &self.link_delay_ranges
+    }
+
+    /// Number of network messages sent so far over each edge `(origin, destination)`.
+    pub fn edge_message_counts(&self) -> &BTreeMap<(Author, Author), usize> {
+        &self.edge_message_counts
+    }
+
+    /// Serialize the state of the random generator of the simulator (e.g. network delays and
+    /// shuffling of receivers), so that a snapshot of a run can later sample the same values.
+    pub fn rng_state(&self) -> Vec<u8> {
+        bcs::to_bytes(&self.rng).expect("serializing the random generator should not fail")
+    }
+
+    /// Restore the state of the random generator saved by `rng_state`.
+    pub fn restore_rng_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        self.rng = bcs::from_bytes(state)?;
+        Ok(())
+    }
+
+    /// Measure the size of each network message by serializing it. Disabled by default to avoid
+    /// the overhead.
+    pub fn with_message_size_accounting(mut self, message_size_accounting: bool) -> Self {
+        self.message_size_accounting = message_size_accounting;
+        self
+    }
+
+    /// Number of bytes sent so far over each edge `(origin, destination)`. Empty unless message
+    /// sizes are measured.
+    pub fn bytes_sent(&self) -> &BTreeMap<(Author, Author), usize> {
+        &self.bytes_sent
+    }
+
+    /// Number of network messages sent so far between two nodes that were both followers
+    /// according to the origin of the message.
+    pub fn follower_to_follower_message_count(&self) -> usize {
+        self.follower_to_follower_message_count
+    }
+
+    /// Record the events processed by `loop_until` in a trace, written to the file `path` when
+    /// `loop_until` returns.
+    pub fn record_trace(&mut self, path: &Path) {
+        self.trace_recording = Some((path.to_path_buf(), Vec::new()));
+    }
+
+    /// Replay the trace recorded in the file `path`: events are scheduled at their recorded
+    /// times instead of the sampled ones. Events are identified by their creation stamps, hence
+    /// the simulator should use the same seed and configuration as the recorded run.
+    pub fn replay_trace(&mut self, path: &Path) -> anyhow::Result<()> {
+        let entries: Vec<TraceEntry> = serde_json::from_slice(&fs::read(path)?)?;
+        self.replayed_trace = entries
+            .into_iter()
+            .map(|entry| (entry.creation_stamp, entry))
+            .collect();
+        Ok(())
+    }
+
+    /// First processed event that differs from the replayed trace, if any, together with the
+    /// recorded event.
+    pub fn trace_divergence(&self) -> Option<&(TraceEntry, TraceEntry)> {
+        self.trace_divergence.as_ref()
+    }
+
+    /// Drop the network messages sent over the links severed by `partition`.
+    pub fn with_partition(mut self, partition: Partition) -> Self {
+        self.partition = partition;
+        self
+    }
+
+    /// Number of network messages lost so far, according to the loss probabilities of the
+    /// delay distributions.
+    pub fn lost_message_count(&self) -> usize {
+        self.lost_message_count
+    }
+
+    /// Drop all the notifications carrying a proposal from one of the given authors. Other
+    /// notifications of these authors (e.g. with votes or timeouts) are still delivered.
+    pub fn withhold_proposals<I>(&mut self, authors: I)
+    where
+        I: IntoIterator<Item = Author>,
+    {
+        let authors: Vec<_> = authors.into_iter().collect();
+        self.faulty_authors.extend(authors.iter().cloned());
+        self.proposal_withholders.extend(authors);
+    }
+
+    /// Number of nodes restarted from their storage so far.
+    pub fn restart_count(&self) -> usize {
+        self.restart_count
+    }
+
+    /// Number of notifications sent so far with a conflicting proposal by the nodes flagged
+    /// with `ByzantineBehavior::Equivocate`.
+    pub fn equivocation_count(&self) -> usize {
+        self.equivocation_count
+    }
+
+    /// Delay the notifications carrying a commit certificate by `extra_delay`, on top of the
+    /// network delay, unless the same certificate (or a newer one) was already sent over the
+    /// same link. Other messages are not affected.
+    pub fn delay_commit_certificates(&mut self, extra_delay: Duration) {
+        assert!(extra_delay.0 >= 0, "Extra delays must be non-negative");
+        self.commit_certificate_delay = extra_delay;
+    }
+
+    fn is_disconnected(&self, author: Author, clock: GlobalTime) -> bool {
+        clock <= self.simulated_node(author).disconnected_until
+    }
+
+    /// Model a partially synchronous network: the delays of the messages sent at or after the
+    /// global stabilization time `gst` are capped at `delta_bound`. Before `gst`, delays follow
+    /// the random distribution of the simulator.
+    pub fn with_gst(mut self, gst: GlobalTime, delta_bound: i64) -> Self {
+        assert!(delta_bound >= 0, "Delay bound must be non-negative");
+        self.synchrony = Some((gst, Duration(delta_bound)));
+        self
+    }
+
+    /// Record the pacemaker parameters of the nodes in the run metadata. The simulator does not
+    /// see the configuration of the nodes, so these are only informative.
+    pub fn with_pacemaker_parameters(mut self, delta: Duration, gamma: f64, lambda: f64) -> Self {
+        self.run_metadata.delta = Some(delta);
+        self.run_metadata.gamma = Some(gamma);
+        self.run_metadata.lambda = Some(lambda);
+        self
+    }
+
+    /// Aggregate the throughput data written by `loop_until` over intervals of the given length.
+    pub fn with_throughput_bucket_size(mut self, bucket_size: GlobalTime) -> Self {
+        assert!(bucket_size.0 > 0, "Buckets must not be empty");
+        self.throughput_bucket_size = bucket_size;
+        self
+    }
+
+    /// Model a signing latency (e.g. of an HSM): the messages sent by a node after an event are
+    /// delayed by `signing_delay` for every signature produced while processing the event.
+    pub fn with_signing_delay(mut self, signing_delay: Duration) -> Self {
+        assert!(signing_delay.0 >= 0, "Signing delays must be non-negative");
+        self.signing_delay = signing_delay;
+        self
+    }
+
+    /// Parameters needed to reproduce this run.
+    pub fn run_metadata(&self) -> &RunMetadata {
+        &self.run_metadata
+    }
+
+    /// Largest delay of the network messages sent at or after GST so far, if any.
+    pub fn max_network_delay_after_gst(&self) -> Option<Duration> {
+        self.max_network_delay_after_gst
+    }
+
+    /// Add a bounded and symmetric noise to the clock passed to `update_node`, uniformly
+    /// distributed in `[-max_jitter, max_jitter]`. The noise of each node is deterministic: it is
+    /// seeded with `seed` and the index of the node.
+    pub fn with_clock_jitter(mut self, max_jitter: Duration, seed: u64) -> Self {
+        assert!(max_jitter.0 >= 0, "Clock jitter must be non-negative");
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            node.clock_jitter = Some(ClockJitter {
+                max_jitter,
+                rng: Xoshiro256StarStar::seed_from_u64(seed.wrapping_add(index as u64)),
+            });
+        }
+        self
+    }
 }
 
 impl<Node, Context, Notification, Request, Response>
     Simulator<Node, Context, Notification, Request, Response>
 where
     Context: SmrContext<Author = Author>,
+{
+    /// Compare the weight of the nodes marked as faulty so far (by `disconnect_until` or
+    /// `withhold_proposals`) with the maximal faulty weight tolerated by the current
+    /// configuration of the first node.
+    pub fn fault_report(&self) -> FaultReport {
+        let context = &self.simulated_node(Author(0)).context;
+        let configuration = context.configuration(&context.last_committed_state());
+        let tolerated_f = configuration.max_faulty_weight();
+        let actual_faulty_weight = configuration.count_votes(&self.faulty_authors);
+        FaultReport {
+            tolerated_f,
+            actual_faulty_weight,
+            safe: actual_faulty_weight <= tolerated_f,
+        }
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Node: ActiveRound,
+    Context: CommittedCommandCount,
+{
+    /// Report whether the run made progress so far and which nodes did not commit anything.
+    pub fn liveness_report(&self) -> LivenessReport {
+        let stalled_nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.context.committed_command_count() == 0)
+            .map(|(index, _)| Author(index))
+            .collect();
+        let final_round = self
+            .nodes
+            .iter()
+            .map(|node| node.node.active_round())
+            .max()
+            .unwrap_or(Round(0));
+        LivenessReport {
+            committed_any: stalled_nodes.len() < self.nodes.len(),
+            final_round,
+            stalled_nodes,
+        }
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Context: SafetyMonitoring,
+{
+    /// Cross-check the commits of the current nodes with the given monitor. The simulation
+    /// panics as soon as two nodes commit different states at the same height of the ledger.
+    pub fn with_safety_monitor(mut self, monitor: SafetyMonitor) -> Self {
+        for node in &mut self.nodes {
+            node.context.set_safety_monitor(monitor.clone());
+        }
+        self
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Node: CommittedLeaders<Author = Author>,
+{
+    /// Number of committed blocks proposed by each node, counting every block committed by
+    /// any node once.
+    pub fn leader_histogram(&self) -> BTreeMap<Author, usize> {
+        let mut leaders = BTreeMap::new();
+        for node in &self.nodes {
+            for (epoch_id, round, author) in node.node.committed_leaders() {
+                leaders.insert((epoch_id, round), author);
+            }
+        }
+        let mut histogram: BTreeMap<_, _> = (0..self.nodes.len()).map(|i| (Author(i), 0)).collect();
+        for author in leaders.values() {
+            *histogram.entry(*author).or_insert(0) += 1;
+        }
+        histogram
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Node: ApproximateMemory,
+    Context: ApproximateMemory,
+{
+    /// Report the approximate memory used by each node.
+    pub fn memory_report(&self) -> Vec<(Author, usize)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (Author(index), node.approximate_memory()))
+            .collect()
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Node: KnownRecords,
+{
+    /// Report the fraction of all the records known in the network that each node holds.
+    pub fn sync_completeness(&self) -> Vec<(Author, f64)> {
+        let known_records: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| node.node.known_records())
+            .collect();
+        let mut all_records = HashSet::new();
+        for records in &known_records {
+            all_records.extend(records.iter());
+        }
+        known_records
+            .iter()
+            .enumerate()
+            .map(|(index, records)| {
+                let completeness = if all_records.is_empty() {
+                    1.0
+                } else {
+                    records.len() as f64 / all_records.len() as f64
+                };
+                (Author(index), completeness)
+            })
+            .collect()
+    }
+}
+
+impl<Node, Context, Notification, Request, Response>
+    Simulator<Node, Context, Notification, Request, Response>
+where
+    Context: SmrContext<Author = Author>
+        + LocalClock
+        + ForkRate
+        + CommittedCommandCount
+        + SignatureCount,
     Node: ConsensusNode<Context>
         + DataSyncNode<Context, Notification = Notification, Request = Request, Response = Response>
         + ActiveRound
+        + ActiveEpoch
+        + CommitGap
+        + ViewChangeLatencies
+        + ActiveLeader<Author = Author>
         + Debug,
-    Notification: Debug + Clone,
-    Request: Debug + Clone,
-    Response: Debug,
+    Notification: ProposalAuthor<Author = Author>
+        + CommitCertificateCarrier
+        + ByzantineNotification<Context>
+        + Serialize
+        + Debug
+        + Clone,
+    Request: Serialize + Debug + Clone,
+    Response: Serialize + Debug,
 {
+    fn schedule_network_event(&mut self, event: Event<Notification, Request, Response>) {
+        self.schedule_delayed_network_event(event, Duration(0))
+    }
+
+    /// Schedule a network event after an extra delay spent by the sender before sending.
+    fn schedule_delayed_network_event(
+        &mut self,
+        event: Event<Notification, Request, Response>,
+        extra_delay: Duration,
+    ) {
+        let endpoints = event.message_endpoints();
+        if let Some((origin, destination)) = endpoints {
+            let leader = self.simulated_node(origin).node.active_leader();
+            let is_leader = leader == Some(origin);
+            if self.message_schedule == MessageSchedule::TwoPhase
+                && !is_leader
+                && leader != Some(destination)
+            {
+                debug!("@{:?} Dropping message of follower {:?}", self.clock, event);
+                return;
+            }
+            *self
+                .edge_message_counts
+                .entry((origin, destination))
+                .or_insert(0) += 1;
+            if self.message_size_accounting {
+                *self.bytes_sent.entry((origin, destination)).or_insert(0) += event.message_size();
+            }
+            if !is_leader && leader != Some(destination) {
+                self.follower_to_follower_message_count += 1;
+            }
+        }
+        let network_delay = match endpoints {
+            Some((origin, destination)) => self.link_delays.delay(origin, destination),
+            None => self.network_delay,
+        };
+        if let Some((origin, destination)) = endpoints {
+            if !self.partition.is_link_up(origin, destination, self.clock) {
+                debug!(
+                    "@{:?} Dropping message on severed link {:?}",
+                    self.clock, event
+                );
+                return;
+            }
+        }
+        // Only sample the RNG when needed so that lossless runs are unchanged.
+        if network_delay.loss_probability > 0.0 && self.rng.gen_bool(network_delay.loss_probability)
+        {
+            debug!("@{:?} Losing message {:?}", self.clock, event);
+            self.lost_message_count += 1;
+            return;
+        }
+        let mut scheduled_time = self.clock.add_delay(&mut self.rng, network_delay) + extra_delay;
+        if let Event::DataSyncNotifyEvent {
+            sender,
+            receiver,
+            notification,
+        } = &event
+        {
+            if let Some(certificate) = notification.commit_certificate() {
+                let last_sent = self.sent_commit_certificates.get(&(*sender, *receiver));
+                if last_sent < Some(&certificate) {
+                    self.sent_commit_certificates
+                        .insert((*sender, *receiver), certificate);
+                    scheduled_time = scheduled_time + self.commit_certificate_delay;
+                }
+            }
+        }
+        // Faults delay messages, but messages sent after GST are still delivered within the
+        // synchrony bound.
+        if let Some((gst, delta_bound)) = self.synchrony {
+            if self.clock >= gst {
+                scheduled_time = std::cmp::min(scheduled_time, self.clock + delta_bound);
+                let delay = Duration(scheduled_time.0 - self.clock.0);
+                self.max_network_delay_after_gst =
+                    std::cmp::max(self.max_network_delay_after_gst, Some(delay));
+            }
+        }
+        if let Some(edge) = endpoints {
+            let delay = Duration(scheduled_time.0 - self.clock.0);
+            let range = self.link_delay_ranges.entry(edge).or_insert((delay, delay));
+            range.0 = std::cmp::min(range.0, delay);
+            range.1 = std::cmp::max(range.1, delay);
+        }
+        self.schedule_event(scheduled_time, event);
+    }
+
+    /// Adjust the destinations requested for the messages of `author` to the message schedule.
+    fn message_destinations(&self, author: Author, requested: Vec<Author>) -> Vec<Author> {
+        let requested =
+            if self.message_schedule != MessageSchedule::TwoPhase || requested.is_empty() {
+                requested
+            } else {
+                match self.simulated_node(author).node.active_leader() {
+                    Some(leader) if leader == author => (0..self.nodes.len()).map(Author).collect(),
+                    Some(leader) => vec![leader],
+                    None => Vec::new(),
+                }
+            };
+        // Members of future epochs may not be simulated yet.
+        requested
+            .into_iter()
+            .filter(|receiver| *receiver != author && receiver.0 < self.nodes.len())
+            .collect()
+    }
+
     fn process_node_actions(
         &mut self,
         clock: GlobalTime,
@@ -323,81 +1567,155 @@ where
         let event = Event::UpdateTimerEvent { author };
         self.schedule_event(new_scheduled_time, event);
         // Schedule sending notifications.
-        let mut receivers = Vec::new();
-        if actions.should_broadcast {
+        let receivers = if actions.should_broadcast {
             // TODO: broadcasting to all (past and future) nodes in the network is not entirely
             // realistic. The pseudo-code should probably use `actions.should_send` instead to
             // broadcast only to the nodes that a sender consider part of the epoch.
-            for index in 0..self.nodes.len() {
-                if index != author.0 {
-                    receivers.push(Author(index));
+            match self.message_schedule {
+                MessageSchedule::PartialBroadcast { fanout } => {
+                    let mut others: Vec<_> = (0..self.nodes.len())
+                        .map(Author)
+                        .filter(|receiver| {
+                            *receiver != author && !actions.should_send.contains(receiver)
+                        })
+                        .collect();
+                    others.shuffle(&mut self.rng);
+                    others.truncate(fanout);
+                    actions.should_send.into_iter().chain(others).collect()
                 }
+                _ => (0..self.nodes.len()).map(Author).collect(),
             }
         } else {
-            for receiver in actions.should_send {
-                if receiver != author {
-                    receivers.push(receiver);
-                }
-            }
-        }
+            actions.should_send
+        };
+        let mut receivers = self.message_destinations(author, receivers);
         receivers.shuffle(&mut self.rng);
-        let notification = {
-            let node = self.simulated_node(author);
-            node.node.create_notification(&node.context)
+        let behavior = self.byzantine_behaviors.get(&author).cloned();
+        let signing_delay = self.signing_delay;
+        let (notification, conflicting_notification, extra_delay) = {
+            let node = self.simulated_node_mut(author);
+            let notification = node.node.create_notification(&node.context);
+            let (notification, conflicting_notification) = match behavior {
+                Some(ByzantineBehavior::Equivocate) => {
+                    let conflicting_notification = notification.equivocate(&mut node.context);
+                    (notification, conflicting_notification)
+                }
+                Some(ByzantineBehavior::VoteWithholding) => (notification.without_vote(), None),
+                _ => (notification, None),
+            };
+            // Messages are sent once all the signatures of the event are produced.
+            let signature_count = node.context.signature_count();
+            let new_signatures = signature_count.saturating_sub(node.signature_count);
+            node.signature_count = signature_count;
+            let extra_delay = Duration(signing_delay.0 * new_signatures as i64);
+            (notification, conflicting_notification, extra_delay)
         };
-        for receiver in receivers {
-            self.schedule_network_event(Event::DataSyncNotifyEvent {
-                sender: author,
-                receiver,
-                notification: notification.clone(),
-            });
+        for (index, receiver) in receivers.into_iter().enumerate() {
+            // Equivocators send their conflicting proposal to every other receiver.
+            let notification = match &conflicting_notification {
+                Some(conflicting_notification) if index % 2 == 1 => {
+                    debug!("@{:?} {:?} equivocates to {:?}", clock, author, receiver);
+                    self.equivocation_count += 1;
+                    conflicting_notification.clone()
+                }
+                _ => notification.clone(),
+            };
+            self.schedule_delayed_network_event(
+                Event::DataSyncNotifyEvent {
+                    sender: author,
+                    receiver,
+                    notification,
+                },
+                extra_delay,
+            );
         }
         // Schedule sending requests.
-        let mut senders = Vec::new();
-        if actions.should_query_all {
+        let senders = if actions.should_query_all {
             // TODO: similarly `should_query_all` is probably too coarse.
-            for index in 0..self.nodes.len() {
-                if index != author.0 {
-                    senders.push(Author(index));
-                }
-            }
-        }
+            (0..self.nodes.len()).map(Author).collect()
+        } else {
+            Vec::new()
+        };
         let request = {
             let node = self.simulated_node(author);
             node.node.create_request(&node.context)
         };
-        let mut senders = senders.into_iter().collect::<Vec<_>>();
+        let mut senders = self.message_destinations(author, senders);
         senders.shuffle(&mut self.rng);
         for sender in senders {
-            self.schedule_network_event(Event::DataSyncRequestEvent {
-                receiver: author,
-                sender,
-                request: request.clone(),
-            });
+            self.schedule_delayed_network_event(
+                Event::DataSyncRequestEvent {
+                    receiver: author,
+                    sender,
+                    request: request.clone(),
+                },
+                extra_delay,
+            );
         }
     }
 
     pub fn loop_until(&mut self, max_clock: GlobalTime, csv_path: Option<String>) -> Vec<&Context> {
-        let mut data_writer = { csv_path.map(|path| DataWriter::new(self.nodes.len(), path)) };
+        let mut data_writer = csv_path.map(|path| {
+            DataWriter::new(
+                self.nodes.len(),
+                path,
+                &self.run_metadata,
+                self.throughput_bucket_size,
+            )
+        });
 
-        while let Some(ScheduledEvent {
-            scheduled_time: clock,
-            event,
-            ..
-        }) = self.pending_events.pop()
-        {
-            if clock > max_clock {
+        while let Some(scheduled_event) = self.pending_events.pop() {
+            if scheduled_event.scheduled_time > max_clock {
+                // Keep the event so that the simulation can be resumed later.
+                self.pending_events.push(scheduled_event);
                 break;
             }
+            let ScheduledEvent {
+                scheduled_time: clock,
+                creation_stamp,
+                event,
+                ..
+            } = scheduled_event;
+            let processing_node = event.processing_node();
+            let entry = TraceEntry {
+                scheduled_time: clock,
+                kind: event.kind(),
+                sender: event.endpoints().map(|(sender, _)| sender),
+                receiver: event.processing_node(),
+                creation_stamp,
+            };
+            if let Some(recorded) = self.replayed_trace.get(&creation_stamp) {
+                if *recorded != entry && self.trace_divergence.is_none() {
+                    debug!("@{:?} Diverging from trace: {:?}", clock, entry);
+                    self.trace_divergence = Some((entry.clone(), recorded.clone()));
+                }
+            }
+            if let Some((_, entries)) = &mut self.trace_recording {
+                entries.push(entry);
+            }
 
             if let Some(data_writer_val) = data_writer.as_mut() {
                 data_writer_val.update_round_number(&self, &clock);
-                data_writer_val.add_message_counter(&event);
+                data_writer_val.update_commit_gap(self, &clock);
+                data_writer_val.update_view_changes(self, &clock);
+                data_writer_val.update_fork_rates(self, &clock);
+                data_writer_val.update_committed_commands(self, &clock);
+                data_writer_val.add_message_counter(&event, &clock);
             }
 
             // Events scheduled in the past are fine but they do not move the clock.
             let clock = std::cmp::max(clock, self.clock);
             self.clock = clock;
+            if let Some((sender, receiver)) = event.endpoints() {
+                if self.is_disconnected(sender, clock) || self.is_disconnected(receiver, clock) {
+                    debug!("@{:?} Dropping event {:?}", clock, event);
+                    continue;
+                }
+            }
+            if self.crash_schedule.is_down(event.processing_node(), clock) {
+                debug!("@{:?} Dropping event of crashed node {:?}", clock, event);
+                continue;
+            }
             debug!("@{:?} Processing event {:?}", clock, event);
             match event {
                 Event::UpdateTimerEvent { author } => {
@@ -413,16 +1731,37 @@ where
                     trace!("Node state: {:?}", self.simulated_node(author));
                     self.process_node_actions(clock, author, actions);
                 }
+                Event::RestartEvent { author } => {
+                    let actions = {
+                        let node = self.simulated_node_mut(author);
+                        let local_clock = clock.to_node_time(node.startup_time);
+                        node.node = block_on(Node::load_node(&mut node.context, local_clock))
+                            .expect("restarting nodes should not fail in simulator");
+                        node.update(clock)
+                    };
+                    debug!("@{:?} Restarted node {:?}", clock, author);
+                    self.restart_count += 1;
+                    self.process_node_actions(clock, author, actions);
+                }
                 Event::DataSyncNotifyEvent {
                     receiver,
                     sender,
                     notification,
                 } => {
+                    if let Some(author) = notification.proposal_author() {
+                        if self.proposal_withholders.contains(&author) {
+                            debug!("@{:?} Withholding proposal of {:?}", clock, author);
+                            continue;
+                        }
+                    }
                     let node = self.simulated_node_mut(receiver);
-                    let result = block_on(
-                        node.node
-                            .handle_notification(&mut node.context, notification),
-                    );
+                    let local_clock = clock.to_node_time(node.startup_time);
+                    let result = block_on(node.node.handle_notification(
+                        &mut node.context,
+                        sender,
+                        notification,
+                        local_clock,
+                    ));
                     let actions = node.update(clock);
                     if let Some(request) = result {
                         self.schedule_network_event(Event::DataSyncRequestEvent {
@@ -443,7 +1782,8 @@ where
                     sender,
                     request,
                 } => {
-                    let node = self.simulated_node_mut(receiver);
+                    // Requests are answered by the `sender` of the data.
+                    let node = self.simulated_node_mut(sender);
                     let response = block_on(node.node.handle_request(&mut node.context, request));
                     self.schedule_network_event(Event::DataSyncResponseEvent {
                         sender,
@@ -465,11 +1805,20 @@ where
                     self.process_node_actions(clock, receiver, actions);
                 }
             }
+            if let Some(data_writer_val) = data_writer.as_mut() {
+                let node = self.simulated_node(processing_node);
+                let (epoch_id, round) = (node.active_epoch(), node.highest_committed_round());
+                data_writer_val.record_commit(processing_node, epoch_id, round, clock);
+            }
         }
 
         if let Some(data_writer_val) = data_writer {
             data_writer_val.write_to_file();
         }
+        if let Some((path, entries)) = &self.trace_recording {
+            let file = fs::File::create(path).expect("could not create trace file");
+            serde_json::to_writer(file, entries).expect("Writing did not succeed");
+        }
 
         self.nodes.iter().map(|node| &node.context).collect()
     }
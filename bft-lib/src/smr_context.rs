@@ -17,6 +17,15 @@ pub trait SmrTypes {
 pub trait CommandFetcher<Command> {
     /// How to fetch valid commands to submit to the consensus protocol.
     fn fetch(&mut self) -> Option<Command>;
+
+    /// Return the command that the next call to `fetch` would return, without consuming it.
+    fn peek(&self) -> Option<Command>;
+
+    /// Whether some commands are currently waiting to be fetched.
+    /// By default, the supply of commands is assumed to be unlimited.
+    fn has_pending_commands(&self) -> bool {
+        true
+    }
 }
 
 pub trait CommandExecutor<Author, State, Command> {
@@ -32,6 +41,8 @@ pub trait CommandExecutor<Author, State, Command> {
         // Time associated to this execution step, in agreement with
         // other consensus nodes.
         time: NodeTime,
+        // Author of the block proposing the command.
+        author: Author,
         // Suggest to reward the author of the previous block, if any.
         previous_author: Option<Author>,
         // Suggest to reward the voters of the previous block, if any.
@@ -41,8 +52,11 @@ pub trait CommandExecutor<Author, State, Command> {
 
 /// A commit certificate.
 // TODO: more APIs
-pub trait CommitCertificate<State> {
+pub trait CommitCertificate<Author, State> {
     fn committed_state(&self) -> Option<&State>;
+
+    /// Authors of the signatures that certify the commit.
+    fn signers(&self) -> Vec<Author>;
 }
 
 /// How to communicate that a state was committed or discarded.
@@ -51,15 +65,46 @@ pub trait CommitCertificate<State> {
 // module provided by the SMR Context. We use a trait object for now
 // to avoid circular dependencies and keep things simple. (We could also
 // separate the SMRContext and the crypto module)
-pub trait StateFinalizer<State> {
+pub trait StateFinalizer<Author, State> {
     /// Report that a state was committed, together with an optional commit certificate.
-    fn commit(&mut self, state: &State, commit_certificate: Option<&dyn CommitCertificate<State>>);
+    fn commit(
+        &mut self,
+        state: &State,
+        commit_certificate: Option<&dyn CommitCertificate<Author, State>>,
+    );
+
+    /// Report that consecutive states of the same epoch were committed, in order, together with
+    /// an optional commit certificate for the last one. By default, each state is reported with
+    /// `commit`.
+    fn commit_batch(
+        &mut self,
+        states: &[(Round, State)],
+        commit_certificate: Option<&dyn CommitCertificate<Author, State>>,
+    ) {
+        if let Some(((_, last_state), states)) = states.split_last() {
+            for (_, state) in states {
+                self.commit(state, None);
+            }
+            self.commit(last_state, commit_certificate);
+        }
+    }
 
     /// Report that a state was discarded.
     fn discard(&mut self, state: &State);
 
     /// Obtain the last committed state, if any, and otherwise the genesis state.
     fn last_committed_state(&self) -> State;
+
+    /// Serialize the committed ledger for external checkpointing.
+    /// By default, checkpoints are not supported and carry no data.
+    fn export_checkpoint(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore the committed ledger from a checkpoint created by `export_checkpoint`.
+    fn import_checkpoint(&mut self, _bytes: &[u8]) -> Result<()> {
+        anyhow::bail!("Checkpoints are not supported")
+    }
 }
 
 /// How to read epoch ids and configuration from a state.
@@ -69,6 +114,9 @@ pub trait EpochReader<Author: Hash, State> {
 
     /// Return the configuration (i.e. voting rights) for the epoch starting at a given state.
     fn configuration(&self, state: &State) -> EpochConfiguration<Author>;
+
+    /// Return the first state of the given epoch, if it is known and committed.
+    fn epoch_boundary_state(&self, epoch_id: EpochId) -> Option<State>;
 }
 
 /// Something that we know how to hash and sign.
@@ -100,13 +148,13 @@ pub trait CryptographicModule {
     type Hasher: std::io::Write;
 
     /// The identity (ie. public key) of a node.
-    type Author: Serialize + DeserializeOwned + Debug + Copy + Eq + Hash + Send + 'static;
+    type Author: Serialize + DeserializeOwned + Debug + Copy + Ord + Hash + Send + 'static;
 
     /// The type of signature values.
     type Signature: Serialize + DeserializeOwned + Debug + Copy + Eq + Hash + Send + 'static;
 
     /// The type of hash values.
-    type HashValue: Serialize + DeserializeOwned + Debug + Copy + Eq + Hash + Send + 'static;
+    type HashValue: Serialize + DeserializeOwned + Debug + Copy + Ord + Hash + Send + 'static;
 
     /// Hash the given message, including a type-based seed.
     fn hash(&self, message: &dyn Signable<Self::Hasher>) -> Self::HashValue;
@@ -121,9 +169,10 @@ pub trait CryptographicModule {
     /// The public key of this node.
     fn author(&self) -> Self::Author;
 
-    /// Sign a message using the private key of this node.
-    // TODO: make async to enable HSM implementations.
-    fn sign(&mut self, hash: Self::HashValue) -> Self::Signature;
+    /// Sign a message using the private key of this node. Asynchronous so that the key may be
+    /// held by an HSM.
+    /// The latency of signing is modeled by the simulator (see `Simulator::with_signing_delay`).
+    fn sign(&mut self, hash: Self::HashValue) -> AsyncResult<'_, Self::Signature>;
 }
 
 pub trait Storage {
@@ -140,7 +189,7 @@ pub trait SmrContext:
         <Self as SmrTypes>::State,
         <Self as SmrTypes>::Command,
     > + CommandFetcher<<Self as SmrTypes>::Command>
-    + StateFinalizer<<Self as SmrTypes>::State>
+    + StateFinalizer<<Self as CryptographicModule>::Author, <Self as SmrTypes>::State>
     + EpochReader<<Self as CryptographicModule>::Author, <Self as SmrTypes>::State>
     + Storage
     + Eq
@@ -170,7 +219,8 @@ pub trait Authored<A> {
 }
 
 impl<T, S> SignedValue<T, S> {
-    pub fn make<C>(context: &mut C, value: T) -> Self
+    /// Sign `value` on behalf of its author.
+    pub async fn make<C>(context: &mut C, value: T) -> Result<Self>
     where
         S: Copy,
         C: SmrContext<Signature = S>,
@@ -178,8 +228,8 @@ impl<T, S> SignedValue<T, S> {
     {
         assert_eq!(value.author(), context.author());
         let h = context.hash(&value);
-        let signature = context.sign(h);
-        SignedValue { value, signature }
+        let signature = context.sign(h).await?;
+        Ok(SignedValue { value, signature })
     }
 
     pub fn verify<C>(&self, context: &C) -> Result<()>
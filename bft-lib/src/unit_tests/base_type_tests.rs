@@ -7,3 +7,10 @@ use super::*;
 fn test_round_plus_usize() {
     assert_eq!(Round(3) + 4, Round(7));
 }
+
+#[test]
+fn test_previous_epoch() {
+    assert_eq!(EpochId(0).previous(), None);
+    assert_eq!(EpochId(1).previous(), Some(EpochId(0)));
+    assert_eq!(EpochId(5).previous(), Some(EpochId(4)));
+}
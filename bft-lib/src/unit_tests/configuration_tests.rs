@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
+use std::collections::HashSet;
 
 #[test]
 fn test_count() {
@@ -13,6 +14,81 @@ fn test_count() {
     assert_eq!(config.count_votes(vec![&"4"]), 0);
 }
 
+#[test]
+#[should_panic(expected = "at least one author")]
+fn test_empty_committee() {
+    EpochConfiguration::<&str>::new(Vec::new());
+}
+
+#[test]
+#[should_panic(expected = "positive total of voting rights")]
+fn test_zero_votes_committee() {
+    EpochConfiguration::new(vec![("0", 0), ("1", 0)]);
+}
+
+#[test]
+fn test_single_author_committee() {
+    let config = EpochConfiguration::new(vec![("0", 1)]);
+    assert_eq!(config.quorum_threshold(), 1);
+    for seed in 0..10 {
+        assert_eq!(config.pick_author(seed), "0");
+    }
+}
+
+#[test]
+fn test_thresholds_of_small_committees() {
+    // (size, quorum threshold, validity threshold) for unit voting rights.
+    let expected = vec![
+        (1, 1, 1),
+        (2, 2, 1),
+        (3, 3, 1),
+        (4, 3, 2),
+        (5, 4, 2),
+        (6, 5, 2),
+        (7, 5, 3),
+        (8, 6, 3),
+        (9, 7, 3),
+        (10, 7, 4),
+    ];
+    for (size, quorum, validity) in expected {
+        let config = EpochConfiguration::new((0..size).map(|i| (i, 1)).collect());
+        assert_eq!(config.quorum_threshold(), quorum);
+        assert_eq!(config.validity_threshold(), validity);
+        assert_eq!(config.max_faulty_weight(), (size - 1) / 3);
+    }
+}
+
+#[test]
+fn test_quorum_intersection() {
+    let mut rng = Xoshiro256StarStar::seed_from_u64(7);
+    for _ in 0..500 {
+        let size = rng.gen_range(1..15);
+        let rights: Vec<(usize, usize)> = (0..size).map(|i| (i, rng.gen_range(1..10))).collect();
+        let config = EpochConfiguration::new(rights.clone());
+        // Draw two random quorums by adding random authors until the threshold is reached.
+        let mut draw_quorum = || {
+            let mut authors: Vec<_> = rights.iter().map(|(author, _)| *author).collect();
+            let mut quorum = HashSet::new();
+            while !config.is_quorum(config.count_votes(&quorum)) {
+                let index = rng.gen_range(0..authors.len());
+                quorum.insert(authors.swap_remove(index));
+            }
+            quorum
+        };
+        let (quorum1, quorum2) = (draw_quorum(), draw_quorum());
+        let intersection = config.count_votes(quorum1.intersection(&quorum2));
+        // The intersection always contains an honest node.
+        assert!(intersection >= config.validity_threshold());
+        assert!(intersection > config.max_faulty_weight());
+    }
+    // The worst case (two quorums of minimal weight) also holds for any total weight.
+    for total_votes in 1..10_000 {
+        let quorum = quorum_threshold(total_votes);
+        assert!(quorum <= total_votes);
+        assert!(2 * quorum - total_votes >= validity_threshold(total_votes));
+    }
+}
+
 #[test]
 fn test_pick_author() {
     let rights = vec![("0", 1), ("1", 2), ("2", 5)];
@@ -28,6 +104,85 @@ fn test_pick_author() {
     assert_eq!(vec![1, 2, 5], results);
 }
 
+/// Author owning the vote at position `target`, found by a linear scan.
+fn author_at_position(authors: &[(usize, usize)], mut target: usize) -> usize {
+    for (author, votes) in authors {
+        if *votes > target {
+            return *author;
+        }
+        target -= *votes;
+    }
+    unreachable!()
+}
+
+#[test]
+fn test_pick_author_matches_linear_scan() {
+    let mut rng = Xoshiro256StarStar::seed_from_u64(0);
+    for _ in 0..200 {
+        let num_authors = rng.gen_range(1..50);
+        // Include authors without voting rights.
+        let mut rights: Vec<_> = (0..num_authors).map(|i| (i, rng.gen_range(0..5))).collect();
+        rights[rng.gen_range(0..num_authors)].1 += 1;
+        let config = EpochConfiguration::new(rights.clone());
+        for _ in 0..50 {
+            let seed = rng.gen();
+            let mut seeded_rng = Xoshiro256StarStar::seed_from_u64(seed);
+            assert_eq!(
+                config.pick_author(seed),
+                author_at_position(&rights, seeded_rng.gen_range(0..config.total_votes))
+            );
+            let index = rng.gen_range(0..10 * config.total_votes);
+            assert_eq!(
+                config.author_at(index),
+                author_at_position(&rights, index % config.total_votes)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_author_at() {
+    let config = EpochConfiguration::new(vec![("0", 1), ("1", 2), ("2", 3)]);
+    let schedule: Vec<_> = (0..8).map(|index| config.author_at(index)).collect();
+    assert_eq!(schedule, vec!["0", "1", "1", "2", "2", "2", "0", "1"]);
+}
+
+#[test]
+fn test_pick_author_by_content() {
+    let config = EpochConfiguration::new(vec![("0", 1), ("1", 2), ("2", 5), ("3", 1)]);
+    let shuffled = EpochConfiguration::new(vec![("2", 5), ("3", 1), ("0", 1), ("1", 2)]);
+    let schedule = |config: &EpochConfiguration<&'static str>| {
+        (0..100)
+            .map(|seed| config.pick_author_by_content(seed))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(schedule(&config), schedule(&shuffled));
+    // Every author is picked eventually.
+    let picked: std::collections::HashSet<_> = schedule(&config).into_iter().collect();
+    assert_eq!(picked.len(), 4);
+}
+
+#[test]
+fn test_committee_file() {
+    let path = std::env::temp_dir().join("test_committee_file.json");
+    let config = EpochConfiguration::new(vec![(2usize, 1), (0, 3), (1, 5), (3, 2)]);
+    config.to_file(&path).unwrap();
+    let loaded = EpochConfiguration::<usize>::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(config, loaded);
+    for seed in 0..100 {
+        assert_eq!(config.pick_author(seed), loaded.pick_author(seed));
+    }
+}
+
+#[test]
+fn test_invalid_committee_file() {
+    let path = std::env::temp_dir().join("test_invalid_committee_file.json");
+    std::fs::write(&path, "[]").unwrap();
+    assert!(EpochConfiguration::<usize>::from_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
 fn equal_configuration(num_nodes: usize) -> EpochConfiguration<usize> {
     let mut voting_rights = Vec::new();
     for index in 0..num_nodes {
@@ -45,3 +200,13 @@ fn test_quorum() {
     assert_eq!(equal_configuration(5).quorum_threshold(), 4);
     assert_eq!(equal_configuration(6).quorum_threshold(), 5);
 }
+
+#[test]
+fn test_max_faulty_weight() {
+    for n in &[1, 4, 7] {
+        let config = equal_configuration(*n);
+        assert_eq!(config.max_faulty_weight(), (n - 1) / 3);
+        assert!(config.is_quorum(n - config.max_faulty_weight()));
+        assert!(!config.is_quorum(n - config.max_faulty_weight() - 1));
+    }
+}
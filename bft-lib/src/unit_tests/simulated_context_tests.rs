@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
+use futures::executor::block_on;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -23,7 +24,7 @@ fn test_hashing_and_signing() {
     let h1 = context.hash(&Foo(35));
     let h2 = context.hash(&Bar(35));
 
-    let sig1 = context.sign(h1);
+    let sig1 = block_on(context.sign(h1)).unwrap();
     assert!(context.verify(Author(0), h1, sig1).is_ok());
     assert!(context.verify(Author(1), h1, sig1).is_err());
     assert!(context.verify(Author(0), h2, sig1).is_err());
@@ -33,18 +34,26 @@ fn test_hashing_and_signing() {
     hasher.write(b"Foo::");
     hasher.write(&bytes);
     let h = hasher.finish();
+    #[cfg(not(feature = "wide-hash"))]
     assert_eq!(h1, h);
+    #[cfg(feature = "wide-hash")]
+    {
+        // The lower bits are unchanged.
+        assert_eq!(h1 as u64, h);
+        assert_ne!(h1 >> 64, 0);
+    }
 }
 
 #[test]
 fn test_happened_before() {
-    let mut s1 = SimulatedLedgerState::new();
-    let mut s2 = SimulatedLedgerState::new();
+    let mut s1 = SimulatedLedgerState::new(2);
+    let mut s2 = SimulatedLedgerState::new(2);
     assert!(!s1.happened_just_before(&s2));
     s1.execute(
         Command {
             proposer: Author(0),
             index: 0,
+            payload: Vec::new(),
         },
         NodeTime(1),
     );
@@ -54,6 +63,7 @@ fn test_happened_before() {
         Command {
             proposer: Author(1),
             index: 0,
+            payload: Vec::new(),
         },
         NodeTime(1),
     );
@@ -61,6 +71,7 @@ fn test_happened_before() {
         Command {
             proposer: Author(1),
             index: 0,
+            payload: Vec::new(),
         },
         NodeTime(1),
     );
@@ -70,10 +81,27 @@ fn test_happened_before() {
 #[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Debug, Serialize, Deserialize)]
 struct DummyCertificate;
 
-impl CommitCertificate<State> for DummyCertificate {
+impl CommitCertificate<Author, State> for DummyCertificate {
     fn committed_state(&self) -> Option<&State> {
         None
     }
+
+    fn signers(&self) -> Vec<Author> {
+        Vec::new()
+    }
+}
+
+/// A certificate committing a given state with the given signers.
+struct TestCertificate(State, Vec<Author>);
+
+impl CommitCertificate<Author, State> for TestCertificate {
+    fn committed_state(&self) -> Option<&State> {
+        Some(&self.0)
+    }
+
+    fn signers(&self) -> Vec<Author> {
+        self.1.clone()
+    }
 }
 
 #[test]
@@ -89,23 +117,23 @@ fn test_simulated_context() {
     let c3 = context.fetch().unwrap();
 
     let s1 = context
-        .compute(&s0, c1, NodeTime(1), None, Vec::new())
+        .compute(&s0, c1, NodeTime(1), Author(0), None, Vec::new())
         .unwrap();
     assert_eq!(context.read_epoch_id(&s1), EpochId(0));
 
     let s2 = context
-        .compute(&s1, c2, NodeTime(4), None, Vec::new())
+        .compute(&s1, c2, NodeTime(4), Author(0), None, Vec::new())
         .unwrap();
     assert_eq!(context.read_epoch_id(&s2), EpochId(1));
 
     let s3 = context
-        .compute(&s0, c3, NodeTime(3), None, Vec::new())
+        .compute(&s0, c3, NodeTime(3), Author(0), None, Vec::new())
         .unwrap();
     assert_eq!(context.read_epoch_id(&s3), EpochId(0));
 
-    StateFinalizer::<State>::commit(&mut context, &s1, None);
-    StateFinalizer::<State>::commit(&mut context, &s2, Some(&DummyCertificate));
-    StateFinalizer::<State>::discard(&mut context, &s3);
+    StateFinalizer::<Author, State>::commit(&mut context, &s1, None);
+    StateFinalizer::<Author, State>::commit(&mut context, &s2, Some(&DummyCertificate));
+    StateFinalizer::<Author, State>::discard(&mut context, &s3);
 
     assert_eq!(
         context.last_committed_ledger_state.execution_history,
@@ -114,6 +142,7 @@ fn test_simulated_context() {
                 Command {
                     proposer: Author(0),
                     index: 0,
+                    payload: Vec::new(),
                 },
                 NodeTime(1)
             ),
@@ -121,9 +150,240 @@ fn test_simulated_context() {
                 Command {
                     proposer: Author(0),
                     index: 1,
+                    payload: Vec::new(),
                 },
                 NodeTime(4)
             ),
         ]
     )
 }
+
+#[test]
+fn test_command_proposer_check() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 2,
+    )
+    .with_command_proposer_check(true);
+    let s0 = context.last_committed_state();
+    let c1 = context.fetch().unwrap();
+    assert!(context
+        .compute(&s0, c1.clone(), NodeTime(1), Author(1), None, Vec::new())
+        .is_none());
+    assert!(context
+        .compute(&s0, c1, NodeTime(1), Author(0), None, Vec::new())
+        .is_some());
+}
+
+#[test]
+fn test_epoch_boundary_state() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 2,
+    );
+    let s0 = context.last_committed_state();
+    let c1 = context.fetch().unwrap();
+    let c2 = context.fetch().unwrap();
+    let c3 = context.fetch().unwrap();
+
+    let s1 = context
+        .compute(&s0, c1, NodeTime(1), Author(0), None, Vec::new())
+        .unwrap();
+    let s2 = context
+        .compute(&s1, c2, NodeTime(2), Author(0), None, Vec::new())
+        .unwrap();
+    let s3 = context
+        .compute(&s2, c3, NodeTime(3), Author(0), None, Vec::new())
+        .unwrap();
+    assert_eq!(context.read_epoch_id(&s1), EpochId(0));
+    assert_eq!(context.read_epoch_id(&s2), EpochId(1));
+    assert_eq!(context.read_epoch_id(&s3), EpochId(1));
+    assert_eq!(context.epoch_boundary_state(EpochId(0)), Some(s0));
+    // Uncommitted states are not considered.
+    assert_eq!(context.epoch_boundary_state(EpochId(1)), None);
+
+    StateFinalizer::<Author, State>::commit(&mut context, &s1, None);
+    StateFinalizer::<Author, State>::commit(&mut context, &s2, None);
+    StateFinalizer::<Author, State>::commit(&mut context, &s3, None);
+    assert_eq!(context.epoch_boundary_state(EpochId(1)), Some(s2));
+    assert_eq!(context.epoch_boundary_state(EpochId(2)), None);
+}
+
+#[test]
+fn test_mismatched_epoch_limits() {
+    let mut context1 = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 2,
+    );
+    let mut context2 = SimulatedContext::new(
+        Author(1),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 3,
+    );
+    // The limit is part of the replicated state: nodes that disagree on epoch boundaries start
+    // from different states, hence cannot vote for the same states.
+    let s0 = context1.last_committed_state();
+    assert_ne!(s0, context2.last_committed_state());
+    let command = context1.fetch().unwrap();
+    let s1 = context1
+        .compute(
+            &s0,
+            command.clone(),
+            NodeTime(1),
+            Author(0),
+            None,
+            Vec::new(),
+        )
+        .unwrap();
+    assert!(context2
+        .compute(&s0, command, NodeTime(1), Author(0), None, Vec::new())
+        .is_none());
+    let command = context1.fetch().unwrap();
+    let s2 = context1
+        .compute(&s1, command, NodeTime(2), Author(0), None, Vec::new())
+        .unwrap();
+    assert_eq!(context1.read_epoch_id(&s2), EpochId(1));
+}
+
+#[test]
+fn test_command_generator() {
+    let generator = VariableSizeCommandGenerator {
+        max_payload_size: 100,
+    };
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 2,
+    )
+    .with_command_generator(generator.clone());
+    let mut state = context.last_committed_state();
+    for index in 0..5 {
+        let command = context.fetch().unwrap();
+        assert_eq!(command, generator.generate(Author(0), index));
+        assert!(command.payload.len() <= 100);
+        state = context
+            .compute(&state, command, NodeTime(1), Author(0), None, Vec::new())
+            .unwrap();
+        StateFinalizer::<Author, State>::commit(&mut context, &state, None);
+    }
+    let history = context.committed_history();
+    assert_eq!(history.len(), 5);
+    assert!(history
+        .iter()
+        .any(|(command, _)| command.payload.len() != history[0].0.payload.len()));
+    assert!(context
+        .committed_commands()
+        .zip(context.committed_history())
+        .all(|(command, (expected, _))| command == expected));
+    assert_eq!(context.commit_time(4), Some(NodeTime(1)));
+    assert_eq!(context.commit_time(5), None);
+}
+
+#[test]
+fn test_checkpoint() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 10,
+    );
+    let mut state = context.last_committed_state();
+    for _ in 0..3 {
+        let command = context.fetch().unwrap();
+        state = context
+            .compute(&state, command, NodeTime(1), Author(0), None, Vec::new())
+            .unwrap();
+        StateFinalizer::<Author, State>::commit(&mut context, &state, None);
+    }
+    let checkpoint = context.export_checkpoint();
+
+    let mut context2 = SimulatedContext::new(
+        Author(1),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 10,
+    );
+    assert_ne!(context2.last_committed_state(), state);
+    context2.import_checkpoint(&checkpoint).unwrap();
+    assert_eq!(context2.last_committed_state(), state);
+    assert_eq!(context2.committed_history(), context.committed_history());
+    assert!(context2.import_checkpoint(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_signature_verification() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 2,
+        /* max commands per epoch */ 2,
+    );
+    let hash = context.hash(&Foo(35));
+    let signature = block_on(context.sign(hash)).unwrap();
+    assert_eq!(context.signature_count(), 1);
+    let forged_signature = Signature(1, hash);
+    assert!(context.verify(Author(0), hash, forged_signature).is_err());
+    assert!(context.verify(Author(0), hash, signature).is_ok());
+    assert_eq!(context.verified_signature_count(), 2);
+
+    let context = context.with_signature_verification(SignatureVerification::Skip);
+    assert!(context.verify(Author(0), hash, forged_signature).is_ok());
+    assert_eq!(context.verified_signature_count(), 2);
+}
+
+/// Execute a command on top of the last committed state of a context with 4 nodes and
+/// commit the result with a certificate signed by the given authors.
+fn commit_with_signers(signers: Vec<Author>) {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 4,
+        /* max commands per epoch */ 2,
+    )
+    .with_commit_certificate_verification(true);
+    let s0 = context.last_committed_state();
+    let c1 = context.fetch().unwrap();
+    let s1 = context
+        .compute(&s0, c1, NodeTime(1), Author(0), None, Vec::new())
+        .unwrap();
+    let certificate = TestCertificate(s1.clone(), signers);
+    StateFinalizer::<Author, State>::commit(&mut context, &s1, Some(&certificate));
+    assert_eq!(context.last_committed_state(), s1);
+}
+
+#[test]
+fn test_commit_certificate_verification() {
+    commit_with_signers(vec![Author(0), Author(2), Author(3)]);
+}
+
+#[test]
+#[should_panic(expected = "is not signed by a quorum")]
+fn test_commit_certificate_verification_sub_quorum() {
+    // Repeated signers do not count twice.
+    commit_with_signers(vec![Author(0), Author(2), Author(2)]);
+}
+
+#[test]
+#[should_panic(expected = "Safety violation at height 1")]
+fn test_safety_monitor_detects_conflicting_commits() {
+    let monitor = SafetyMonitor::new();
+    let mut contexts: Vec<_> = (0..2)
+        .map(|index| {
+            let mut context = SimulatedContext::new(
+                Author(index),
+                /* num_nodes */ 2,
+                /* max commands per epoch */ 10,
+            );
+            context.set_safety_monitor(monitor.clone());
+            context
+        })
+        .collect();
+    // The same command executed at different times gives different states.
+    for (index, context) in contexts.iter_mut().enumerate() {
+        let s0 = context.last_committed_state();
+        let c1 = context.fetch().unwrap();
+        let s1 = context
+            .compute(&s0, c1, NodeTime(index as i64), Author(0), None, Vec::new())
+            .unwrap();
+        StateFinalizer::<Author, State>::commit(context, &s1, None);
+    }
+}
@@ -10,3 +10,65 @@ fn test_time_conversion() {
     assert_eq!(x.to_node_time(start), NodeTime(12));
     assert_eq!(GlobalTime::from_node_time(NodeTime(12), start), x);
 }
+
+#[test]
+fn test_content_stamps() {
+    type TestEvent = Event<u8, u8, u8>;
+    let events: Vec<(GlobalTime, TestEvent)> = vec![
+        (GlobalTime(3), Event::UpdateTimerEvent { author: Author(0) }),
+        (GlobalTime(3), Event::UpdateTimerEvent { author: Author(1) }),
+        (GlobalTime(5), Event::UpdateTimerEvent { author: Author(0) }),
+        (
+            GlobalTime(5),
+            Event::DataSyncNotifyEvent {
+                receiver: Author(1),
+                sender: Author(0),
+                notification: 7,
+            },
+        ),
+    ];
+    let schedule = |order: Vec<usize>| {
+        let mut stamps = vec![0; events.len()];
+        for (creation_stamp, index) in order.into_iter().enumerate() {
+            let (time, event) = events[index].clone();
+            stamps[index] = ScheduledEvent::new(time, creation_stamp, event).content_stamp();
+        }
+        stamps
+    };
+    let stamps = schedule(vec![0, 1, 2, 3]);
+    // Equal events have the same stamps regardless of the order of insertion.
+    assert_eq!(stamps, schedule(vec![3, 1, 0, 2]));
+    // Different events (or times) have different stamps.
+    let distinct: HashSet<_> = stamps.iter().collect();
+    assert_eq!(distinct.len(), events.len());
+    // Simultaneous events of the same kind are processed in the same order regardless of the
+    // order of insertion.
+    let process = |order: Vec<usize>| {
+        let mut heap = BinaryHeap::new();
+        for (creation_stamp, index) in order.into_iter().enumerate() {
+            let (time, event) = events[index].clone();
+            heap.push(ScheduledEvent::new(time, creation_stamp, event));
+        }
+        std::iter::from_fn(|| heap.pop().map(|scheduled| scheduled.event)).collect::<Vec<_>>()
+    };
+    assert_eq!(process(vec![0, 1, 2, 3]), process(vec![3, 1, 0, 2]));
+    assert_eq!(process(vec![0, 1, 2, 3]), process(vec![1, 0, 3, 2]));
+}
+
+#[test]
+fn test_safety_monitor() {
+    let monitor = SafetyMonitor::new();
+    assert!(monitor.record_commit(Author(0), 1, State(10)).is_ok());
+    assert!(monitor.record_commit(Author(0), 2, State(20)).is_ok());
+    assert!(monitor.record_commit(Author(1), 1, State(10)).is_ok());
+    assert_eq!(
+        monitor.record_commit(Author(1), 2, State(21)),
+        Err(SafetyViolation {
+            height: 2,
+            first_commit: (Author(0), State(20)),
+            second_commit: (Author(1), State(21)),
+        })
+    );
+    assert_eq!(monitor.highest_height(), 2);
+    assert_eq!(monitor.commit_count(), 4);
+}
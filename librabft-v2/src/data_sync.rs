@@ -28,6 +28,11 @@ pub struct DataSyncNotification<Context: SmrContext> {
     #[serde(bound(serialize = "Context: SmrContext"))]
     #[serde(bound(deserialize = "Context: SmrContext"))]
     timeouts: Vec<Timeout<Context>>,
+    /// Highest QC referenced by the timeouts of the highest TC, together with the certified
+    /// block, if enabled by the configuration of the sender.
+    #[serde(bound(serialize = "Context: SmrContext"))]
+    #[serde(bound(deserialize = "Context: SmrContext"))]
+    timeout_certificate_quorum_certificate: Option<(Block<Context>, QuorumCertificate<Context>)>,
     /// Sender's vote at the current round, if any (meant for the proposer).
     #[serde(bound(serialize = "Context: SmrContext"))]
     #[serde(bound(deserialize = "Context: SmrContext"))]
@@ -39,11 +44,16 @@ pub struct DataSyncNotification<Context: SmrContext> {
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-pub struct DataSyncRequest {
+pub struct DataSyncRequest<Context: SmrContext> {
     /// Current epoch identifier.
     current_epoch: EpochId,
     /// Selection of rounds for which the receiver already knows a QC.
     known_quorum_certificates: BTreeSet<Round>,
+    /// Blocks of the current epoch already known to the receiver, which should not be sent
+    /// again.
+    #[serde(bound(serialize = "Context: SmrContext"))]
+    #[serde(bound(deserialize = "Context: SmrContext"))]
+    known_block_hashes: BTreeSet<BlockHash<Context::HashValue>>,
 }
 
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Clone)]
@@ -52,7 +62,7 @@ pub struct DataSyncResponse<Context: SmrContext> {
     current_epoch: EpochId,
     /// Records for the receiver to insert, for each epoch, in the given order.
     /// Epochs older than the receiver's current epoch will be skipped, as well as chains
-    /// of records ending with QC known to the receiver.
+    /// of records ending with QC known to the receiver. Only the last epoch may be truncated.
     #[serde(bound(serialize = "Context: SmrContext"))]
     #[serde(bound(deserialize = "Context: SmrContext"))]
     records: Vec<(EpochId, Vec<Record<Context>>)>,
@@ -63,38 +73,91 @@ impl<Context> NodeState<Context>
 where
     Context: SmrContext,
 {
-    fn create_request_internal(&self) -> DataSyncRequest {
+    fn create_request_internal(&self) -> DataSyncRequest<Context> {
         DataSyncRequest {
             current_epoch: self.epoch_id(),
             known_quorum_certificates: self.record_store().known_quorum_certificate_rounds(),
+            known_block_hashes: self.record_store().known_block_hashes(),
         }
     }
 }
 
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ProposalAuthor for DataSyncNotification<Context> {
+    type Author = Context::Author;
+
+    fn proposal_author(&self) -> Option<Context::Author> {
+        self.proposed_block.as_ref().map(|block| block.value.author)
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::CommitCertificateCarrier
+    for DataSyncNotification<Context>
+{
+    fn commit_certificate(&self) -> Option<(EpochId, Round)> {
+        self.highest_commit_certificate
+            .as_ref()
+            .map(|certificate| (certificate.value.epoch_id, certificate.value.round))
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ByzantineNotification<Context>
+    for DataSyncNotification<Context>
+{
+    fn equivocate(&self, context: &mut Context) -> Option<Self> {
+        let block = self.proposed_block.as_ref()?;
+        let mut value = block.value.clone();
+        // Changing the proposed time is enough to change the hash of the block.
+        value.time = NodeTime(value.time.0 + 1);
+        let mut notification = self.clone();
+        notification.proposed_block = Some(sign_record(context, value));
+        Some(notification)
+    }
+
+    fn without_vote(&self) -> Self {
+        let mut notification = self.clone();
+        notification.current_vote = None;
+        notification
+    }
+}
+
 impl<Context> DataSyncNode<Context> for NodeState<Context>
 where
     Context: SmrContext,
 {
     type Notification = DataSyncNotification<Context>;
-    type Request = DataSyncRequest;
+    type Request = DataSyncRequest<Context>;
     type Response = DataSyncResponse<Context>;
 
     fn create_notification(&self, context: &Context) -> Self::Notification {
         // Pass the latest (non-empty) commit certificate across epochs.
         let highest_commit_certificate = match self.record_store().highest_commit_certificate() {
             Some(hqc) => Some(hqc.clone()),
-            None => self.epoch_id().previous().and_then(|previous_epoch| {
-                self.record_store_at(previous_epoch)
-                    .expect("The record store of the previous epoch should exist.")
-                    .highest_commit_certificate()
-                    .cloned()
-            }),
+            // Nodes started in a later epoch (e.g. from a genesis committee) have no record
+            // store for the previous epoch.
+            None => self
+                .epoch_id()
+                .previous()
+                .and_then(|previous_epoch| self.record_store_at(previous_epoch))
+                .and_then(|store| store.highest_commit_certificate().cloned()),
         };
+        // Optionally pass the QC referenced by the highest TC.
+        let timeout_certificate_quorum_certificate =
+            if self.config().attach_timeout_quorum_certificate {
+                self.record_store()
+                    .highest_timeout_certificate_quorum_certificate()
+                    .map(|(block, qc)| (block.clone(), qc.clone()))
+            } else {
+                None
+            };
         DataSyncNotification {
             current_epoch: self.epoch_id(),
             highest_commit_certificate,
             highest_quorum_certificate: self.record_store().highest_quorum_certificate().cloned(),
             timeouts: self.record_store().timeouts(),
+            timeout_certificate_quorum_certificate,
             current_vote: self.record_store().current_vote(context.author()).cloned(),
             proposed_block: match self.record_store().proposed_block(self.pacemaker()) {
                 Some((hash, _, author)) => {
@@ -113,7 +176,9 @@ where
     fn handle_notification(
         &mut self,
         smr_context: &mut Context,
+        sender: Context::Author,
         notification: Self::Notification,
+        clock: NodeTime,
     ) -> Async<Option<Self::Request>> {
         // Whether we should request more data because of a new epoch or missings records.
         let mut should_sync = false;
@@ -121,7 +186,18 @@ where
         // well send us a lengthy and slow `DataSyncResponse` directly. (DoS prevention is out of
         // scope for this simulator.)
         should_sync |= notification.current_epoch > self.epoch_id();
+        // Remember our highest QC to detect if the notification teaches us a higher one.
+        let previous_highest_quorum_certificate = (
+            self.epoch_id(),
+            self.record_store().highest_quorum_certificate_round(),
+        );
 
+        if let Some((block, qc)) = notification.timeout_certificate_quorum_certificate {
+            // Try to insert the QC referenced by the TC first so that timeouts can be verified.
+            let epoch_id = qc.value.epoch_id;
+            self.insert_network_record(epoch_id, Record::Block(block), smr_context);
+            self.insert_network_record(epoch_id, Record::QuorumCertificate(qc), smr_context);
+        }
         if let Some(highest_commit_certificate) = &notification.highest_commit_certificate {
             // Try to insert the QC just in case.
             self.insert_network_record(
@@ -147,6 +223,16 @@ where
                     && highest_quorum_certificate.value.round
                         > self.record_store().highest_quorum_certificate_round());
         }
+        if (
+            self.epoch_id(),
+            self.record_store().highest_quorum_certificate_round(),
+        ) > previous_highest_quorum_certificate
+        {
+            self.schedule_echo(
+                smr_context.author(),
+                self.record_store().highest_quorum_certificate_hash(),
+            );
+        }
         // Try to insert the proposed block right away.
         if let Some(block) = notification.proposed_block {
             self.insert_network_record(
@@ -167,8 +253,12 @@ where
         if let Some(vote) = notification.current_vote {
             self.insert_network_record(notification.current_epoch, Record::Vote(vote), smr_context);
         }
-        // Create a follow-up request if needed.
-        let value = if should_sync {
+        // Create a follow-up request if needed, unless previous requests to the same peer keep
+        // failing to make progress and we are backing off.
+        let value = if !should_sync {
+            self.reset_sync_attempts(sender);
+            None
+        } else if self.register_sync_attempt(sender, clock) {
             Some(self.create_request_internal())
         } else {
             None
@@ -185,19 +275,45 @@ where
         _smr_context: &mut Context,
         request: Self::Request,
     ) -> Async<Self::Response> {
+        let max_epochs = self.config().max_epochs_per_response.unwrap_or(usize::MAX);
         let mut records = Vec::new();
         if let Some(store) = self.record_store_at(request.current_epoch) {
             records.push((
                 request.current_epoch,
-                store.unknown_records(request.known_quorum_certificates),
+                store.unknown_records(
+                    request.known_quorum_certificates,
+                    request.known_block_hashes,
+                ),
             ));
         }
-        for i in (request.current_epoch.0 + 1)..(self.epoch_id().0 + 1) {
-            let epoch_id = EpochId(i);
-            let store = self
-                .record_store_at(epoch_id)
-                .expect("All record stores up to the current epoch should exist.");
-            records.push((epoch_id, store.unknown_records(BTreeSet::new())));
+        for epoch_id in self.known_epochs() {
+            if records.len() >= max_epochs {
+                // The requester will ask for the next epochs after applying these ones.
+                break;
+            }
+            if epoch_id <= request.current_epoch {
+                continue;
+            }
+            if let Some(store) = self.record_store_at(epoch_id) {
+                records.push((
+                    epoch_id,
+                    store.unknown_records(BTreeSet::new(), BTreeSet::new()),
+                ));
+            }
+        }
+        if let Some(max_records) = self.config().max_records_per_response {
+            // Keep the oldest records so that the requester can insert them right away.
+            let mut remaining = max_records;
+            let mut truncated_records = Vec::new();
+            for (epoch_id, mut epoch_records) in records {
+                if remaining == 0 {
+                    break;
+                }
+                epoch_records.truncate(remaining);
+                remaining -= epoch_records.len();
+                truncated_records.push((epoch_id, epoch_records));
+            }
+            records = truncated_records;
         }
         let value = DataSyncResponse {
             current_epoch: self.epoch_id(),
@@ -212,23 +328,23 @@ where
         response: Self::Response,
         clock: NodeTime,
     ) -> Async<()> {
-        let num_records = response.records.len();
         // Insert all the records in order.
         // Process the commits so that new epochs are created along the way.
         // No need to call a full handler `update_node` because past epochs are stopped.
-        for (i, (epoch_id, records)) in response.records.into_iter().enumerate() {
+        for (epoch_id, records) in response.records {
             if epoch_id < self.epoch_id() {
                 // Looks like we have stopped this epoch in the meantime.
                 continue;
             }
             if epoch_id > self.epoch_id() {
-                // This should not happen. Abort.
+                // This should not happen unless the previous epoch could not be completed.
+                // Abort.
                 break;
             }
             for record in records {
                 self.insert_network_record(epoch_id, record, smr_context);
             }
-            if i == num_records - 1 {
+            if epoch_id >= response.current_epoch {
                 // Leave the latest epoch for the main handler to process.
                 break;
             }
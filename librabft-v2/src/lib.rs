@@ -18,3 +18,11 @@ pub mod record;
 
 /// In-memory index of records.
 pub mod record_store;
+
+/// Simulations of LibraBFT v2 nodes.
+#[cfg(feature = "simulator")]
+pub mod simulation;
+
+/// Helpers to build signed records in tests.
+#[cfg(all(test, feature = "simulator"))]
+pub(crate) mod testing;
@@ -4,169 +4,171 @@
 //! Main executable to run a simulation of LibraBFT v2.
 
 use bft_lib::{
-    base_types::*, interfaces::ConsensusNode, simulated_context::SimulatedContext, simulator,
+    base_types::*,
+    simulated_context::Author,
+    simulator::{self, CommittedCommandCount, SafetyMonitor},
 };
-use clap::{App, Arg};
-use futures::executor::block_on;
 use librabft_v2::{
-    data_sync::*,
-    node::{NodeConfig, NodeState},
+    node::{dump_dag, NodeConfig},
+    simulation::SimulatorBuilder,
 };
 use log::{info, warn};
-use rand::Rng;
+use serde::Deserialize;
+use std::path::PathBuf;
+use structopt::{clap::ArgMatches, StructOpt};
+
+#[cfg(test)]
+#[path = "unit_tests/main_tests.rs"]
+mod main_tests;
 
 fn main() {
     let args = get_arguments();
     env_logger::init();
-    let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let seed = args.seed;
+    // Print the seed so that any run can be reproduced.
     warn!("seed: {}", seed);
-    let context_factory = |author, num_nodes| {
-        let mut context = SimulatedContext::new(author, num_nodes, args.commands_per_epoch);
-        let config = NodeConfig {
-            target_commit_interval: args.target_commit_interval,
-            delta: args.delta,
-            gamma: args.gamma,
-            lambda: args.lambda,
-        };
-        let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
-        block_on(node.save_node(&mut context)).unwrap();
-        context
+    let config = NodeConfig {
+        target_commit_interval: Duration(args.target_commit_interval),
+        delta: Duration(args.delta),
+        gamma: args.gamma,
+        lambda: args.lambda,
+        ..Default::default()
     };
     let delay_distribution = simulator::RandomDelay::new(args.mean, args.variance);
-    let mut sim = simulator::Simulator::<
-        NodeState<SimulatedContext>,
-        SimulatedContext,
-        DataSyncNotification<SimulatedContext>,
-        DataSyncRequest,
-        DataSyncResponse<SimulatedContext>,
-    >::new(seed, args.nodes, delay_distribution, context_factory);
-    let contexts = sim.loop_until(
-        simulator::GlobalTime(args.max_clock),
-        args.output_data_files,
-    );
+    let mut sim = SimulatorBuilder::new(seed, args.nodes, args.commands_per_epoch)
+        .with_config(config)
+        .with_network_delay(delay_distribution)
+        .build()
+        .with_pacemaker_parameters(Duration(args.delta), args.gamma, args.lambda)
+        .with_safety_monitor(SafetyMonitor::new());
+    let contexts = sim.loop_until(simulator::GlobalTime(args.max_clock), args.create_csv);
     warn!("Commands executed per node: {:#?}", {
         let x: Vec<_> = contexts
             .iter()
-            .map(|context| context.committed_history().len())
+            .map(|context| context.committed_command_count())
             .collect();
         x
     });
     info!("SMR contexts: {:#?}", contexts);
+    if let Some(path) = args.dump_dag {
+        let nodes = (0..args.nodes).map(|index| sim.simulated_node(Author(index)).node());
+        dump_dag(nodes, std::path::Path::new(&path)).expect("Failed to dump record DAGs");
+    }
 }
 
+/// Command-line arguments. The same parameters may be given in a TOML file with `--config`.
+#[derive(StructOpt)]
+#[structopt(
+    name = "Consensus simulator",
+    about = "A monte-carlo simulation of the LibraBFT consensus protocol",
+    rename_all = "snake"
+)]
 struct CliArguments {
+    /// TOML file containing values for the other arguments, using their names as keys
+    #[structopt(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Time at which to stop the simulation
+    #[structopt(long, default_value = "1000")]
     max_clock: i64,
+    /// The mean value of the normal distribution of the network delay
+    #[structopt(long, default_value = "10.0")]
     mean: f64,
+    /// The variance of the normal distribution of the network delay
+    #[structopt(long, default_value = "4.0")]
     variance: f64,
-    seed: Option<u64>,
+    /// The number of nodes to simulate
+    #[structopt(long, default_value = "3")]
     nodes: usize,
+    /// Seed for the PRNG of the simulator
+    #[structopt(long, value_name = "SEED", default_value = "0")]
+    seed: u64,
+    /// The maximum number of commands per epoch
+    #[structopt(long, default_value = "30000")]
     commands_per_epoch: usize,
-    target_commit_interval: Duration,
-    delta: Duration,
+    /// Minimal interval between query-all actions when no commit happens
+    #[structopt(long, default_value = "100000")]
+    target_commit_interval: i64,
+    /// Maximal duration of the first round after a commit rule
+    #[structopt(long, default_value = "20")]
+    delta: i64,
+    /// Exponent to increase round durations
+    #[structopt(long, default_value = "2.0")]
     gamma: f64,
+    /// Coefficient to control the frequency of query-all actions
+    #[structopt(long, default_value = "0.5")]
     lambda: f64,
-    output_data_files: Option<String>,
+    /// If given this argument, csv files will be generated with data on the simulation
+    #[structopt(long)]
+    create_csv: Option<String>,
+    /// Directory where to write the final record DAG of each node in DOT format
+    #[structopt(long, value_name = "PATH")]
+    dump_dag: Option<String>,
 }
 
-// TODO: use structopt
-fn get_arguments() -> CliArguments {
-    let matches = App::new("Consensus simulator")
-        .about("A monte-carlo simulation of the LibraBFT consensus protocol")
-        .arg(
-            Arg::with_name("max_clock")
-                .long("max_clock")
-                .help("Time at which to stop the simulation")
-                .default_value("1000"),
-        )
-        .arg(
-            Arg::with_name("mean")
-                .long("mean")
-                .help("The mean value of the normal distribution of the network delay")
-                .default_value("10.0"),
-        )
-        .arg(
-            Arg::with_name("variance")
-                .long("variance")
-                .help("The variance of the normal distribution of the network delay")
-                .default_value("4.0"),
-        )
-        .arg(
-            Arg::with_name("nodes")
-                .long("nodes")
-                .help("The number of nodes to simulate")
-                .default_value("3"),
-        )
-        .arg(
-            Arg::with_name("seed")
-                .long("seed")
-                .value_name("SEED")
-                .takes_value(true)
-                .help("Seed for the PRNG"),
-        )
-        .arg(
-            Arg::with_name("commands_per_epoch")
-                .long("commands_per_epoch")
-                .help("The maximum number of commands per epoch")
-                .default_value("30000"),
-        )
-        .arg(
-            Arg::with_name("target_commit_interval")
-                .long("target_commit_interval")
-                .help("Minimal interval between query-all actions when no commit happens")
-                .default_value("100000"),
-        )
-        .arg(
-            Arg::with_name("delta")
-                .long("delta")
-                .help("Maximal duration of the first round after a commit rule")
-                .default_value("20"),
-        )
-        .arg(
-            Arg::with_name("gamma")
-                .long("gamma")
-                .help("Exponent to increase round durations")
-                .default_value("2.0"),
-        )
-        .arg(
-            Arg::with_name("lambda")
-                .long("lambda")
-                .help("Coefficient to control the frequency of query-all actions")
-                .default_value("0.5"),
-        )
-        .arg(Arg::with_name("create_csv").long("create_csv").help(
-            "If given this argument, csv files will be generated with data on the simulation"
-        ).takes_value(true))
-        .get_matches();
+/// Arguments read from a config file.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileArguments {
+    max_clock: Option<i64>,
+    mean: Option<f64>,
+    variance: Option<f64>,
+    nodes: Option<usize>,
+    seed: Option<u64>,
+    commands_per_epoch: Option<usize>,
+    target_commit_interval: Option<i64>,
+    delta: Option<i64>,
+    gamma: Option<f64>,
+    lambda: Option<f64>,
+    create_csv: Option<String>,
+    dump_dag: Option<String>,
+}
 
-    CliArguments {
-        max_clock: matches
-            .value_of("max_clock")
-            .unwrap()
-            .parse::<i64>()
-            .unwrap(),
-        mean: matches.value_of("mean").unwrap().parse::<f64>().unwrap(),
-        variance: matches
-            .value_of("variance")
-            .unwrap()
-            .parse::<f64>()
-            .unwrap(),
-        nodes: matches.value_of("nodes").unwrap().parse::<usize>().unwrap(),
-        seed: matches.value_of("seed").map(|x| x.parse::<u64>().unwrap()),
-        commands_per_epoch: matches
-            .value_of("commands_per_epoch")
-            .unwrap()
-            .parse::<usize>()
-            .unwrap(),
-        target_commit_interval: Duration(
-            matches
-                .value_of("target_commit_interval")
-                .unwrap()
-                .parse::<i64>()
-                .unwrap(),
-        ),
-        delta: Duration(matches.value_of("delta").unwrap().parse::<i64>().unwrap()),
-        gamma: matches.value_of("gamma").unwrap().parse::<f64>().unwrap(),
-        lambda: matches.value_of("lambda").unwrap().parse::<f64>().unwrap(),
-        output_data_files: matches.value_of("create_csv").map(|x| x.to_string()),
+impl CliArguments {
+    /// Use the values of `file` for the arguments that were not given on the command line,
+    /// as recorded by `matches`.
+    fn complete(mut self, matches: &ArgMatches, file: FileArguments) -> Self {
+        macro_rules! complete {
+            ($($field:ident),*) => {
+                $(
+                    if matches.occurrences_of(stringify!($field)) == 0 {
+                        if let Some(value) = file.$field {
+                            self.$field = value;
+                        }
+                    }
+                )*
+            };
+        }
+        complete!(
+            max_clock,
+            mean,
+            variance,
+            nodes,
+            seed,
+            commands_per_epoch,
+            target_commit_interval,
+            delta,
+            gamma,
+            lambda
+        );
+        self.create_csv = self.create_csv.or(file.create_csv);
+        self.dump_dag = self.dump_dag.or(file.dump_dag);
+        self
     }
 }
+
+fn get_arguments() -> CliArguments {
+    let matches = CliArguments::clap().get_matches();
+    let args = CliArguments::from_clap(&matches);
+    let file = match &args.config {
+        Some(path) => {
+            let data = std::fs::read_to_string(path).unwrap_or_else(|error| {
+                panic!("Failed to read config file {}: {}", path.display(), error)
+            });
+            toml::from_str(&data).unwrap_or_else(|error| {
+                panic!("Failed to parse config file {}: {}", path.display(), error)
+            })
+        }
+        None => FileArguments::default(),
+    };
+    args.complete(&matches, file)
+}
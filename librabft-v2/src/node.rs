@@ -8,13 +8,13 @@ use anyhow::anyhow;
 use bft_lib::{
     base_types::*,
     interfaces::{ConsensusNode, NodeUpdateActions},
-    smr_context::SmrContext,
+    smr_context::{CommitCertificate, SmrContext},
 };
-use log::debug;
+use log::{debug, trace, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::{max, min},
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
 };
 
 #[cfg(all(test, feature = "simulator"))]
@@ -38,13 +38,77 @@ pub struct NodeState<Context: SmrContext> {
     locked_round: Round,
     /// Time of the latest query-all operation.
     latest_query_all_time: NodeTime,
+    /// Highest clock passed to `update_node` so far.
+    last_seen_clock: NodeTime,
     /// Track data to which the main handler has already reacted.
     tracker: CommitTracker,
     /// Record stores from previous epochs.
     past_record_stores: HashMap<EpochId, RecordStoreState<Context>>,
+    /// Static configuration of the node.
+    config: NodeConfig,
+    /// Recent data-sync requests to each peer. (Not persisted.)
+    #[serde(skip)]
+    sync_attempts: BTreeMap<Context::Author, SyncAttempts>,
+    /// Hash of the latest QC scheduled to be echoed to other nodes. (Not persisted.)
+    #[serde(skip)]
+    latest_echo: Option<QuorumCertificateHash<Context::HashValue>>,
+    /// Whether the next update should broadcast our data to echo a QC. (Not persisted.)
+    #[serde(skip)]
+    pending_echo: bool,
+    /// Number of proposals that we refused to vote for, by reason.
+    rejected_votes: RejectedVotes,
+    /// Latest proposal that we voted for or refused to vote for, so that each proposal is
+    /// evaluated (and each rejection counted) once. (Not persisted.)
+    #[serde(skip)]
+    latest_evaluated_proposal: Option<BlockHash<Context::HashValue>>,
+}
+
+/// Number of proposals that a node refused to vote for, by reason.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectedVotes {
+    /// The proposal does not extend a block at or above the locked round.
+    pub locked_round: usize,
+    /// We already voted at the round of the proposal (or at a higher round).
+    pub already_voted: usize,
+}
+
+/// Summary of the consensus view of a node, e.g. for dashboards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSnapshot<Author> {
+    /// Current epoch identifier.
+    pub epoch_id: EpochId,
+    /// Current round of the record store.
+    pub current_round: Round,
+    /// Round of the highest QC.
+    pub highest_quorum_certificate_round: Round,
+    /// Round of the highest commit.
+    pub highest_committed_round: Round,
+    /// Leader of the active round, if known.
+    pub active_leader: Option<Author>,
+    /// Number of votes received at the current round.
+    pub pending_vote_count: usize,
+    /// Total voting rights of the timeouts received at the current round.
+    pub timeout_weight: usize,
 }
 // -- END FILE --
 
+/// Maximal number of consecutive data-sync requests to the same peer that may fail to make
+/// progress before we start backing off.
+pub(crate) const MAX_SYNC_ATTEMPTS_WITHOUT_PROGRESS: usize = 10;
+
+/// Maximal number of times the backoff delay between data-sync requests is doubled.
+const MAX_SYNC_BACKOFF_EXPONENT: usize = 6;
+
+#[derive(Debug, PartialEq)]
+struct SyncAttempts {
+    /// Number of consecutive requests that did not make progress.
+    count: usize,
+    /// Our epoch and highest QC round at the time of the latest request.
+    progress: (EpochId, Round),
+    /// Time before which no new request should be sent.
+    next_attempt: NodeTime,
+}
+
 // -- BEGIN FILE commit_tracker --
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 struct CommitTracker {
@@ -72,12 +136,85 @@ impl CommitTracker {
 
 /// Initial configuration of LibraBFTv2 node.
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
-#[cfg_attr(test, derive(Default))]
 pub struct NodeConfig {
     pub target_commit_interval: Duration,
     pub delta: Duration,
     pub gamma: f64,
     pub lambda: f64,
+    /// Whether notifications should carry the QC referenced by the highest TC (and its block).
+    pub attach_timeout_quorum_certificate: bool,
+    /// Whether a leader should propose blocks when no commands are pending.
+    pub propose_when_empty: ProposeWhenEmpty,
+    /// Whether a leader should re-propose the command of its latest proposal abandoned after a
+    /// timeout.
+    pub reproposal: bool,
+    /// How committed states are delivered to the SMR layer.
+    pub commit_delivery: CommitDelivery,
+    /// Number of rounds before the current one for which votes are kept, so that leaders may
+    /// still form QCs for their proposals after moving to a higher round.
+    pub vote_retention: usize,
+    /// Whether leaders refuse to propose on top of a QC other than the highest one known
+    /// (instead of only warning about it).
+    pub strict_proposals: bool,
+    /// Maximal number of epochs of records sent in a data-sync response, if any. Nodes
+    /// lagging behind by more epochs catch up over several requests.
+    pub max_epochs_per_response: Option<usize>,
+    /// Whether nodes re-broadcast (once) every strictly higher QC learned from a notification,
+    /// so that QCs propagate by gossip when broadcasts only reach a subset of the nodes.
+    pub echo_quorum_certificates: bool,
+    /// How the leader of each round is elected.
+    pub leader_election: LeaderElectionPolicy,
+    /// Weight of the latest round in the moving average of round latencies, if the base
+    /// timeout of rounds adapts to this average instead of being fixed to `delta`.
+    pub adaptive_timeout: Option<f64>,
+    /// Number of committed rounds below the highest commit for which records are kept, if
+    /// older records are discarded to bound memory. Nodes lagging further behind cannot catch
+    /// up from a pruned store.
+    pub pruning_depth: Option<usize>,
+    /// Maximal number of records sent in a data-sync response, if any. The oldest records are
+    /// sent first and lagging nodes fetch the rest with their next requests.
+    pub max_records_per_response: Option<usize>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            target_commit_interval: Duration(100000),
+            delta: Duration(20),
+            gamma: 2.0,
+            lambda: 0.5,
+            attach_timeout_quorum_certificate: false,
+            propose_when_empty: ProposeWhenEmpty::default(),
+            reproposal: false,
+            commit_delivery: CommitDelivery::default(),
+            vote_retention: 0,
+            strict_proposals: false,
+            max_epochs_per_response: None,
+            echo_quorum_certificates: false,
+            leader_election: LeaderElectionPolicy::default(),
+            adaptive_timeout: None,
+            pruning_depth: None,
+            max_records_per_response: None,
+        }
+    }
+}
+
+/// How committed states are delivered to the SMR layer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommitDelivery {
+    /// Call `commit` once per committed round.
+    Individual,
+    /// Call `commit_batch` once for all the consecutive committed rounds of an epoch that are
+    /// processed together, e.g. after catching up.
+    Batched,
+}
+
+// Deriving `Default` for enums requires a more recent compiler.
+#[allow(clippy::derivable_impls)]
+impl Default for CommitDelivery {
+    fn default() -> Self {
+        CommitDelivery::Individual
+    }
 }
 
 impl<Context> NodeState<Context>
@@ -93,6 +230,7 @@ where
             initial_state.clone(),
             epoch_id,
             context.configuration(&initial_state),
+            &config,
         );
         let pacemaker = PacemakerState::new(
             epoch_id,
@@ -100,7 +238,9 @@ where
             config.delta,
             config.gamma,
             config.lambda,
-        );
+            config.propose_when_empty,
+        )
+        .with_adaptive_timeout(config.adaptive_timeout);
         NodeState {
             record_store,
             pacemaker,
@@ -108,8 +248,15 @@ where
             latest_voted_round: Round(0),
             locked_round: Round(0),
             latest_query_all_time: node_time,
+            last_seen_clock: node_time,
             tracker,
             past_record_stores: HashMap::new(),
+            config,
+            sync_attempts: BTreeMap::new(),
+            latest_echo: None,
+            pending_echo: false,
+            rejected_votes: RejectedVotes::default(),
+            latest_evaluated_proposal: None,
         }
     }
 
@@ -134,10 +281,79 @@ where
             .map(|store| &*store as &dyn RecordStore<Context>)
     }
 
+    /// Return the epochs for which this node has a record store, in increasing order.
+    pub fn known_epochs(&self) -> Vec<EpochId> {
+        let mut epochs: Vec<_> = self
+            .past_record_stores
+            .keys()
+            .cloned()
+            .chain(std::iter::once(self.epoch_id))
+            .collect();
+        epochs.sort();
+        epochs
+    }
+
+    /// Return the highest epoch and round known to this node, including past epochs.
+    pub fn global_highest_round(&self) -> (EpochId, Round) {
+        self.past_record_stores
+            .iter()
+            .map(|(epoch_id, store)| (*epoch_id, store.current_round()))
+            .chain(std::iter::once((
+                self.epoch_id,
+                self.record_store.current_round(),
+            )))
+            .max()
+            .expect("The current record store always exists")
+    }
+
+    /// Return the number of replayed network records dropped by this node, in all epochs.
+    pub fn replayed_record_count(&self) -> usize {
+        self.past_record_stores
+            .values()
+            .chain(std::iter::once(&self.record_store))
+            .map(|store| store.replayed_record_count())
+            .sum()
+    }
+
+    /// Summarize the current consensus view of this node.
+    pub fn snapshot(&self) -> NodeSnapshot<Context::Author> {
+        let record_store = self.record_store();
+        NodeSnapshot {
+            epoch_id: self.epoch_id,
+            current_round: record_store.current_round(),
+            highest_quorum_certificate_round: record_store.highest_quorum_certificate_round(),
+            highest_committed_round: record_store.highest_committed_round(),
+            active_leader: self.pacemaker.active_leader(),
+            pending_vote_count: record_store.current_vote_count(),
+            timeout_weight: record_store.current_timeouts_weight(),
+        }
+    }
+
+    /// Export the records of the current epoch as a graph in DOT format.
+    pub fn export_dot(&self) -> String {
+        self.record_store.to_dot()
+    }
+
+    /// Rewind the records of the current epoch to the given round, e.g. to test the behavior
+    /// of nodes after an operator error. Fails if the round is final.
+    #[cfg(feature = "testing")]
+    pub fn rewind_to(&mut self, round: Round) -> anyhow::Result<()> {
+        self.record_store.rewind_to(round)
+    }
+
+    pub(crate) fn config(&self) -> &NodeConfig {
+        &self.config
+    }
+
     pub(crate) fn pacemaker(&self) -> &dyn Pacemaker<Context> {
         &self.pacemaker
     }
 
+    /// Moving average of round latencies, if round durations adapt to it.
+    pub fn adaptive_timeout(&self) -> Option<&AdaptiveTimeout> {
+        self.pacemaker.adaptive_timeout()
+    }
+
     pub(crate) fn update_tracker(&mut self, clock: NodeTime) {
         // Ignore actions
         self.tracker.update_tracker(
@@ -165,6 +381,111 @@ where
             );
         }
     }
+
+    /// Record a new data-sync request to the given peer and return whether it should be sent.
+    /// Once too many consecutive requests made no progress, which suggests that the peer and us
+    /// have incompatible views, requests are delayed by an exponential backoff starting at
+    /// `target_commit_interval`.
+    pub(crate) fn register_sync_attempt(&mut self, peer: Context::Author, clock: NodeTime) -> bool {
+        let progress = (
+            self.epoch_id,
+            self.record_store.highest_quorum_certificate_round(),
+        );
+        let attempts = self.sync_attempts.entry(peer).or_insert(SyncAttempts {
+            count: 0,
+            progress,
+            next_attempt: clock,
+        });
+        if attempts.progress != progress {
+            attempts.count = 0;
+            attempts.progress = progress;
+            attempts.next_attempt = clock;
+        }
+        if clock < attempts.next_attempt {
+            return false;
+        }
+        attempts.count += 1;
+        if attempts.count >= MAX_SYNC_ATTEMPTS_WITHOUT_PROGRESS {
+            if attempts.count == MAX_SYNC_ATTEMPTS_WITHOUT_PROGRESS {
+                warn!(
+                    "Backing off data-sync requests to {:?} after {} attempts without progress",
+                    peer, attempts.count
+                );
+            }
+            let exponent = min(
+                attempts.count - MAX_SYNC_ATTEMPTS_WITHOUT_PROGRESS,
+                MAX_SYNC_BACKOFF_EXPONENT,
+            );
+            let delay = Duration(self.config.target_commit_interval.0 << exponent);
+            attempts.next_attempt = clock + delay;
+        }
+        true
+    }
+
+    /// Forget about previous data-sync requests to the given peer.
+    pub(crate) fn reset_sync_attempts(&mut self, peer: Context::Author) {
+        self.sync_attempts.remove(&peer);
+    }
+
+    /// Number of proposals that we refused to vote for so far, by reason.
+    pub fn rejected_votes(&self) -> RejectedVotes {
+        self.rejected_votes
+    }
+
+    /// Count and log a refused vote.
+    fn reject_vote(
+        &mut self,
+        author: Context::Author,
+        block_hash: BlockHash<Context::HashValue>,
+        block_round: Round,
+        due_to_lock: bool,
+    ) {
+        self.latest_evaluated_proposal = Some(block_hash);
+        if due_to_lock {
+            self.rejected_votes.locked_round += 1;
+            debug!(
+                "{:?} Rejected vote for block {:?} at round {:?} due to lock: previous round {:?} is below locked round {:?}",
+                author,
+                block_hash,
+                block_round,
+                self.record_store.previous_round(block_hash),
+                self.locked_round
+            );
+        } else {
+            self.rejected_votes.already_voted += 1;
+            debug!(
+                "{:?} Rejected vote for block {:?} at round {:?} due to already voted at round {:?}",
+                author, block_hash, block_round, self.latest_voted_round
+            );
+        }
+    }
+
+    /// Ask the next update to broadcast our data so that a newly learned QC is relayed to
+    /// other nodes. Each QC is echoed at most once to prevent storms.
+    pub(crate) fn schedule_echo(
+        &mut self,
+        author: Context::Author,
+        hash: QuorumCertificateHash<Context::HashValue>,
+    ) {
+        if self.config.echo_quorum_certificates && self.latest_echo != Some(hash) {
+            debug!("{:?} Echoing QC {:?}", author, hash);
+            self.latest_echo = Some(hash);
+            self.pending_echo = true;
+        }
+    }
+}
+
+/// Write the DOT export of the i-th node to the file `node_{i}.dot` of the given directory.
+pub fn dump_dag<'a, Context: SmrContext + 'a>(
+    nodes: impl IntoIterator<Item = &'a NodeState<Context>>,
+    directory: &std::path::Path,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    for (index, node) in nodes.into_iter().enumerate() {
+        let path = directory.join(format!("node_{}.dot", index));
+        std::fs::write(path, node.export_dot())?;
+    }
+    Ok(())
 }
 
 #[cfg(feature = "simulator")]
@@ -172,6 +493,83 @@ impl<Context: SmrContext> bft_lib::simulator::ActiveRound for NodeState<Context>
     fn active_round(&self) -> Round {
         self.pacemaker.active_round()
     }
+
+    fn highest_committed_round(&self) -> Round {
+        self.record_store.highest_committed_round()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ActiveEpoch for NodeState<Context> {
+    fn active_epoch(&self) -> EpochId {
+        self.pacemaker.active_epoch()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ActiveLeader for NodeState<Context> {
+    type Author = Context::Author;
+
+    fn active_leader(&self) -> Option<Context::Author> {
+        self.pacemaker.active_leader()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::CommitGap for NodeState<Context> {
+    fn commit_gap(&self) -> usize {
+        self.record_store.commit_gap()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ViewChangeLatencies for NodeState<Context> {
+    fn view_change_latencies(&self) -> Vec<(Round, Duration)> {
+        self.record_store.view_change_latencies()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::CommittedLeaders for NodeState<Context> {
+    type Author = Context::Author;
+
+    fn committed_leaders(&self) -> Vec<(EpochId, Round, Context::Author)> {
+        self.past_record_stores
+            .iter()
+            .map(|(epoch_id, store)| (*epoch_id, store))
+            .chain(std::iter::once((self.epoch_id, &self.record_store)))
+            .flat_map(|(epoch_id, store)| {
+                store
+                    .committed_leaders()
+                    .into_iter()
+                    .map(move |(round, author)| (epoch_id, round, author))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::KnownRecords for NodeState<Context> {
+    type RecordHash = Context::HashValue;
+
+    fn known_records(&self) -> std::collections::HashSet<Context::HashValue> {
+        self.past_record_stores
+            .values()
+            .chain(std::iter::once(&self.record_store))
+            .flat_map(|store| store.known_hashes())
+            .collect()
+    }
+}
+
+#[cfg(feature = "simulator")]
+impl<Context: SmrContext> bft_lib::simulator::ApproximateMemory for NodeState<Context> {
+    fn approximate_memory(&self) -> usize {
+        self.past_record_stores
+            .values()
+            .chain(std::iter::once(&self.record_store))
+            .map(|store| store.approximate_memory())
+            .sum()
+    }
 }
 
 // -- BEGIN FILE process_pacemaker_actions --
@@ -242,6 +640,18 @@ where
         context: &mut Context,
         clock: NodeTime,
     ) -> NodeUpdateActions<Context> {
+        // Never let the clock go backward (e.g. after a step of the system clock) since the
+        // computations of durations below assume a monotonic clock.
+        let clock = if clock < self.last_seen_clock {
+            warn!(
+                "Clock moved backward from {:?} to {:?}: using {:?} instead",
+                self.last_seen_clock, clock, self.last_seen_clock
+            );
+            self.last_seen_clock
+        } else {
+            clock
+        };
+        self.last_seen_clock = clock;
         // Update pacemaker state and process pacemaker actions (e.g., creating a timeout, proposing
         // a block).
         let pacemaker_actions = self.pacemaker.update_pacemaker(
@@ -249,17 +659,28 @@ where
             self.epoch_id,
             &self.record_store,
             self.latest_query_all_time,
+            context.has_pending_commands(),
             clock,
         );
         let mut actions = self.process_pacemaker_actions(pacemaker_actions, clock, context);
+        let pacemaker_should_broadcast = actions.should_broadcast;
+        let pacemaker_should_query_all = actions.should_query_all;
         // Vote on a valid proposal block designated by the pacemaker, if any.
         if let Some((block_hash, block_round, proposer)) =
             self.record_store.proposed_block(&self.pacemaker)
         {
-            // Enforce voting constraints.
-            if block_round > self.latest_voted_round
-                && self.record_store.previous_round(block_hash) >= self.locked_round
+            // Enforce voting constraints. Observers do not vote. Since the latest voted round
+            // and the locked round never decrease, a proposal is only evaluated once.
+            if self.record_store.voting_rights(context.author()) == 0
+                || self.latest_evaluated_proposal == Some(block_hash)
             {
+                // Nothing to do.
+            } else if block_round <= self.latest_voted_round {
+                self.reject_vote(context.author(), block_hash, block_round, false);
+            } else if self.record_store.previous_round(block_hash) < self.locked_round {
+                self.reject_vote(context.author(), block_hash, block_round, true);
+            } else {
+                self.latest_evaluated_proposal = Some(block_hash);
                 // Update the latest voted round.
                 self.latest_voted_round = block_round;
                 // Update the locked round.
@@ -269,20 +690,32 @@ where
                 );
                 // Try to execute the command contained the a block and create a vote.
                 if self.record_store.create_vote(context, block_hash) {
-                    // Ask to notify and send our vote to the author of the block.
-                    actions.should_send = vec![proposer];
+                    // Ask to notify and send our vote to the author of the block, in addition
+                    // to the nodes requested by the pacemaker (if any).
+                    actions.should_send.push(proposer);
+                    actions.should_send.sort();
+                    actions.should_send.dedup();
                 }
             }
         }
         // Check if our last proposal has reached a quorum of votes and create a QC.
-        if self.record_store.check_for_new_quorum_certificate(context) {
+        let has_new_quorum_certificate =
+            self.record_store.check_for_new_quorum_certificate(context);
+        if has_new_quorum_certificate {
             // Broadcast the QC to finish our work as a leader.
             actions.should_broadcast = true;
             // Schedule a new run now to process the new QC.
             actions.next_scheduled_update = clock;
         }
+        // Relay a higher QC learned from a notification, if requested.
+        if self.pending_echo {
+            self.pending_echo = false;
+            actions.should_broadcast = true;
+        }
         // Check for new commits and verify if we should start a new epoch.
         self.process_commits(context);
+        // Time the round changes observed so far.
+        self.record_store.update_clock(clock);
         // Update the commit tracker and ask that we query all nodes if needed.
         let tracker_actions = self.tracker.update_tracker(
             self.latest_query_all_time,
@@ -299,6 +732,16 @@ where
         if actions.should_query_all {
             self.latest_query_all_time = clock;
         }
+        trace!(
+            "{:?}{:?} Update actions: pacemaker (broadcast: {}, query_all: {}), new QC (broadcast: {}), tracker (query_all: {}) => {:?}",
+            context.author(),
+            clock,
+            pacemaker_should_broadcast,
+            pacemaker_should_query_all,
+            has_new_quorum_certificate,
+            tracker_actions.should_query_all,
+            actions
+        );
         // Return desired actions to main handler.
         actions
     }
@@ -312,41 +755,87 @@ where
 {
     pub(crate) fn process_commits(&mut self, context: &mut Context) {
         // For all commits that have not been processed yet, according to the commit tracker..
+        let mut committed_states = Vec::new();
+        let mut new_epoch_id = None;
         for (round, state) in self
             .record_store
             .committed_states_after(self.tracker.highest_committed_round)
         {
-            // .. deliver the committed state to the SMR layer, together with a commit certificate,
-            // if any.
-            if round == self.record_store.highest_committed_round() {
-                match self.record_store.highest_commit_certificate() {
-                    None => context.commit(&state, None),
-                    Some(x) => context.commit(&state, Some(&x.value)),
-                };
-            } else {
-                context.commit(&state, None);
-            };
-            // .. check if the current epoch just ended. If it did..
-            let new_epoch_id = context.read_epoch_id(&state);
-            if new_epoch_id > self.epoch_id {
-                // .. create a new record store and switch to the new epoch.
-                let new_record_store = RecordStoreState::new(
-                    Self::initial_hash(context, new_epoch_id),
-                    state.clone(),
-                    new_epoch_id,
-                    context.configuration(&state),
-                );
-                let old_record_store = std::mem::replace(&mut self.record_store, new_record_store);
-                self.past_record_stores
-                    .insert(self.epoch_id, old_record_store);
-                self.epoch_id = new_epoch_id;
-                // .. initialize voting constraints.
-                self.latest_voted_round = Round(0);
-                self.locked_round = Round(0);
-                // .. stop delivering commits after an epoch change.
+            // .. check if the current epoch ends with this state. If it does, stop delivering
+            // commits after it.
+            let epoch_id = context.read_epoch_id(&state);
+            committed_states.push((round, state));
+            if epoch_id > self.epoch_id {
+                new_epoch_id = Some(epoch_id);
                 break;
             }
         }
+        // .. deliver the committed states to the SMR layer, together with a commit certificate,
+        // if any.
+        let commit_certificate = match committed_states.last() {
+            Some((round, _)) if *round == self.record_store.highest_committed_round() => {
+                self.record_store.highest_commit_certificate()
+            }
+            _ => None,
+        };
+        if let Some(qc) = commit_certificate {
+            // .. trace the 3-chain that satisfied the commit rule.
+            debug!(
+                "{:?} Commit certificate {:?} at round {:?} commits round {:?} (3-chain rounds: {:?}, {:?}, {:?})",
+                context.author(),
+                context.hash(&qc.value),
+                qc.value.round,
+                self.record_store.highest_committed_round(),
+                self.record_store.highest_committed_round(),
+                self.record_store.previous_round(qc.value.certified_block_hash),
+                qc.value.round
+            );
+        }
+        let commit_certificate =
+            commit_certificate.map(|x| &x.value as &dyn CommitCertificate<_, _>);
+        match self.config.commit_delivery {
+            CommitDelivery::Individual => {
+                if let Some(((_, last_state), states)) = committed_states.split_last() {
+                    for (_, state) in states {
+                        context.commit(state, None);
+                    }
+                    context.commit(last_state, commit_certificate);
+                }
+            }
+            CommitDelivery::Batched => {
+                if !committed_states.is_empty() {
+                    context.commit_batch(&committed_states, commit_certificate);
+                }
+            }
+        }
+        // .. allow the delivered records to be pruned.
+        if let Some((round, _)) = committed_states.last() {
+            self.record_store.set_delivered_round(*round);
+        }
+        // If the current epoch just ended..
+        if let (Some(new_epoch_id), Some((_, state))) = (new_epoch_id, committed_states.last()) {
+            // .. create a new record store and switch to the new epoch.
+            let new_record_store = RecordStoreState::new(
+                Self::initial_hash(context, new_epoch_id),
+                state.clone(),
+                new_epoch_id,
+                context.configuration(state),
+                &self.config,
+            );
+            if !new_record_store.follows_epoch(&self.record_store, context) {
+                warn!(
+                    "Initial state of epoch {:?} does not descend from the last commit of epoch {:?}",
+                    new_epoch_id, self.epoch_id
+                );
+            }
+            let old_record_store = std::mem::replace(&mut self.record_store, new_record_store);
+            self.past_record_stores
+                .insert(self.epoch_id, old_record_store);
+            self.epoch_id = new_epoch_id;
+            // .. initialize voting constraints.
+            self.latest_voted_round = Round(0);
+            self.locked_round = Round(0);
+        }
     }
 }
 // -- END FILE --
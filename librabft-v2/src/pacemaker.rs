@@ -4,12 +4,16 @@
 use crate::{record::QuorumCertificateHash, record_store::*};
 use bft_lib::{
     base_types::{Duration, EpochId, NodeTime, Round},
+    configuration::EpochConfiguration,
     smr_context::SmrContext,
 };
 use serde::{Deserialize, Serialize};
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    hash::Hash,
+};
 
-#[cfg(test)]
+#[cfg(all(test, feature = "simulator"))]
 #[path = "unit_tests/pacemaker_tests.rs"]
 mod pacemaker_tests;
 
@@ -44,6 +48,8 @@ pub(crate) trait Pacemaker<Context: SmrContext> {
         record_store: &dyn RecordStore<Context>,
         // Local time of the latest query-all by us.
         latest_query_all: NodeTime,
+        // Whether commands are waiting to be proposed.
+        has_pending_commands: bool,
         // Current local time.
         clock: NodeTime,
     ) -> PacemakerUpdateActions<Context>;
@@ -74,9 +80,189 @@ pub(crate) struct PacemakerState<Context: SmrContext> {
     gamma: f64,
     /// Coefficient to control the frequency of query-all actions.
     lambda: f64,
+    /// Whether to propose blocks when no commands are pending.
+    propose_when_empty: ProposeWhenEmpty,
+    /// Moving average of the observed round latencies, if round durations adapt to it.
+    adaptive_timeout: Option<AdaptiveTimeout>,
 }
 // -- END FILE --
 
+/// Exponential moving average (EMA) of the time taken by recent rounds to reach a QC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AdaptiveTimeout {
+    /// Weight of the latest observation in the average, between 0 and 1.
+    weight: f64,
+    /// Average latency of a round, if any was observed.
+    average_latency: Option<f64>,
+    /// Round of the highest QC and time at which it was first observed.
+    latest_quorum_certificate: Option<(Round, NodeTime)>,
+}
+
+/// Ratio between the maximal duration of a round and the average round latency.
+const ADAPTIVE_TIMEOUT_FACTOR: f64 = 2.0;
+
+/// Policy of a leader when no commands are pending.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposeWhenEmpty {
+    /// Always propose a block.
+    Always,
+    /// Never propose a block.
+    Never,
+    /// Propose a block once the given duration has passed since the start of the round.
+    AfterTimeout(Duration),
+}
+
+// Deriving `Default` for enums requires a more recent compiler.
+#[allow(clippy::derivable_impls)]
+impl Default for ProposeWhenEmpty {
+    fn default() -> Self {
+        ProposeWhenEmpty::Always
+    }
+}
+
+/// Policy to elect the leader of each round.
+pub trait LeaderElection<Author: Hash> {
+    fn leader(&self, round: Round, configuration: &EpochConfiguration<Author>) -> Author;
+}
+
+/// Elect leaders by hashing the round, with chances proportional to voting rights.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HashElection;
+
+/// Same as `HashElection`, except that leaders are picked from the sorted content of the
+/// committee (see `EpochConfiguration::pick_author_by_content`), hence do not depend on the
+/// order of the authors in the configuration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ContentAddressedElection;
+
+/// Elect leaders in turn, following the order of the authors in the configuration. Each
+/// author leads as many consecutive rounds as its voting rights.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RoundRobinElection;
+
+/// Elect leaders by hashing the round together with a seed derived from the QC extended by
+/// the proposal. Leaders cannot be predicted (nor ground) before this QC is formed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VrfElection {
+    pub seed: u64,
+}
+
+/// Leader-election policy of a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeaderElectionPolicy {
+    /// See `HashElection`.
+    Hash,
+    /// See `ContentAddressedElection`.
+    ContentAddressed,
+    /// See `RoundRobinElection`.
+    RoundRobin,
+    /// See `VrfElection`.
+    Vrf,
+}
+
+// Deriving `Default` for enums requires a more recent compiler.
+#[allow(clippy::derivable_impls)]
+impl Default for LeaderElectionPolicy {
+    fn default() -> Self {
+        LeaderElectionPolicy::Hash
+    }
+}
+
+impl<Author: Hash + Eq + Clone> LeaderElection<Author> for HashElection {
+    fn leader(&self, round: Round, configuration: &EpochConfiguration<Author>) -> Author {
+        use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        round.hash(&mut hasher);
+        configuration.pick_author(hasher.finish())
+    }
+}
+
+impl<Author: Hash + Eq + Clone + Ord> LeaderElection<Author> for ContentAddressedElection {
+    fn leader(&self, round: Round, configuration: &EpochConfiguration<Author>) -> Author {
+        use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        round.hash(&mut hasher);
+        configuration.pick_author_by_content(hasher.finish())
+    }
+}
+
+impl VrfElection {
+    /// Seed the election with the hash of the QC extended by the proposal.
+    pub(crate) fn new<V: Hash>(previous_quorum_certificate_hash: QuorumCertificateHash<V>) -> Self {
+        use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        previous_quorum_certificate_hash.hash(&mut hasher);
+        VrfElection {
+            seed: hasher.finish(),
+        }
+    }
+}
+
+impl<Author: Hash + Eq + Clone> LeaderElection<Author> for VrfElection {
+    fn leader(&self, round: Round, configuration: &EpochConfiguration<Author>) -> Author {
+        use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        round.hash(&mut hasher);
+        configuration.pick_author(hasher.finish())
+    }
+}
+
+impl<Author: Hash + Eq + Clone> LeaderElection<Author> for RoundRobinElection {
+    fn leader(&self, round: Round, configuration: &EpochConfiguration<Author>) -> Author {
+        configuration.author_at(round.0)
+    }
+}
+
+impl AdaptiveTimeout {
+    pub fn new(weight: f64) -> Self {
+        assert!(
+            weight > 0.0 && weight <= 1.0,
+            "The weight of observations must be in (0, 1]"
+        );
+        AdaptiveTimeout {
+            weight,
+            average_latency: None,
+            latest_quorum_certificate: None,
+        }
+    }
+
+    /// Record that the highest QC is at `round` at time `clock`. When the round advances, the
+    /// elapsed time is averaged over the rounds that were skipped.
+    pub fn observe(&mut self, round: Round, clock: NodeTime) {
+        match self.latest_quorum_certificate {
+            Some((latest_round, latest_time)) if round > latest_round => {
+                let latency = (clock.0 - latest_time.0) as f64 / (round.0 - latest_round.0) as f64;
+                self.average_latency = Some(match self.average_latency {
+                    Some(average) => self.weight * latency + (1.0 - self.weight) * average,
+                    None => latency,
+                });
+                self.latest_quorum_certificate = Some((round, clock));
+            }
+            // Rounds restart in new epochs.
+            Some((latest_round, _)) if round >= latest_round => (),
+            _ => self.latest_quorum_certificate = Some((round, clock)),
+        }
+    }
+
+    /// Average latency of a round, if any was observed.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.average_latency.map(|average| Duration(average as i64))
+    }
+
+    /// Scale the base timeout `delta` so that it covers the average round latency.
+    pub fn base_timeout(&self, delta: Duration) -> Duration {
+        match self.average_latency {
+            Some(average) => max(delta, Duration((ADAPTIVE_TIMEOUT_FACTOR * average) as i64)),
+            None => delta,
+        }
+    }
+}
+
 impl<Context: SmrContext> PacemakerState<Context> {
     pub(crate) fn new(
         epoch_id: EpochId,
@@ -84,6 +270,7 @@ impl<Context: SmrContext> PacemakerState<Context> {
         delta: Duration,
         gamma: f64,
         lambda: f64,
+        propose_when_empty: ProposeWhenEmpty,
     ) -> Self {
         PacemakerState {
             active_epoch: epoch_id,
@@ -94,18 +281,44 @@ impl<Context: SmrContext> PacemakerState<Context> {
             delta,
             gamma,
             lambda,
+            propose_when_empty,
+            adaptive_timeout: None,
         }
     }
 
+    /// Scale the durations of rounds with the moving average of observed round latencies,
+    /// where `weight` is the weight of the latest round in the average.
+    pub(crate) fn with_adaptive_timeout(mut self, weight: Option<f64>) -> Self {
+        self.adaptive_timeout = weight.map(AdaptiveTimeout::new);
+        self
+    }
+
+    pub(crate) fn adaptive_timeout(&self) -> Option<&AdaptiveTimeout> {
+        self.adaptive_timeout.as_ref()
+    }
+
+    fn may_propose(&self, has_pending_commands: bool, clock: NodeTime) -> bool {
+        has_pending_commands
+            || match self.propose_when_empty {
+                ProposeWhenEmpty::Always => true,
+                ProposeWhenEmpty::Never => false,
+                ProposeWhenEmpty::AfterTimeout(delay) => {
+                    clock >= self.active_round_start_time + delay
+                }
+            }
+    }
+
     pub(crate) fn leader(record_store: &dyn RecordStore<Context>, round: Round) -> Context::Author {
-        use std::{
-            collections::hash_map::DefaultHasher,
-            hash::{Hash, Hasher},
-        };
+        Self::leader_with_seed(record_store, round)
+    }
 
-        let mut hasher = DefaultHasher::new();
-        round.hash(&mut hasher);
-        record_store.pick_author(hasher.finish())
+    /// Leader of `round` for a proposal extending the highest QC of `record_store`. With
+    /// `LeaderElectionPolicy::Vrf`, the hash of this QC is mixed into the seed of the election.
+    pub(crate) fn leader_with_seed(
+        record_store: &dyn RecordStore<Context>,
+        round: Round,
+    ) -> Context::Author {
+        record_store.leader(round, record_store.highest_quorum_certificate_hash())
     }
 
     fn duration(&self, record_store: &dyn RecordStore<Context>, round: Round) -> Duration {
@@ -120,7 +333,19 @@ impl<Context: SmrContext> PacemakerState<Context> {
             "Active round is higher than any QC round."
         );
         let n = round.0 - highest_commit_certificate_round.0;
-        Duration(((self.delta.0 as f64) * (n as f64).powf(self.gamma)) as i64)
+        let delta = match &self.adaptive_timeout {
+            Some(adaptive_timeout) => adaptive_timeout.base_timeout(self.delta),
+            None => self.delta,
+        };
+        Duration(((delta.0 as f64) * (n as f64).powf(self.gamma)) as i64)
+    }
+}
+
+impl<Context: SmrContext> PacemakerUpdateActions<Context> {
+    /// Whether a leader proposing a block is also scheduled to run again right away, so that it
+    /// votes on its own proposal without delay.
+    pub(crate) fn schedules_vote_on_proposal(&self, clock: NodeTime) -> bool {
+        self.should_propose_block.is_none() || self.next_scheduled_update == clock
     }
 }
 
@@ -145,10 +370,15 @@ impl<Context: SmrContext> Pacemaker<Context> for PacemakerState<Context> {
         epoch_id: EpochId,
         record_store: &dyn RecordStore<Context>,
         latest_query_all_time: NodeTime,
+        has_pending_commands: bool,
         clock: NodeTime,
     ) -> PacemakerUpdateActions<Context> {
         // Initialize actions with default values.
         let mut actions = PacemakerUpdateActions::default();
+        // Track the latency of rounds, if needed.
+        if let Some(adaptive_timeout) = &mut self.adaptive_timeout {
+            adaptive_timeout.observe(record_store.highest_quorum_certificate_round(), clock);
+        }
         // Compute the active round from the current record store.
         let active_round = max(
             record_store.highest_quorum_certificate_round(),
@@ -174,13 +404,20 @@ impl<Context: SmrContext> Pacemaker<Context> for PacemakerState<Context> {
         }
         // If we are the leader and have not proposed yet..
         if self.active_leader == Some(local_author) && record_store.proposed_block(&*self) == None {
-            // .. propose a block on top of the highest QC that we know.
-            actions.should_propose_block = Some(record_store.highest_quorum_certificate_hash());
-            actions.should_broadcast = true;
-            // .. force an immediate update to vote on our own proposal.
-            actions.next_scheduled_update = clock;
+            if self.may_propose(has_pending_commands, clock) {
+                // .. propose a block on top of the highest QC that we know.
+                actions.should_propose_block = Some(record_store.highest_quorum_certificate_hash());
+                actions.should_broadcast = true;
+                // .. force an immediate update to vote on our own proposal.
+                actions.next_scheduled_update = clock;
+            } else if let ProposeWhenEmpty::AfterTimeout(delay) = self.propose_when_empty {
+                // .. or wait until we are allowed to propose an empty block.
+                actions.next_scheduled_update = self.active_round_start_time + delay;
+            }
         }
-        if !record_store.has_timeout(local_author, active_round) {
+        // Observers never create timeouts: they only follow the progress of the others.
+        let is_observer = record_store.voting_rights(local_author) == 0;
+        if !is_observer && !record_store.has_timeout(local_author, active_round) {
             let timeout_deadline = self.active_round_start_time + self.active_round_duration;
             // If we have not created a timeout yet, check if the round has passed its maximal
             // duration. Then, either broadcast a new timeout now, or schedule an update
@@ -202,6 +439,13 @@ impl<Context: SmrContext> Pacemaker<Context> for PacemakerState<Context> {
             }
             actions.next_scheduled_update = min(actions.next_scheduled_update, query_all_deadline);
         }
+        // Simulations check this invariant in release builds too.
+        if cfg!(any(debug_assertions, feature = "simulator")) {
+            assert!(
+                actions.schedules_vote_on_proposal(clock),
+                "Leaders should vote on their proposals right away."
+            );
+        }
         // Return all computed actions.
         actions
     }
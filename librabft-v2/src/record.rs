@@ -3,7 +3,7 @@
 
 use bft_lib::{
     base_types::*,
-    smr_context::{Authored, BcsSignable, CryptographicModule, SignedValue, SmrContext},
+    smr_context::{Authored, BcsSignable, CryptographicModule, Signable, SignedValue, SmrContext},
 };
 use serde::{Deserialize, Serialize};
 
@@ -42,7 +42,7 @@ pub(crate) type QuorumCertificate<C> =
 
 pub(crate) type Timeout<C> = SignedValue<Timeout_<C>, <C as CryptographicModule>::Signature>;
 
-#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
 pub(crate) struct BlockHash<V>(pub V);
 
 #[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Serialize, Deserialize)]
@@ -111,21 +111,62 @@ pub(crate) struct Timeout_<Context: SmrContext> {
 }
 // -- END FILE --
 
-impl<Context: SmrContext> bft_lib::smr_context::CommitCertificate<Context::State>
+impl<Context: SmrContext> Vote_<Context> {
+    /// Reconstruct the vote of `author` from the shared fields of a QC.
+    pub(crate) fn from_quorum_certificate(
+        quorum_certificate: &QuorumCertificate_<Context>,
+        author: Context::Author,
+    ) -> Self {
+        Vote_ {
+            epoch_id: quorum_certificate.epoch_id,
+            round: quorum_certificate.round,
+            certified_block_hash: quorum_certificate.certified_block_hash,
+            state: quorum_certificate.state.clone(),
+            committed_state: quorum_certificate.committed_state.clone(),
+            author,
+        }
+    }
+
+    /// Bytes that are hashed and signed for this vote, following the conventions of
+    /// `BcsSignable`. Votes reconstructed from QCs must produce the same bytes as the original
+    /// votes.
+    pub(crate) fn canonical_signing_bytes(&self) -> Vec<u8> {
+        let name = serde_name::trace_name::<Self>().expect("Self must be a struct or an enum");
+        let mut bytes = format!("{}::", name).into_bytes();
+        bcs::serialize_into(&mut bytes, self)
+            .expect("Serialization should not fail for consensus messages");
+        bytes
+    }
+}
+
+impl<Context: SmrContext> bft_lib::smr_context::CommitCertificate<Context::Author, Context::State>
     for QuorumCertificate_<Context>
 {
     fn committed_state(&self) -> Option<&Context::State> {
         self.committed_state.as_ref()
     }
+
+    fn signers(&self) -> Vec<Context::Author> {
+        self.votes.iter().map(|(author, _)| *author).collect()
+    }
 }
 
 // Requirements for SignedValue. To avoid computing hashes in the
 // wrong way, `Record` should not implement `BcsSignable`.
 impl<Context: SmrContext> BcsSignable for Block_<Context> {}
-impl<Context: SmrContext> BcsSignable for Vote_<Context> {}
 impl<Context: SmrContext> BcsSignable for QuorumCertificate_<Context> {}
 impl<Context: SmrContext> BcsSignable for Timeout_<Context> {}
 
+// Votes are hashed through their canonical bytes, both when they are created and when they are
+// reconstructed from QCs.
+impl<Context: SmrContext, Hasher: std::io::Write> Signable<Hasher> for Vote_<Context> {
+    fn write(&self, hasher: &mut Hasher) {
+        hasher
+            .write_all(&self.canonical_signing_bytes())
+            .expect("Hasher should not fail");
+    }
+}
+
 impl<Context: SmrContext> Authored<Context::Author> for Block_<Context> {
     fn author(&self) -> Context::Author {
         self.author
@@ -149,3 +190,17 @@ impl<Context: SmrContext> Authored<Context::Author> for Timeout_<Context> {
         self.author
     }
 }
+
+/// Sign a new record of this node. Records are created while updating the node, which is
+/// synchronous, hence we block until the signature is ready.
+pub(crate) fn sign_record<Context, T>(
+    context: &mut Context,
+    value: T,
+) -> SignedValue<T, Context::Signature>
+where
+    Context: SmrContext,
+    T: Authored<Context::Author> + Signable<Context::Hasher>,
+{
+    futures::executor::block_on(SignedValue::make(context, value))
+        .expect("Signing records should not fail")
+}
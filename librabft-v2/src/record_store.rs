@@ -2,19 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    pacemaker::{Pacemaker, PacemakerState},
+    node::NodeConfig,
+    pacemaker::{
+        ContentAddressedElection, HashElection, LeaderElection, LeaderElectionPolicy, Pacemaker,
+        RoundRobinElection, VrfElection,
+    },
     record::*,
 };
 use anyhow::{bail, ensure};
-use bft_lib::{
-    base_types::*,
-    configuration::EpochConfiguration,
-    smr_context::{SignedValue, SmrContext},
-};
+use bft_lib::{base_types::*, configuration::EpochConfiguration, smr_context::SmrContext};
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeSet, HashMap},
+    cmp::{max, min},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Debug,
 };
 
@@ -22,6 +23,9 @@ use std::{
 #[path = "unit_tests/record_store_tests.rs"]
 mod record_store_tests;
 
+/// Maximal number of committed rounds for which the author of the block is remembered.
+pub(crate) const MAX_COMMITTED_LEADERS: usize = 1_000;
+
 // -- BEGIN FILE record_store --
 pub(crate) trait RecordStore<Context: SmrContext> {
     /// Return the hash of a QC at the highest round, or the initial hash.
@@ -32,17 +36,44 @@ pub(crate) trait RecordStore<Context: SmrContext> {
     fn highest_quorum_certificate(&self) -> Option<&QuorumCertificate<Context>>;
     /// Query the round of the highest TC.
     fn highest_timeout_certificate_round(&self) -> Round;
+    /// Query the highest QC referenced by the timeouts of the highest TC, and its block.
+    fn highest_timeout_certificate_quorum_certificate(
+        &self,
+    ) -> Option<(&Block<Context>, &QuorumCertificate<Context>)>;
     /// Query the round of the highest commit.
     fn highest_committed_round(&self) -> Round;
+    /// Number of rounds between the highest QC and the highest commit. A gap that keeps
+    /// growing means that the commit rule does not fire.
+    #[cfg(feature = "simulator")]
+    fn commit_gap(&self) -> usize;
     /// Query the last QC of the highest commit rule.
     fn highest_commit_certificate(&self) -> Option<&QuorumCertificate<Context>>;
     /// Current round as seen by the record store.
     fn current_round(&self) -> Round;
+    /// Number of votes received at the current round.
+    fn current_vote_count(&self) -> usize;
+    /// Total voting rights of the authors of the timeouts received at the current round.
+    fn current_timeouts_weight(&self) -> usize;
+    /// Number of network records dropped because they were already inserted.
+    fn replayed_record_count(&self) -> usize;
+
+    /// Record the current time, used to measure the latency of view changes.
+    fn update_clock(&mut self, clock: NodeTime);
+
+    /// For each round abandoned after a TC, the time between entering the round (as first
+    /// observed by `update_clock`) and forming the TC.
+    #[cfg(feature = "simulator")]
+    fn view_change_latencies(&self) -> Vec<(Round, Duration)>;
 
     /// Iterate on the committed blocks starting after the round `after_round` and ending with the
-    /// highest commit known so far.
+    /// highest commit known so far. The result is empty if nothing was committed in this epoch.
     fn committed_states_after(&self, after_round: Round) -> Vec<(Round, Context::State)>;
 
+    /// Round and author of the latest blocks committed in this epoch (at most
+    /// `MAX_COMMITTED_LEADERS`), in increasing rounds.
+    #[cfg(feature = "simulator")]
+    fn committed_leaders(&self) -> Vec<(Round, Context::Author)>;
+
     /// Access the block proposed by the leader chosen by the Pacemaker (if any).
     fn proposed_block(
         &self,
@@ -60,6 +91,17 @@ pub(crate) trait RecordStore<Context: SmrContext> {
         previous_qc_hash: QuorumCertificateHash<Context::HashValue>,
         clock: NodeTime,
     );
+    /// Report the command, the previous QC hash, and the round of the block that
+    /// `propose_block` would create on top of the highest QC, without side effects.
+    #[cfg(feature = "simulator")]
+    fn preview_proposal(
+        &self,
+        context: &Context,
+    ) -> Option<(
+        Context::Command,
+        QuorumCertificateHash<Context::HashValue>,
+        Round,
+    )>;
     /// Execute the command contained in a block and vote for the resulting state.
     /// Return false if the execution failed.
     fn create_vote(
@@ -73,15 +115,36 @@ pub(crate) trait RecordStore<Context: SmrContext> {
     /// Compute the previous round and the second previous round of a block.
     fn previous_round(&self, block_hash: BlockHash<Context::HashValue>) -> Round;
     fn second_previous_round(&self, block_hash: BlockHash<Context::HashValue>) -> Round;
-    /// Pick an author based on a seed, with chances proportional to voting rights.
-    fn pick_author(&self, seed: u64) -> Context::Author;
+    /// Leader of the given round in the current epoch for a proposal extending the given QC,
+    /// according to the leader-election policy. Only `LeaderElectionPolicy::Vrf` depends on
+    /// the QC.
+    fn leader(
+        &self,
+        round: Round,
+        previous_quorum_certificate_hash: QuorumCertificateHash<Context::HashValue>,
+    ) -> Context::Author;
+    /// Voting rights of an author in the current epoch. Authors without voting rights are
+    /// observers: they follow the protocol but never vote nor create timeouts.
+    fn voting_rights(&self, author: Context::Author) -> usize;
 
     /// APIs supporting data synchronization.
     fn timeouts(&self) -> Vec<Timeout<Context>>;
     fn current_vote(&self, local_author: Context::Author) -> Option<&Vote<Context>>;
     fn block(&self, block_hash: BlockHash<Context::HashValue>) -> Option<&Block<Context>>;
     fn known_quorum_certificate_rounds(&self) -> BTreeSet<Round>;
-    fn unknown_records(&self, known_qc_rounds: BTreeSet<Round>) -> Vec<Record<Context>>;
+    /// Hashes of the known blocks above the highest committed round.
+    fn known_block_hashes(&self) -> BTreeSet<BlockHash<Context::HashValue>>;
+    /// Records to send to a node that knows the given QC rounds. Blocks in
+    /// `known_block_hashes` are skipped.
+    fn unknown_records(
+        &self,
+        known_qc_rounds: BTreeSet<Round>,
+        known_block_hashes: BTreeSet<BlockHash<Context::HashValue>>,
+    ) -> Vec<Record<Context>>;
+    /// Return the blocks and QCs leading from the start of the epoch to the given block
+    /// (included), in insertion order. Return an empty vector if the block is unknown.
+    #[cfg(feature = "simulator")]
+    fn ancestry(&self, block_hash: BlockHash<Context::HashValue>) -> Vec<Record<Context>>;
     fn insert_network_record(&mut self, record: Record<Context>, context: &mut Context);
 }
 // -- END FILE --
@@ -111,11 +174,42 @@ pub struct RecordStoreState<Context: SmrContext> {
     /// Storage of verified timeouts at the highest TC round.
     highest_timeout_certificate: Option<Vec<Timeout<Context>>>,
     /// Storage of verified votes and timeouts at the current round.
-    current_timeouts: HashMap<Context::Author, Timeout<Context>>,
-    current_votes: HashMap<Context::Author, Vote<Context>>,
+    /// Ordered by author so that QCs and TCs are formed deterministically.
+    current_timeouts: BTreeMap<Context::Author, Timeout<Context>>,
+    current_votes: BTreeMap<Context::Author, Vote<Context>>,
+    /// Votes of the latest rounds before the current one, kept to form QCs late.
+    recent_votes: HashMap<Round, BTreeMap<Context::Author, Vote<Context>>>,
+    /// Number of rounds before the current one for which votes are kept.
+    vote_retention: usize,
     /// Computed weight values.
     current_timeouts_weight: usize,
     current_election: ElectionState<Context>,
+    /// Author and command of the latest proposal abandoned without a QC.
+    abandoned_proposal: Option<(Context::Author, Context::Command)>,
+    /// Whether leaders should re-propose the command of their abandoned proposal.
+    reproposal: bool,
+    /// Whether to refuse proposals on top of a QC other than the highest one.
+    strict_proposals: bool,
+    /// How leaders are elected.
+    leader_election: LeaderElectionPolicy,
+    /// Number of network records that were dropped because they were already inserted.
+    replayed_record_count: usize,
+    /// Time at which the current round was first observed, if any.
+    current_round_start_time: Option<NodeTime>,
+    /// Rounds abandoned after a TC since the latest clock update, with their start times.
+    pending_view_changes: Vec<(Round, Option<NodeTime>)>,
+    /// Rounds abandoned after a TC, with the times of entering the round and forming the TC.
+    view_changes: Vec<(Round, NodeTime, NodeTime)>,
+    /// Authors of the latest committed blocks, by round.
+    committed_leaders: BTreeMap<Round, Context::Author>,
+    /// Number of rounds below the highest commit for which records are kept, if records are
+    /// pruned at all.
+    pruning_depth: Option<usize>,
+    /// Highest round whose commit was delivered to the SMR layer. Committed records are only
+    /// pruned after their delivery.
+    delivered_round: Round,
+    /// Highest committed round and lowest kept round at the latest pruning.
+    latest_pruning: (Round, Round),
 }
 
 /// Counting votes for a proposed block and its execution state.
@@ -158,8 +252,16 @@ impl<'a, Context: SmrContext> Iterator for BackwardQuorumCertificateIterator<'a,
         if self.current_hash == self.store.initial_hash {
             return None;
         }
-        let qc = self.store.quorum_certificate(self.current_hash).unwrap();
-        let block = self.store.block(qc.value.certified_block_hash).unwrap();
+        // Records may only be missing after pruning.
+        let pruning = self.store.pruning_depth.is_some();
+        let qc = match self.store.quorum_certificate(self.current_hash) {
+            None if pruning => return None,
+            qc => qc.unwrap(),
+        };
+        let block = match self.store.block(qc.value.certified_block_hash) {
+            None if pruning => return None,
+            block => block.unwrap(),
+        };
         self.current_hash = block.value.previous_quorum_certificate_hash;
         Some(qc)
     }
@@ -171,6 +273,7 @@ impl<Context: SmrContext> RecordStoreState<Context> {
         initial_state: Context::State,
         epoch_id: EpochId,
         configuration: EpochConfiguration<Context::Author>,
+        config: &NodeConfig,
     ) -> Self {
         warn!("Creating new record store for epoch: {:?}, initial_hash: {:?}, initial_state: {:?}, configuration: {:?}", epoch_id, initial_hash, initial_state, configuration);
         RecordStoreState {
@@ -188,12 +291,233 @@ impl<Context: SmrContext> RecordStoreState<Context> {
             highest_committed_round: Round(0),
             highest_commit_certificate_hash: None,
             highest_timeout_certificate: None,
-            current_timeouts: HashMap::new(),
-            current_votes: HashMap::new(),
+            current_timeouts: BTreeMap::new(),
+            current_votes: BTreeMap::new(),
+            recent_votes: HashMap::new(),
+            vote_retention: config.vote_retention,
             current_timeouts_weight: 0,
             current_election: ElectionState::Ongoing {
                 ballot: HashMap::new(),
             },
+            abandoned_proposal: None,
+            reproposal: config.reproposal,
+            strict_proposals: config.strict_proposals,
+            leader_election: config.leader_election,
+            replayed_record_count: 0,
+            current_round_start_time: None,
+            pending_view_changes: Vec::new(),
+            view_changes: Vec::new(),
+            committed_leaders: BTreeMap::new(),
+            pruning_depth: config.pruning_depth,
+            delivered_round: Round(0),
+            latest_pruning: (Round(0), Round(0)),
+        }
+    }
+
+    /// Whether the initial state of this epoch is the boundary state of the epoch (as known by
+    /// the SMR layer) and was committed in the epoch of `previous`.
+    pub(crate) fn follows_epoch(
+        &self,
+        previous: &dyn RecordStore<Context>,
+        context: &Context,
+    ) -> bool {
+        match context.epoch_boundary_state(self.epoch_id) {
+            Some(boundary_state) => {
+                boundary_state == self.initial_state
+                    && previous
+                        .committed_states_after(Round(0))
+                        .iter()
+                        .any(|(_, state)| *state == boundary_state)
+            }
+            None => false,
+        }
+    }
+
+    /// Forget the blocks, QCs, TCs, votes, and timeouts above the given round. Rounds certified
+    /// by the highest commit certificate are final, hence cannot be rewound.
+    #[cfg(feature = "testing")]
+    pub(crate) fn rewind_to(&mut self, round: Round) -> anyhow::Result<()> {
+        let final_round = self
+            .highest_commit_certificate()
+            .map_or(Round(0), |qc| qc.value.round);
+        ensure!(
+            round >= final_round,
+            "Refusing to rewind to round {:?}: round {:?} is committed and rounds up to {:?} are final",
+            round,
+            self.highest_committed_round,
+            final_round
+        );
+        self.blocks.retain(|_, block| block.value.round <= round);
+        self.quorum_certificates
+            .retain(|_, qc| qc.value.round <= round);
+        let (highest_round, highest_hash) = self
+            .quorum_certificates
+            .iter()
+            .map(|(hash, qc)| (qc.value.round, *hash))
+            .max_by_key(|(round, _)| *round)
+            .unwrap_or((Round(0), self.initial_hash));
+        self.highest_quorum_certificate_round = highest_round;
+        self.highest_quorum_certificate_hash = highest_hash;
+        if self.highest_timeout_certificate_round > round {
+            self.highest_timeout_certificate_round = Round(0);
+            self.highest_timeout_certificate = None;
+        }
+        self.current_round =
+            std::cmp::max(highest_round, self.highest_timeout_certificate_round) + 1;
+        self.current_proposed_block = None;
+        self.current_timeouts = BTreeMap::new();
+        self.current_votes = BTreeMap::new();
+        self.recent_votes = HashMap::new();
+        self.current_timeouts_weight = 0;
+        self.current_election = ElectionState::Ongoing {
+            ballot: HashMap::new(),
+        };
+        Ok(())
+    }
+
+    /// Record that the commits up to `round` were delivered to the SMR layer.
+    pub(crate) fn set_delivered_round(&mut self, round: Round) {
+        self.delivered_round = max(self.delivered_round, round);
+    }
+
+    /// Approximate number of bytes used to store blocks and QCs.
+    #[cfg(feature = "simulator")]
+    pub(crate) fn approximate_memory(&self) -> usize {
+        let blocks_size = self.blocks.len()
+            * (std::mem::size_of::<BlockHash<Context::HashValue>>()
+                + std::mem::size_of::<Block<Context>>());
+        let quorum_certificates_size = self
+            .quorum_certificates
+            .values()
+            .map(|qc| {
+                std::mem::size_of::<QuorumCertificateHash<Context::HashValue>>()
+                    + std::mem::size_of::<QuorumCertificate<Context>>()
+                    + qc.value.votes.len()
+                        * std::mem::size_of::<(Context::Author, Context::Signature)>()
+            })
+            .sum::<usize>();
+        blocks_size + quorum_certificates_size
+    }
+
+    /// Hashes of the blocks and QCs of the store.
+    #[cfg(feature = "simulator")]
+    pub(crate) fn known_hashes(&self) -> impl Iterator<Item = Context::HashValue> + '_ {
+        self.blocks
+            .keys()
+            .map(|hash| hash.0)
+            .chain(self.quorum_certificates.keys().map(|hash| hash.0))
+    }
+
+    /// Export the blocks and QCs of the store as a graph in DOT format.
+    pub(crate) fn to_dot(&self) -> String {
+        let mut blocks: Vec<_> = self.blocks.iter().collect();
+        blocks.sort_by_key(|(_, block)| block.value.round);
+        let mut qcs: Vec<_> = self.quorum_certificates.iter().collect();
+        qcs.sort_by_key(|(_, qc)| qc.value.round);
+        let mut lines = vec!["digraph {".to_string()];
+        lines.push(format!(
+            "  \"QC{:?}\" [label=\"{:?}\"];",
+            self.initial_hash.0, self.epoch_id
+        ));
+        for (hash, block) in blocks {
+            lines.push(format!(
+                "  \"B{:?}\" [shape=box, label=\"{:?}\\n{:?}\"];",
+                hash.0, block.value.round, block.value.author
+            ));
+            lines.push(format!(
+                "  \"B{:?}\" -> \"QC{:?}\";",
+                hash.0, block.value.previous_quorum_certificate_hash.0
+            ));
+        }
+        for (hash, qc) in qcs {
+            lines.push(format!(
+                "  \"QC{:?}\" [label=\"QC {:?}\"];",
+                hash.0, qc.value.round
+            ));
+            lines.push(format!(
+                "  \"QC{:?}\" -> \"B{:?}\";",
+                hash.0, qc.value.certified_block_hash.0
+            ));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    fn prune_records(&mut self) {
+        let pruning_depth = match self.pruning_depth {
+            Some(pruning_depth) => pruning_depth,
+            None => return,
+        };
+        let committed_round = self.highest_committed_round;
+        let lowest_kept_round = Round(
+            min(self.delivered_round, committed_round)
+                .0
+                .saturating_sub(pruning_depth),
+        );
+        if (committed_round, lowest_kept_round) == self.latest_pruning {
+            return;
+        }
+        self.latest_pruning = (committed_round, lowest_kept_round);
+        // Below the highest commit, only keep the records that certified blocks can still
+        // refer to, i.e. the ancestors of the highest QC and the highest commit certificate.
+        let mut reachable_quorum_certificates = HashSet::new();
+        let mut reachable_blocks = HashSet::new();
+        let highest_cc_hash = self
+            .highest_commit_certificate_hash
+            .unwrap_or(self.initial_hash);
+        for qc_hash in &[self.highest_quorum_certificate_hash, highest_cc_hash] {
+            let ancestors = BackwardQuorumCertificateIterator::new(self, *qc_hash)
+                .take_while(|qc| qc.value.round >= lowest_kept_round);
+            self.collect_ancestors(
+                *qc_hash,
+                ancestors,
+                &mut reachable_quorum_certificates,
+                &mut reachable_blocks,
+            );
+        }
+        // Uncommitted blocks may still be voted on: keep the two QCs (and their blocks) needed
+        // by `previous_round` and `second_previous_round`.
+        let uncommitted_parents: Vec<_> = self
+            .blocks
+            .values()
+            .filter(|block| block.value.round > committed_round)
+            .map(|block| block.value.previous_quorum_certificate_hash)
+            .collect();
+        for qc_hash in uncommitted_parents {
+            let ancestors = BackwardQuorumCertificateIterator::new(self, qc_hash).take(2);
+            self.collect_ancestors(
+                qc_hash,
+                ancestors,
+                &mut reachable_quorum_certificates,
+                &mut reachable_blocks,
+            );
+        }
+        self.blocks.retain(|hash, block| {
+            block.value.round >= committed_round || reachable_blocks.contains(hash)
+        });
+        self.quorum_certificates.retain(|hash, qc| {
+            qc.value.round >= committed_round || reachable_quorum_certificates.contains(hash)
+        });
+    }
+
+    /// Record the hashes of the given QCs, found by walking back from `qc_hash`, and of their
+    /// certified blocks.
+    fn collect_ancestors<'a>(
+        &'a self,
+        qc_hash: QuorumCertificateHash<Context::HashValue>,
+        ancestors: impl Iterator<Item = &'a QuorumCertificate<Context>>,
+        quorum_certificates: &mut HashSet<QuorumCertificateHash<Context::HashValue>>,
+        blocks: &mut HashSet<BlockHash<Context::HashValue>>,
+    ) {
+        let mut hash = qc_hash;
+        for qc in ancestors {
+            quorum_certificates.insert(hash);
+            blocks.insert(qc.value.certified_block_hash);
+            hash = self
+                .block(qc.value.certified_block_hash)
+                .unwrap()
+                .value
+                .previous_quorum_certificate_hash;
         }
     }
 
@@ -204,14 +528,39 @@ impl<Context: SmrContext> RecordStoreState<Context> {
         BackwardQuorumCertificateIterator::new(self, qc_hash).map(|qc| qc.value.round)
     }
 
-    fn update_current_round(&mut self, round: Round) {
+    fn update_current_round(&mut self, round: Round, context: &mut Context) {
         if round <= self.current_round {
             return;
         }
         self.current_round = round;
-        self.current_proposed_block = None;
-        self.current_timeouts = HashMap::new();
-        self.current_votes = HashMap::new();
+        self.current_round_start_time = None;
+        if let Some(block_hash) = self.current_proposed_block.take() {
+            let is_certified = self
+                .highest_quorum_certificate()
+                .map(|qc| qc.value.certified_block_hash)
+                == Some(block_hash);
+            if !is_certified {
+                let block = self.block(block_hash).unwrap();
+                self.abandoned_proposal = Some((block.value.author, block.value.command.clone()));
+                // Free the speculative state computed when we voted for the abandoned proposal.
+                if let Some(vote) = self.current_votes.get(&context.author()) {
+                    if vote.value.certified_block_hash == block_hash {
+                        context.discard(&vote.value.state);
+                    }
+                }
+            }
+        }
+        self.current_timeouts = BTreeMap::new();
+        let votes = std::mem::take(&mut self.current_votes);
+        if self.vote_retention > 0 {
+            if !votes.is_empty() {
+                let vote_round = votes.values().next().unwrap().value.round;
+                self.recent_votes.insert(vote_round, votes);
+            }
+            let retention = self.vote_retention;
+            self.recent_votes
+                .retain(|vote_round, _| vote_round.0 + retention >= round.0);
+        }
         self.current_timeouts_weight = 0;
         self.current_election = ElectionState::Ongoing {
             ballot: HashMap::new(),
@@ -228,6 +577,23 @@ impl<Context: SmrContext> RecordStoreState<Context> {
         };
         if let (Some(r1), Some(r2), Some(r3)) = rounds {
             if r3 == r2 + 1 && r2 == r1 + 1 && r1 > self.highest_committed_round {
+                // Record the authors of the newly committed blocks, i.e. the blocks certified
+                // by the QCs from the committed one back to the previous commit (excluded).
+                let previous_committed_round = self.highest_committed_round;
+                let new_leaders: Vec<_> = BackwardQuorumCertificateIterator::new(self, qc_hash)
+                    .skip(2)
+                    .take_while(|qc| qc.value.round > previous_committed_round)
+                    .map(|qc| {
+                        let block = self.block(qc.value.certified_block_hash).unwrap();
+                        (qc.value.round, block.value.author)
+                    })
+                    .collect();
+                self.committed_leaders.extend(new_leaders);
+                if self.committed_leaders.len() > MAX_COMMITTED_LEADERS {
+                    let excess = self.committed_leaders.len() - MAX_COMMITTED_LEADERS;
+                    let first_round = *self.committed_leaders.keys().nth(excess).unwrap();
+                    self.committed_leaders = self.committed_leaders.split_off(&first_round);
+                }
                 self.highest_committed_round = r1;
                 self.highest_commit_certificate_hash = Some(qc_hash);
             }
@@ -254,17 +620,55 @@ impl<Context: SmrContext> RecordStoreState<Context> {
         None
     }
 
+    fn record_hash(context: &Context, record: &Record<Context>) -> Context::HashValue {
+        match record {
+            Record::Block(block) => context.hash(&block.value),
+            Record::Vote(vote) => context.hash(&vote.value),
+            Record::QuorumCertificate(qc) => context.hash(&qc.value),
+            Record::Timeout(timeout) => context.hash(&timeout.value),
+        }
+    }
+
+    /// Check if a record was already inserted, without verifying it.
+    fn is_known_record(&self, record: &Record<Context>, hash: Context::HashValue) -> bool {
+        match record {
+            Record::Block(_) => self.blocks.contains_key(&BlockHash(hash)),
+            Record::Vote(vote) => self.current_votes.get(&vote.value.author) == Some(vote),
+            Record::QuorumCertificate(_) => self
+                .quorum_certificates
+                .contains_key(&QuorumCertificateHash(hash)),
+            Record::Timeout(timeout) => {
+                self.current_timeouts.get(&timeout.value.author) == Some(timeout)
+            }
+        }
+    }
+
+    /// Whether a different block or QC is already stored under the same hash.
+    fn has_hash_collision(&self, record: &Record<Context>, hash: Context::HashValue) -> bool {
+        match record {
+            Record::Block(block) => matches!(
+                self.blocks.get(&BlockHash(hash)),
+                Some(known) if known.value != block.value
+            ),
+            Record::QuorumCertificate(qc) => matches!(
+                self.quorum_certificates.get(&QuorumCertificateHash(hash)),
+                Some(known) if known.value != qc.value
+            ),
+            Record::Vote(_) | Record::Timeout(_) => false,
+        }
+    }
+
     fn verify_network_record(
         &self,
         context: &Context,
         record: &Record<Context>,
-    ) -> Result<Context::HashValue> {
+        hash: Context::HashValue,
+    ) -> Result<()> {
         match record {
             Record::Block(block) => {
-                let hash = context.hash(&block.value);
                 ensure!(
-                    !self.blocks.contains_key(&BlockHash(hash)),
-                    "Block was already inserted."
+                    self.configuration.weight(&block.value.author) > 0,
+                    "The author of a block must be a member of the current epoch."
                 );
                 context.verify(block.value.author, hash, block.signature)?;
                 ensure!(
@@ -287,10 +691,19 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                         "Rounds must be increasing"
                     );
                 }
-                Ok(hash)
+                if self.leader_election == LeaderElectionPolicy::Vrf {
+                    // The previous QC was verified above, hence anyone can check the election.
+                    ensure!(
+                        self.leader(
+                            block.value.round,
+                            block.value.previous_quorum_certificate_hash
+                        ) == block.value.author,
+                        "The author of the block was not elected for this round and QC."
+                    );
+                }
+                Ok(())
             }
             Record::Vote(vote) => {
-                let hash = context.hash(&vote.value);
                 ensure!(
                     vote.value.epoch_id == self.epoch_id,
                     "Epoch identifier of vote ({:?}) must match the current epoch ({:?}).",
@@ -324,23 +737,20 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                     !self.current_votes.contains_key(&vote.value.author),
                     "We insert votes only for authors who haven't voted yet."
                 );
+                ensure!(
+                    self.configuration.weight(&vote.value.author) > 0,
+                    "The author of a vote must be a member of the current epoch."
+                );
                 context.verify(vote.value.author, hash, vote.signature)?;
-                Ok(hash)
+                Ok(())
             }
             Record::QuorumCertificate(qc) => {
-                let hash = context.hash(&qc.value);
                 ensure!(
                     qc.value.epoch_id == self.epoch_id,
                     "Epoch identifier of QC ({:?}) must match the current epoch ({:?}).",
                     qc.value.epoch_id,
                     self.epoch_id
                 );
-                ensure!(
-                    !self
-                        .quorum_certificates
-                        .contains_key(&QuorumCertificateHash(hash)),
-                    "QuorumCertificate was already inserted."
-                );
                 ensure!(
                     self.blocks.contains_key(&qc.value.certified_block_hash),
                     "The certified block hash of a QC must be verified first."
@@ -369,26 +779,23 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                 );
                 let mut weight = 0;
                 for (author, signature) in &qc.value.votes {
-                    let original_vote_hash = context.hash(&Vote_::<Context> {
-                        epoch_id: self.epoch_id,
-                        round: qc.value.round,
-                        certified_block_hash: qc.value.certified_block_hash,
-                        state: qc.value.state.clone(),
-                        committed_state: qc.value.committed_state.clone(),
-                        author: *author,
-                    });
+                    ensure!(
+                        self.configuration.weight(author) > 0,
+                        "The votes of a QC must come from members of the current epoch."
+                    );
+                    let original_vote_hash =
+                        context.hash(&Vote_::from_quorum_certificate(&qc.value, *author));
                     context.verify(*author, original_vote_hash, *signature)?;
                     weight += self.configuration.weight(author);
                 }
                 ensure!(
-                    weight >= self.configuration.quorum_threshold(),
+                    self.configuration.is_quorum(weight),
                     "Votes in QCs must form a quorum"
                 );
                 context.verify(qc.value.author, hash, qc.signature)?;
-                Ok(hash)
+                Ok(())
             }
             Record::Timeout(timeout) => {
-                let hash = context.hash(&timeout.value);
                 ensure!(
                     timeout.value.epoch_id == self.epoch_id,
                     "Epoch identifier of timeout ({:?}) must match the current epoch ({:?}).",
@@ -410,8 +817,12 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                     !self.current_timeouts.contains_key(&timeout.value.author),
                     "A timeout is already known for the same round and the same author"
                 );
+                ensure!(
+                    self.configuration.weight(&timeout.value.author) > 0,
+                    "The author of a timeout must be a member of the current epoch."
+                );
                 context.verify(timeout.value.author, hash, timeout.signature)?;
-                Ok(hash)
+                Ok(())
             }
         }
     }
@@ -448,28 +859,100 @@ impl<Context: SmrContext> RecordStoreState<Context> {
             previous_state,
             block.value.command.clone(),
             block.value.time,
+            block.value.author,
             previous_voters,
             previous_author,
         )
     }
 
+    /// Try to create a QC for one of our proposals from the votes retained for a past round.
+    fn check_for_late_quorum_certificate(&mut self, context: &mut Context) -> bool {
+        let mut rounds: Vec<_> = self.recent_votes.keys().cloned().collect();
+        rounds.sort();
+        for round in rounds {
+            let votes = &self.recent_votes[&round];
+            // Weights of each pair (block hash, state), in a deterministic order.
+            let mut ballot: Vec<(_, usize)> = Vec::new();
+            for vote in votes.values() {
+                let block = match self.block(vote.value.certified_block_hash) {
+                    Some(block) => block,
+                    None => continue,
+                };
+                if block.value.author != context.author() {
+                    continue;
+                }
+                let key = (vote.value.certified_block_hash, vote.value.state.clone());
+                let weight = self.configuration.weight(&vote.value.author);
+                match ballot.iter_mut().find(|(other, _)| *other == key) {
+                    Some((_, total)) => *total += weight,
+                    None => ballot.push((key, weight)),
+                }
+            }
+            let winner = ballot
+                .into_iter()
+                .find(|(_, weight)| self.configuration.is_quorum(*weight))
+                .map(|(key, _)| key);
+            if let Some((block_hash, state)) = winner {
+                let votes = self.recent_votes.remove(&round).unwrap();
+                let authors_and_signatures = votes
+                    .values()
+                    .filter(|vote| {
+                        vote.value.certified_block_hash == block_hash && vote.value.state == state
+                    })
+                    .map(|vote| (vote.value.author, vote.signature))
+                    .collect();
+                let quorum_certificate = Record::QuorumCertificate(sign_record(
+                    context,
+                    QuorumCertificate_ {
+                        epoch_id: self.epoch_id,
+                        round,
+                        certified_block_hash: block_hash,
+                        committed_state: self.vote_committed_state(block_hash),
+                        state,
+                        votes: authors_and_signatures,
+                        author: context.author(),
+                    },
+                ));
+                self.insert_network_record(quorum_certificate, context);
+                return true;
+            }
+        }
+        false
+    }
+
     fn try_insert_network_record(
         &mut self,
         record: Record<Context>,
         context: &mut Context,
     ) -> Result<()> {
+        #[cfg(debug_assertions)]
+        let previous_round = self.current_round;
+        let hash = Self::record_hash(&*context, &record);
+        debug_assert!(
+            !self.has_hash_collision(&record, hash),
+            "Hash collision between two different records: {:?}",
+            hash
+        );
+        // Fast path: drop records that were already inserted before any verification.
+        if self.is_known_record(&record, hash) {
+            self.replayed_record_count += 1;
+            bail!("Record was already inserted.");
+        }
         // First, check that the record is "relevant" and that invariants of "verified records",
         // such as chaining, are respected.
-        let hash = self.verify_network_record(&*context, &record)?;
+        self.verify_network_record(&*context, &record, hash)?;
         // Second, insert the record. In the case of QC, this is where check execution states.
         match record {
             Record::Block(block) => {
                 let block_hash = BlockHash(hash);
+                // The leader depends on the QC extended by the block (with a VRF), which
+                // `verify_network_record` requires to be inserted first.
                 if block.value.round == self.current_round
-                    && PacemakerState::leader(&*self, block.value.round) == block.value.author
+                    && self.leader(
+                        block.value.round,
+                        block.value.previous_quorum_certificate_hash,
+                    ) == block.value.author
                 {
-                    // If we use a VRF, this assumes that we have inserted the highest commit rule
-                    // beforehand.
                     self.current_proposed_block = Some(block_hash);
                 }
                 self.blocks.insert(block_hash, block);
@@ -482,7 +965,7 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                             .entry((vote.value.certified_block_hash, vote.value.state.clone()))
                             .or_insert(0);
                         *entry += self.configuration.weight(&vote.value.author);
-                        if *entry >= self.configuration.quorum_threshold() {
+                        if self.configuration.is_quorum(*entry) {
                             Some(ElectionState::Won {
                                 block_hash: vote.value.certified_block_hash,
                                 state: vote.value.state,
@@ -521,22 +1004,32 @@ impl<Context: SmrContext> RecordStoreState<Context> {
                     self.highest_quorum_certificate_round = qc_round;
                     self.highest_quorum_certificate_hash = qc_hash;
                 }
-                self.update_current_round(qc_round + 1);
+                self.update_current_round(qc_round + 1, context);
                 self.update_commit_3chain_round(qc_hash);
             }
             Record::Timeout(timeout) => {
                 self.current_timeouts
                     .insert(timeout.value.author, timeout.clone());
                 self.current_timeouts_weight += self.configuration.weight(&timeout.value.author);
-                if self.current_timeouts_weight >= self.configuration.quorum_threshold() {
+                if self.configuration.is_quorum(self.current_timeouts_weight) {
                     let timeout_certificate =
                         self.current_timeouts.iter().map(|x| x.1.clone()).collect();
                     self.highest_timeout_certificate = Some(timeout_certificate);
                     self.highest_timeout_certificate_round = self.current_round;
-                    self.update_current_round(self.current_round + 1);
+                    self.pending_view_changes
+                        .push((self.current_round, self.current_round_start_time));
+                    self.update_current_round(self.current_round + 1, context);
                 }
             }
         }
+        // Early returns above happen before any round update.
+        #[cfg(debug_assertions)]
+        debug_assert!(
+            self.current_round >= previous_round,
+            "Inserting a record moved the current round backward from {:?} to {:?}",
+            previous_round,
+            self.current_round
+        );
         Ok(())
     }
 }
@@ -546,8 +1039,58 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         self.current_round
     }
 
-    fn pick_author(&self, seed: u64) -> Context::Author {
-        self.configuration.pick_author(seed)
+    fn current_vote_count(&self) -> usize {
+        self.current_votes.len()
+    }
+
+    fn current_timeouts_weight(&self) -> usize {
+        self.current_timeouts_weight
+    }
+
+    fn update_clock(&mut self, clock: NodeTime) {
+        for (round, start_time) in self.pending_view_changes.drain(..) {
+            // Rounds entered and abandoned between two clock updates are not measured.
+            if let Some(start_time) = start_time {
+                self.view_changes.push((round, start_time, clock));
+            }
+        }
+        if self.current_round_start_time.is_none() {
+            self.current_round_start_time = Some(clock);
+        }
+    }
+
+    #[cfg(feature = "simulator")]
+    fn view_change_latencies(&self) -> Vec<(Round, Duration)> {
+        self.view_changes
+            .iter()
+            .map(|(round, start_time, end_time)| (*round, Duration(end_time.0 - start_time.0)))
+            .collect()
+    }
+
+    fn replayed_record_count(&self) -> usize {
+        self.replayed_record_count
+    }
+
+    fn leader(
+        &self,
+        round: Round,
+        previous_quorum_certificate_hash: QuorumCertificateHash<Context::HashValue>,
+    ) -> Context::Author {
+        match self.leader_election {
+            LeaderElectionPolicy::Hash => HashElection.leader(round, &self.configuration),
+            LeaderElectionPolicy::ContentAddressed => {
+                ContentAddressedElection.leader(round, &self.configuration)
+            }
+            LeaderElectionPolicy::RoundRobin => {
+                RoundRobinElection.leader(round, &self.configuration)
+            }
+            LeaderElectionPolicy::Vrf => VrfElection::new(previous_quorum_certificate_hash)
+                .leader(round, &self.configuration),
+        }
+    }
+
+    fn voting_rights(&self, author: Context::Author) -> usize {
+        self.configuration.weight(&author)
     }
 
     fn highest_quorum_certificate_hash(&self) -> QuorumCertificateHash<Context::HashValue> {
@@ -555,12 +1098,19 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
     }
 
     fn committed_states_after(&self, after_round: Round) -> Vec<(Round, Context::State)> {
-        let cc_hash = self
-            .highest_commit_certificate_hash
-            .unwrap_or(self.initial_hash);
+        let cc_hash = match self.highest_commit_certificate_hash {
+            Some(hash) => hash,
+            // Nothing was committed in this epoch yet.
+            None => return Vec::new(),
+        };
+        // Invariant: a commit certificate is the last QC of a 3-chain, hence the committed QC is
+        // found two steps back and both steps are certified by known QCs.
         let mut iter = BackwardQuorumCertificateIterator::new(self, cc_hash);
-        iter.next();
-        iter.next();
+        let skipped = (iter.next(), iter.next());
+        debug_assert!(
+            skipped.0.is_some() && skipped.1.is_some(),
+            "Commit certificates must extend a 3-chain"
+        );
         let mut commits = Vec::new();
         for qc in iter {
             if qc.value.round <= after_round {
@@ -573,6 +1123,14 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         commits
     }
 
+    #[cfg(feature = "simulator")]
+    fn committed_leaders(&self) -> Vec<(Round, Context::Author)> {
+        self.committed_leaders
+            .iter()
+            .map(|(round, author)| (*round, *author))
+            .collect()
+    }
+
     fn highest_quorum_certificate_round(&self) -> Round {
         self.highest_quorum_certificate_round
     }
@@ -581,10 +1139,34 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         self.highest_timeout_certificate_round
     }
 
+    fn highest_timeout_certificate_quorum_certificate(
+        &self,
+    ) -> Option<(&Block<Context>, &QuorumCertificate<Context>)> {
+        let round = self
+            .highest_timeout_certificate
+            .as_ref()?
+            .iter()
+            .map(|timeout| timeout.value.highest_certified_block_round)
+            .max()?;
+        let qc = self
+            .quorum_certificates
+            .values()
+            .find(|qc| qc.value.round == round)?;
+        let block = self.block(qc.value.certified_block_hash)?;
+        Some((block, qc))
+    }
+
     fn highest_committed_round(&self) -> Round {
         self.highest_committed_round
     }
 
+    #[cfg(feature = "simulator")]
+    fn commit_gap(&self) -> usize {
+        self.highest_quorum_certificate_round
+            .0
+            .saturating_sub(self.highest_committed_round.0)
+    }
+
     fn previous_round(&self, block_hash: BlockHash<Context::HashValue>) -> Round {
         let block = self.block(block_hash).unwrap();
         let hash = block.value.previous_quorum_certificate_hash;
@@ -624,7 +1206,11 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
                 Some(hash) => {
                     let block = self.block(*hash).unwrap();
                     assert_eq!(block.value.round, self.current_round);
-                    assert_eq!(block.value.author, leader);
+                    // With a VRF, the proposal may extend a QC other than the one that elected
+                    // the active leader, e.g. if the highest QC changed after a TC.
+                    if self.leader_election != LeaderElectionPolicy::Vrf {
+                        assert_eq!(block.value.author, leader);
+                    }
                     Some((*hash, block.value.round, block.value.author))
                 }
             }
@@ -635,7 +1221,7 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
 
     fn create_timeout(&mut self, author: Context::Author, round: Round, context: &mut Context) {
         self.insert_network_record(
-            Record::Timeout(SignedValue::make(
+            Record::Timeout(sign_record(
                 context,
                 Timeout_ {
                     epoch_id: self.epoch_id,
@@ -658,8 +1244,28 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         previous_quorum_certificate_hash: QuorumCertificateHash<Context::HashValue>,
         time: NodeTime,
     ) {
-        if let Some(command) = context.fetch() {
-            let block = Record::Block(SignedValue::make(
+        // Proposing on top of a stale QC creates unnecessary forks.
+        if previous_quorum_certificate_hash != self.highest_quorum_certificate_hash {
+            if self.strict_proposals {
+                warn!(
+                    "Refusing to propose on top of {:?} instead of the highest QC {:?}",
+                    previous_quorum_certificate_hash, self.highest_quorum_certificate_hash
+                );
+                return;
+            }
+            warn!(
+                "Proposing on top of {:?} instead of the highest QC {:?}",
+                previous_quorum_certificate_hash, self.highest_quorum_certificate_hash
+            );
+        }
+        let command = match self.abandoned_proposal.take() {
+            Some((author, command)) if self.reproposal && author == context.author() => {
+                Some(command)
+            }
+            _ => context.fetch(),
+        };
+        if let Some(command) = command {
+            let block = Record::Block(sign_record(
                 context,
                 Block_ {
                     command,
@@ -673,6 +1279,30 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         }
     }
 
+    #[cfg(feature = "simulator")]
+    fn preview_proposal(
+        &self,
+        context: &Context,
+    ) -> Option<(
+        Context::Command,
+        QuorumCertificateHash<Context::HashValue>,
+        Round,
+    )> {
+        let command = match &self.abandoned_proposal {
+            Some((author, command)) if self.reproposal && *author == context.author() => {
+                Some(command.clone())
+            }
+            _ => context.peek(),
+        };
+        command.map(|command| {
+            (
+                command,
+                self.highest_quorum_certificate_hash,
+                self.current_round,
+            )
+        })
+    }
+
     fn create_vote(
         &mut self,
         context: &mut Context,
@@ -681,7 +1311,7 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         let committed_state = self.vote_committed_state(certified_block_hash);
         match self.compute_state(certified_block_hash, context) {
             Some(state) => {
-                let vote = Record::Vote(SignedValue::make(
+                let vote = Record::Vote(sign_record(
                     context,
                     Vote_ {
                         epoch_id: self.epoch_id,
@@ -700,6 +1330,9 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
     }
 
     fn check_for_new_quorum_certificate(&mut self, context: &mut Context) -> bool {
+        if self.check_for_late_quorum_certificate(context) {
+            return true;
+        }
         match &self.current_election {
             ElectionState::Won { block_hash, state } => {
                 if self.block(*block_hash).unwrap().value.author != context.author() {
@@ -717,7 +1350,7 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
                         }
                     })
                     .collect();
-                let quorum_certificate = Record::QuorumCertificate(SignedValue::make(
+                let quorum_certificate = Record::QuorumCertificate(sign_record(
                     context,
                     QuorumCertificate_ {
                         epoch_id: self.epoch_id,
@@ -798,7 +1431,19 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         result
     }
 
-    fn unknown_records(&self, known_qc_rounds: BTreeSet<Round>) -> Vec<Record<Context>> {
+    fn known_block_hashes(&self) -> BTreeSet<BlockHash<Context::HashValue>> {
+        self.blocks
+            .iter()
+            .filter(|(_, block)| block.value.round > self.highest_committed_round)
+            .map(|(hash, _)| *hash)
+            .collect()
+    }
+
+    fn unknown_records(
+        &self,
+        known_qc_rounds: BTreeSet<Round>,
+        known_block_hashes: BTreeSet<BlockHash<Context::HashValue>>,
+    ) -> Vec<Record<Context>> {
         let highest_qc_hash = self.highest_quorum_certificate_hash;
         let highest_cc_hash = self
             .highest_commit_certificate_hash
@@ -815,18 +1460,45 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
         let mut result = Vec::new();
         for n in (0..qcs.len()).rev() {
             let qc = qcs[n];
-            let block = self.block(qc.value.certified_block_hash).unwrap();
-            result.push(Record::Block(block.clone()));
+            if !known_block_hashes.contains(&qc.value.certified_block_hash) {
+                let block = self.block(qc.value.certified_block_hash).unwrap();
+                result.push(Record::Block(block.clone()));
+            }
             result.push(Record::QuorumCertificate(qc.clone()));
         }
-        // Copying timeouts again.
-        for timeout in self.timeouts() {
+        // Copying timeouts again, in a deterministic order.
+        let mut timeouts = self.timeouts();
+        timeouts.sort_by_key(|timeout| (timeout.value.round, timeout.value.author));
+        for timeout in timeouts {
             result.push(Record::Timeout(timeout.clone()));
         }
         // Skipping votes intentionally.
         if let Some(block_hash) = &self.current_proposed_block {
-            result.push(Record::Block(self.block(*block_hash).unwrap().clone()));
+            if !known_block_hashes.contains(block_hash) {
+                result.push(Record::Block(self.block(*block_hash).unwrap().clone()));
+            }
+        }
+        result
+    }
+
+    #[cfg(feature = "simulator")]
+    fn ancestry(&self, block_hash: BlockHash<Context::HashValue>) -> Vec<Record<Context>> {
+        let block = match self.block(block_hash) {
+            Some(block) => block,
+            None => return Vec::new(),
+        };
+        let qcs: Vec<_> = BackwardQuorumCertificateIterator::new(
+            self,
+            block.value.previous_quorum_certificate_hash,
+        )
+        .collect();
+        let mut result = Vec::new();
+        for qc in qcs.into_iter().rev() {
+            let certified_block = self.block(qc.value.certified_block_hash).unwrap();
+            result.push(Record::Block(certified_block.clone()));
+            result.push(Record::QuorumCertificate(qc.clone()));
         }
+        result.push(Record::Block(block.clone()));
         result
     }
 
@@ -838,6 +1510,6 @@ impl<Context: SmrContext> RecordStore<Context> for RecordStoreState<Context> {
             }
             Ok(()) => (),
         };
-        // TODO: discard unneeded records from self.blocks and self.quorum_certificates
+        self.prune_records();
     }
 }
@@ -0,0 +1,108 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{data_sync::*, node::*};
+use bft_lib::{
+    base_types::NodeTime,
+    interfaces::ConsensusNode,
+    simulated_context::{Author, SimulatedContext},
+    simulator::{self, ByzantineBehavior, RandomDelay, StartupMode},
+};
+use futures::executor::block_on;
+use std::collections::HashMap;
+
+/// Simulator of LibraBFT v2 nodes running over simulated contexts.
+pub type Simulator = simulator::Simulator<
+    NodeState<SimulatedContext>,
+    SimulatedContext,
+    DataSyncNotification<SimulatedContext>,
+    DataSyncRequest<SimulatedContext>,
+    DataSyncResponse<SimulatedContext>,
+>;
+
+/// Build simulators whose nodes start from their initial state with the same configuration.
+pub struct SimulatorBuilder {
+    seed: u64,
+    nodes: usize,
+    commands_per_epoch: usize,
+    config: NodeConfig,
+    network_delay: RandomDelay,
+    startup_mode: StartupMode,
+    byzantine_behaviors: HashMap<Author, ByzantineBehavior>,
+    context_setup: Box<dyn Fn(SimulatedContext) -> SimulatedContext>,
+}
+
+impl SimulatorBuilder {
+    /// Simulate `nodes` honest nodes with the default configuration, network delays of mean 10
+    /// and variance 4, and randomized startup times.
+    pub fn new(seed: u64, nodes: usize, commands_per_epoch: usize) -> Self {
+        SimulatorBuilder {
+            seed,
+            nodes,
+            commands_per_epoch,
+            config: NodeConfig::default(),
+            network_delay: RandomDelay::new(10.0, 4.0),
+            startup_mode: StartupMode::Randomized,
+            byzantine_behaviors: HashMap::new(),
+            context_setup: Box::new(|context| context),
+        }
+    }
+
+    /// Configuration of all the nodes.
+    pub fn with_config(mut self, config: NodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Distribution of the delays of the network.
+    pub fn with_network_delay(mut self, network_delay: RandomDelay) -> Self {
+        self.network_delay = network_delay;
+        self
+    }
+
+    /// How the startup of nodes is scheduled.
+    pub fn with_startup_mode(mut self, startup_mode: StartupMode) -> Self {
+        self.startup_mode = startup_mode;
+        self
+    }
+
+    /// Misbehaviors injected on behalf of some nodes.
+    pub fn with_byzantine_behaviors(
+        mut self,
+        byzantine_behaviors: HashMap<Author, ByzantineBehavior>,
+    ) -> Self {
+        self.byzantine_behaviors = byzantine_behaviors;
+        self
+    }
+
+    /// Apply `setup` to the context of each node, e.g. to change its committee or its mempool.
+    pub fn with_context_setup<F>(mut self, setup: F) -> Self
+    where
+        F: Fn(SimulatedContext) -> SimulatedContext + 'static,
+    {
+        self.context_setup = Box::new(setup);
+        self
+    }
+
+    /// Context of a node that starts from the initial state. This is also useful to add
+    /// observers or late joiners to a simulation.
+    pub fn initial_context(&self, author: Author) -> SimulatedContext {
+        let context = SimulatedContext::new(author, self.nodes, self.commands_per_epoch);
+        let mut context = (self.context_setup)(context);
+        let mut node = NodeState::make_initial_state(&context, self.config.clone(), NodeTime(0));
+        block_on(node.save_node(&mut context)).unwrap();
+        context
+    }
+
+    /// Create the simulator. The builder may be reused to create identical simulators.
+    pub fn build(&self) -> Simulator {
+        simulator::Simulator::new(
+            self.seed,
+            self.nodes,
+            self.network_delay,
+            self.startup_mode,
+            self.byzantine_behaviors.clone(),
+            |author, _| self.initial_context(author),
+        )
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::record::*;
+use bft_lib::{base_types::*, smr_context::*};
+use std::collections::HashMap;
+
+/// Build signed blocks, votes and QCs of a given epoch. Execution states are computed along
+/// the way so that votes and QCs are consistent with the blocks that they certify. Records are
+/// signed by the context passed to each method.
+pub(crate) struct RecordBuilder<Context: SmrContext> {
+    epoch_id: EpochId,
+    initial_hash: QuorumCertificateHash<Context::HashValue>,
+    /// Execution states certified by the QCs built so far, including the initial hash.
+    certified_states: HashMap<QuorumCertificateHash<Context::HashValue>, Context::State>,
+    /// Rounds and execution states of the blocks built so far.
+    block_states: HashMap<BlockHash<Context::HashValue>, (Round, Context::State)>,
+}
+
+impl<Context: SmrContext> RecordBuilder<Context> {
+    /// Start from the initial state of the given epoch, as known by `context`.
+    pub(crate) fn new(context: &Context, epoch_id: EpochId) -> Self {
+        let initial_hash = QuorumCertificateHash(context.hash(&epoch_id));
+        let mut certified_states = HashMap::new();
+        certified_states.insert(initial_hash, context.last_committed_state());
+        RecordBuilder {
+            epoch_id,
+            initial_hash,
+            certified_states,
+            block_states: HashMap::new(),
+        }
+    }
+
+    /// Hash of the initial (virtual) QC of the epoch.
+    pub(crate) fn initial_hash(&self) -> QuorumCertificateHash<Context::HashValue> {
+        self.initial_hash
+    }
+
+    /// Propose `command` at the given round on top of a known QC. The block is timestamped with
+    /// the round number.
+    pub(crate) fn block(
+        &mut self,
+        context: &mut Context,
+        round: Round,
+        command: Context::Command,
+        previous_quorum_certificate_hash: QuorumCertificateHash<Context::HashValue>,
+    ) -> Block<Context> {
+        let time = NodeTime(round.0 as i64);
+        let base_state = self
+            .certified_states
+            .get(&previous_quorum_certificate_hash)
+            .expect("Previous QC should be built first")
+            .clone();
+        let state = context
+            .compute(
+                &base_state,
+                command.clone(),
+                time,
+                context.author(),
+                None,
+                Vec::new(),
+            )
+            .expect("Command should execute");
+        let block = sign_record(
+            context,
+            Block_ {
+                command,
+                time,
+                previous_quorum_certificate_hash,
+                round,
+                author: context.author(),
+            },
+        );
+        let block_hash = BlockHash(context.hash(&block.value));
+        self.block_states.insert(block_hash, (round, state));
+        block
+    }
+
+    /// Vote for a block built before.
+    pub(crate) fn vote(
+        &self,
+        context: &mut Context,
+        block_hash: BlockHash<Context::HashValue>,
+    ) -> Vote<Context> {
+        let (round, state) = self
+            .block_states
+            .get(&block_hash)
+            .expect("Block should be built first")
+            .clone();
+        sign_record(
+            context,
+            Vote_ {
+                epoch_id: self.epoch_id,
+                round,
+                certified_block_hash: block_hash,
+                state,
+                author: context.author(),
+                committed_state: None,
+            },
+        )
+    }
+
+    /// Aggregate votes for the same block into a QC.
+    pub(crate) fn qc(
+        &mut self,
+        context: &mut Context,
+        votes: Vec<Vote<Context>>,
+    ) -> QuorumCertificate<Context> {
+        let first_vote = &votes.first().expect("A QC needs votes").value;
+        let qc: QuorumCertificate<Context> = sign_record(
+            context,
+            QuorumCertificate_ {
+                epoch_id: self.epoch_id,
+                round: first_vote.round,
+                certified_block_hash: first_vote.certified_block_hash,
+                state: first_vote.state.clone(),
+                votes: votes
+                    .iter()
+                    .map(|vote| (vote.value.author, vote.signature))
+                    .collect(),
+                committed_state: None,
+                author: context.author(),
+            },
+        );
+        let qc_hash = QuorumCertificateHash(context.hash(&qc.value));
+        self.certified_states
+            .insert(qc_hash, qc.value.state.clone());
+        qc
+    }
+}
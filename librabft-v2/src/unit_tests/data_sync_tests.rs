@@ -2,7 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use bft_lib::simulated_context::SimulatedContext;
+use bft_lib::{
+    interfaces::ConsensusNode,
+    simulated_context::*,
+    smr_context::{CommandExecutor, CommandFetcher, CryptographicModule, StateFinalizer},
+};
+use futures::executor::block_on;
 use std::collections::BTreeSet;
 
 #[test]
@@ -12,6 +17,7 @@ fn test_serde_notification() {
         highest_commit_certificate: None,
         highest_quorum_certificate: None,
         timeouts: Vec::new(),
+        timeout_certificate_quorum_certificate: None,
         current_vote: None,
         proposed_block: None,
     };
@@ -22,12 +28,13 @@ fn test_serde_notification() {
 
 #[test]
 fn test_serde_request() {
-    let data = DataSyncRequest {
+    let data = DataSyncRequest::<SimulatedContext> {
         current_epoch: EpochId(0),
         known_quorum_certificates: BTreeSet::default(),
+        known_block_hashes: BTreeSet::default(),
     };
     let message = serde_json::to_string(&data).unwrap();
-    let data2: DataSyncRequest = serde_json::from_str(&message).unwrap();
+    let data2: DataSyncRequest<SimulatedContext> = serde_json::from_str(&message).unwrap();
     assert_eq!(data2, data);
 }
 
@@ -41,3 +48,316 @@ fn test_serde_response() {
     let data2: DataSyncResponse<SimulatedContext> = serde_json::from_str(&message).unwrap();
     assert_eq!(data2, data);
 }
+
+/// Create a node with a QC at round 1 and a TC at round 2, in a committee of one node.
+fn make_node_with_timeout_certificate(
+    config: NodeConfig,
+) -> (NodeState<SimulatedContext>, SimulatedContext) {
+    let mut context = SimulatedContext::new(Author(0), /* num_nodes */ 1, 30000);
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    let epoch_id = EpochId(0);
+    let initial_hash = QuorumCertificateHash(context.hash(&epoch_id));
+    let initial_state = context.last_committed_state();
+
+    let command = context.fetch().unwrap();
+    let block = sign_record(
+        &mut context,
+        Block_ {
+            command: command.clone(),
+            time: NodeTime(1),
+            previous_quorum_certificate_hash: initial_hash,
+            round: Round(1),
+            author: Author(0),
+        },
+    );
+    let block_hash = BlockHash(context.hash(&block.value));
+    let state = context
+        .compute(
+            &initial_state,
+            command,
+            NodeTime(1),
+            Author(0),
+            None,
+            Vec::new(),
+        )
+        .unwrap();
+    let vote = sign_record(
+        &mut context,
+        Vote_::<SimulatedContext> {
+            epoch_id,
+            round: Round(1),
+            certified_block_hash: block_hash,
+            state: state.clone(),
+            committed_state: None,
+            author: Author(0),
+        },
+    );
+    let qc = sign_record(
+        &mut context,
+        QuorumCertificate_ {
+            epoch_id,
+            round: Round(1),
+            certified_block_hash: block_hash,
+            state,
+            votes: vec![(Author(0), vote.signature)],
+            committed_state: None,
+            author: Author(0),
+        },
+    );
+    let timeout = sign_record(
+        &mut context,
+        Timeout_ {
+            epoch_id,
+            round: Round(2),
+            highest_certified_block_round: Round(1),
+            author: Author(0),
+        },
+    );
+    node.insert_network_record(epoch_id, Record::Block(block), &mut context);
+    node.insert_network_record(epoch_id, Record::QuorumCertificate(qc), &mut context);
+    node.insert_network_record(epoch_id, Record::Timeout(timeout), &mut context);
+    assert_eq!(
+        node.record_store().highest_timeout_certificate_round(),
+        Round(2)
+    );
+    (node, context)
+}
+
+#[test]
+fn test_timeout_certificate_with_quorum_certificate() {
+    let config = NodeConfig {
+        attach_timeout_quorum_certificate: true,
+        ..NodeConfig::default()
+    };
+    let (node0, context0) = make_node_with_timeout_certificate(config.clone());
+    let notification = node0.create_notification(&context0);
+    assert!(notification
+        .timeout_certificate_quorum_certificate
+        .is_some());
+
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 30000);
+    let mut node1 = NodeState::make_initial_state(&context1, config, NodeTime(0));
+    let request =
+        block_on(node1.handle_notification(&mut context1, Author(0), notification, NodeTime(0)));
+    // The QC and the TC were both learned without an extra round trip.
+    assert!(request.is_none());
+    assert_eq!(
+        node1.record_store().highest_quorum_certificate_round(),
+        Round(1)
+    );
+    assert_eq!(
+        node1.record_store().highest_timeout_certificate_round(),
+        Round(2)
+    );
+}
+
+#[test]
+fn test_timeout_certificate_without_quorum_certificate() {
+    let (node0, context0) = make_node_with_timeout_certificate(NodeConfig::default());
+    let notification = node0.create_notification(&context0);
+    assert!(notification
+        .timeout_certificate_quorum_certificate
+        .is_none());
+
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 30000);
+    let mut node1 = NodeState::make_initial_state(&context1, NodeConfig::default(), NodeTime(0));
+    let request =
+        block_on(node1.handle_notification(&mut context1, Author(0), notification, NodeTime(0)));
+    // The block of the highest QC is missing hence a request is needed.
+    assert!(request.is_some());
+    assert_eq!(
+        node1.record_store().highest_timeout_certificate_round(),
+        Round(0)
+    );
+}
+
+#[test]
+fn test_bounded_sync_attempts() {
+    // The peer keeps announcing a QC that we cannot verify without a request.
+    let (node0, context0) = make_node_with_timeout_certificate(NodeConfig::default());
+    let notification = node0.create_notification(&context0);
+
+    let config = NodeConfig {
+        target_commit_interval: Duration(100),
+        ..NodeConfig::default()
+    };
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 30000);
+    let mut node1 = NodeState::make_initial_state(&context1, config, NodeTime(0));
+    let mut notify = |sender, notification, clock| {
+        block_on(node1.handle_notification(
+            &mut context1,
+            Author(sender),
+            notification,
+            NodeTime(clock),
+        ))
+        .is_some()
+    };
+    // Responses are never delivered so requests do not make progress.
+    for _ in 0..MAX_SYNC_ATTEMPTS_WITHOUT_PROGRESS {
+        assert!(notify(1, notification.clone(), 0));
+    }
+    assert!(!notify(1, notification.clone(), 0));
+    // Attempts are counted by sender, regardless of the content of the notification.
+    assert!(notify(2, notification.clone(), 0));
+
+    // Requests resume after an exponential backoff.
+    assert!(!notify(1, notification.clone(), 99));
+    assert!(notify(1, notification.clone(), 100));
+    assert!(!notify(1, notification.clone(), 299));
+    assert!(notify(1, notification.clone(), 300));
+    assert!(!notify(1, notification.clone(), 300));
+
+    // Attempts are counted again once the peer no longer requires a sync.
+    let node2 = NodeState::make_initial_state(&context0, NodeConfig::default(), NodeTime(0));
+    assert!(!notify(1, node2.create_notification(&context0), 300));
+    assert!(notify(1, notification, 300));
+}
+
+#[test]
+fn test_response_spanning_several_epochs() {
+    // A single node commits 2 commands per epoch.
+    let mut context0 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 2);
+    let config = NodeConfig {
+        delta: Duration(20),
+        ..NodeConfig::default()
+    };
+    let mut node0 = NodeState::make_initial_state(&context0, config.clone(), NodeTime(0));
+    let mut clock = NodeTime(0);
+    while node0.epoch_id() < EpochId(2) {
+        clock = clock + Duration(1);
+        node0.update_node(&mut context0, clock);
+    }
+
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 2);
+    let mut node1 = NodeState::make_initial_state(&context1, config, NodeTime(0));
+    let request = node1.create_request(&context1);
+    let response = block_on(node0.handle_request(&mut context0, request));
+    assert_eq!(response.records.len(), 3);
+    block_on(node1.handle_response(&mut context1, response, clock));
+    // All the epoch transitions were applied.
+    assert_eq!(node1.epoch_id(), EpochId(2));
+    // Commits of the latest epoch are left for the main handler.
+    node1.update_node(&mut context1, clock);
+    assert_eq!(context1.committed_history(), context0.committed_history());
+    assert_eq!(
+        context1.last_committed_state(),
+        context0.last_committed_state()
+    );
+
+    // Empty responses are ignored.
+    let response = DataSyncResponse {
+        current_epoch: EpochId(2),
+        records: Vec::new(),
+    };
+    block_on(node1.handle_response(&mut context1, response, clock));
+    assert_eq!(node1.epoch_id(), EpochId(2));
+}
+
+#[test]
+fn test_max_epochs_per_response() {
+    // A single node commits 2 commands per epoch.
+    let mut context0 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 2);
+    let config = NodeConfig {
+        delta: Duration(20),
+        max_epochs_per_response: Some(1),
+        ..NodeConfig::default()
+    };
+    let mut node0 = NodeState::make_initial_state(&context0, config.clone(), NodeTime(0));
+    let mut clock = NodeTime(0);
+    while node0.epoch_id() < EpochId(3) {
+        clock = clock + Duration(1);
+        node0.update_node(&mut context0, clock);
+    }
+
+    // A node 3 epochs behind catches up one epoch per round trip.
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 2);
+    let mut node1 = NodeState::make_initial_state(&context1, config, NodeTime(0));
+    for epoch in 1..4 {
+        let request = node1.create_request(&context1);
+        let response = block_on(node0.handle_request(&mut context0, request));
+        assert_eq!(response.records.len(), 1);
+        assert_eq!(response.records[0].0, EpochId(epoch - 1));
+        block_on(node1.handle_response(&mut context1, response, clock));
+        assert_eq!(node1.epoch_id(), EpochId(epoch));
+    }
+    node1.update_node(&mut context1, clock);
+    assert_eq!(context1.committed_history(), context0.committed_history());
+}
+
+#[test]
+fn test_max_records_per_response() {
+    // A single node commits 5 commands per epoch.
+    let mut context0 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 5);
+    let config = NodeConfig {
+        delta: Duration(20),
+        max_records_per_response: Some(4),
+        ..NodeConfig::default()
+    };
+    let mut node0 = NodeState::make_initial_state(&context0, config.clone(), NodeTime(0));
+    let mut clock = NodeTime(0);
+    while node0.epoch_id() < EpochId(2) {
+        clock = clock + Duration(1);
+        node0.update_node(&mut context0, clock);
+    }
+
+    // A node 2 epochs behind catches up over many bounded responses.
+    let mut context1 = SimulatedContext::new(Author(0), /* num_nodes */ 1, 5);
+    let mut node1 = NodeState::make_initial_state(&context1, config, NodeTime(0));
+    let mut round_trips = 0;
+    while node1.global_highest_round() < node0.global_highest_round() {
+        assert!(round_trips < 100, "Catching up should make progress");
+        let request = node1.create_request(&context1);
+        let response = block_on(node0.handle_request(&mut context0, request));
+        let record_count: usize = response
+            .records
+            .iter()
+            .map(|(_, records)| records.len())
+            .sum();
+        assert!(record_count <= 4);
+        block_on(node1.handle_response(&mut context1, response, clock));
+        round_trips += 1;
+    }
+    assert!(round_trips > 2);
+    node1.update_node(&mut context1, clock);
+    assert_eq!(node1.epoch_id(), EpochId(2));
+    assert_eq!(context1.committed_history(), context0.committed_history());
+}
+
+#[test]
+fn test_commit_certificate_of_previous_epoch() {
+    // A single node commits 2 commands per epoch.
+    let mut context = SimulatedContext::new(Author(0), /* num_nodes */ 1, 2);
+    let config = NodeConfig {
+        delta: Duration(20),
+        ..NodeConfig::default()
+    };
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    let mut clock = NodeTime(0);
+    while node.epoch_id() < EpochId(1) {
+        clock = clock + Duration(1);
+        node.update_node(&mut context, clock);
+    }
+    // Nothing was committed in the new epoch yet.
+    assert!(node.record_store().highest_commit_certificate().is_none());
+    let previous_certificate = node
+        .record_store_at(EpochId(0))
+        .unwrap()
+        .highest_commit_certificate()
+        .cloned();
+    assert!(previous_certificate.is_some());
+
+    // The notification forwards the commit certificate of the previous epoch.
+    let notification = node.create_notification(&context);
+    assert_eq!(
+        notification.highest_commit_certificate,
+        previous_certificate
+    );
+    assert_eq!(
+        notification
+            .highest_commit_certificate
+            .unwrap()
+            .value
+            .epoch_id,
+        EpochId(0)
+    );
+}
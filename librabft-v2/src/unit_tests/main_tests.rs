@@ -0,0 +1,40 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+fn parse_arguments(command_line: &[&str], config: &str) -> CliArguments {
+    let matches = CliArguments::clap().get_matches_from(
+        std::iter::once("librabft_simulator").chain(command_line.iter().copied()),
+    );
+    let file = toml::from_str(config).unwrap();
+    CliArguments::from_clap(&matches).complete(&matches, file)
+}
+
+#[test]
+fn test_command_line_overrides_config_file() {
+    let config = r#"
+        nodes = 4
+        delta = 30
+        seed = 3
+        max_clock = 50
+        dump_dag = "dags"
+    "#;
+    let args = parse_arguments(&["--seed", "7", "--max_clock", "1000"], config);
+    // Flags win over the file, even when they are given their default value.
+    assert_eq!(args.seed, 7);
+    assert_eq!(args.max_clock, 1000);
+    // The file wins over the defaults.
+    assert_eq!(args.nodes, 4);
+    assert_eq!(args.delta, 30);
+    assert_eq!(args.dump_dag, Some("dags".to_string()));
+    // Other arguments keep their defaults.
+    assert_eq!(args.mean, 10.0);
+    assert_eq!(args.lambda, 0.5);
+    assert_eq!(args.create_csv, None);
+}
+
+#[test]
+fn test_config_file_rejects_unknown_keys() {
+    assert!(toml::from_str::<FileArguments>("node = 4").is_err());
+}
@@ -2,9 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use crate::{node::NodeConfig, record::BlockHash};
-use bft_lib::{simulated_context::*, smr_context::*};
+use crate::{
+    node::NodeConfig, record::BlockHash, simulation::SimulatorBuilder, testing::RecordBuilder,
+};
+use bft_lib::{simulated_context::*, simulator, smr_context::*};
 use futures::executor::block_on;
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 #[test]
 fn test_node() {
@@ -25,7 +32,7 @@ fn test_node() {
 
     // Make a sequence of blocks / QCs
     let cmd = context.fetch().unwrap();
-    let b0 = SignedValue::make(
+    let b0 = sign_record(
         &mut context,
         Block_ {
             command: cmd.clone(),
@@ -39,10 +46,17 @@ fn test_node() {
     let block_hash = BlockHash(context.hash(&b0.value));
 
     let state = context
-        .compute(&initial_state, cmd, NodeTime(1), None, Vec::new())
+        .compute(
+            &initial_state,
+            cmd,
+            NodeTime(1),
+            Author(0),
+            None,
+            Vec::new(),
+        )
         .unwrap();
 
-    let v0 = SignedValue::make(
+    let v0 = sign_record(
         &mut context,
         Vote_::<SimulatedContext> {
             epoch_id,
@@ -53,7 +67,7 @@ fn test_node() {
             committed_state: None,
         },
     );
-    let qc0 = SignedValue::make(
+    let qc0 = sign_record(
         &mut context,
         QuorumCertificate_ {
             epoch_id,
@@ -74,3 +88,767 @@ fn test_node() {
         qc_hash
     );
 }
+
+#[test]
+fn test_node_with_record_builder() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 2,
+    );
+    let mut node = NodeState::make_initial_state(&context, NodeConfig::default(), NodeTime(0));
+    let epoch_id = EpochId(0);
+    let mut builder = RecordBuilder::new(&context, epoch_id);
+
+    let cmd = context.fetch().unwrap();
+    let b0 = builder.block(&mut context, Round(1), cmd, builder.initial_hash());
+    let block_hash = BlockHash(context.hash(&b0.value));
+    let v0 = builder.vote(&mut context, block_hash);
+    let qc0 = builder.qc(&mut context, vec![v0]);
+    let qc_hash = QuorumCertificateHash(context.hash(&qc0.value));
+
+    node.insert_network_record(epoch_id, Record::Block(b0), &mut context);
+    node.insert_network_record(epoch_id, Record::QuorumCertificate(qc0), &mut context);
+    assert_eq!(node.record_store.highest_quorum_certificate_hash(), qc_hash);
+}
+
+#[test]
+fn test_should_send_deduplicates_recipients() {
+    let make_context = |index| {
+        SimulatedContext::new(
+            Author(index),
+            /* num_nodes */ 4,
+            /* max commands per epoch */ 10,
+        )
+    };
+    let epoch_id = EpochId(0);
+    let make_node = |context: &SimulatedContext| {
+        NodeState::make_initial_state(context, NodeConfig::default(), NodeTime(0))
+    };
+    let leader = PacemakerState::leader(&make_node(&make_context(0)).record_store, Round(1));
+    let voter = if leader == Author(0) { 1 } else { 0 };
+    let mut voter_context = make_context(voter);
+    let mut voter_node = make_node(&voter_context);
+
+    // The proposal of the leader arrives before the first update of the voter.
+    let mut leader_context = make_context(leader.0);
+    let mut builder = RecordBuilder::new(&leader_context, epoch_id);
+    let cmd = leader_context.fetch().unwrap();
+    let block = builder.block(&mut leader_context, Round(1), cmd, builder.initial_hash());
+    voter_node.insert_network_record(epoch_id, Record::Block(block), &mut voter_context);
+    // Entering round 1 synchronizes with the leader, and voting sends the vote to the author
+    // of the block: both target the leader, which is contacted once.
+    let actions = voter_node.update_node(&mut voter_context, NodeTime(1));
+    assert!(voter_node
+        .record_store
+        .current_vote(Author(voter))
+        .is_some());
+    assert_eq!(actions.should_send, vec![leader]);
+}
+
+#[test]
+fn test_should_send_accumulates_recipients() {
+    // With a VRF, the author of a proposal extending an older QC differs from the leader
+    // elected with the highest QC.
+    let config = NodeConfig {
+        leader_election: LeaderElectionPolicy::Vrf,
+        ..NodeConfig::default()
+    };
+    let mut contexts: Vec<_> = (0..4)
+        .map(|index| {
+            SimulatedContext::new(
+                Author(index),
+                /* num_nodes */ 4,
+                /* max commands per epoch */ 10,
+            )
+        })
+        .collect();
+    let epoch_id = EpochId(0);
+    let store =
+        NodeState::make_initial_state(&contexts[0], config.clone(), NodeTime(0)).record_store;
+    let mut builder = RecordBuilder::new(&contexts[0], epoch_id);
+    let initial_hash = builder.initial_hash();
+
+    // A QC is formed at round 1. The election of round 2 depends on its hash, hence the
+    // leader proposes new commands until the two elections of round 2 disagree.
+    let leader1 = store.leader(Round(1), initial_hash);
+    let proposer = store.leader(Round(2), initial_hash);
+    let (block1, qc1, leader2) = loop {
+        let cmd = contexts[leader1.0].fetch().unwrap();
+        let block1 = builder.block(&mut contexts[leader1.0], Round(1), cmd, initial_hash);
+        let block1_hash = BlockHash(contexts[0].hash(&block1.value));
+        let votes = (0..3)
+            .map(|index| builder.vote(&mut contexts[index], block1_hash))
+            .collect();
+        let qc1 = builder.qc(&mut contexts[leader1.0], votes);
+        let qc1_hash = QuorumCertificateHash(contexts[0].hash(&qc1.value));
+        let leader2 = store.leader(Round(2), qc1_hash);
+        if leader2 != proposer {
+            break (block1, qc1, leader2);
+        }
+    };
+
+    // A proposal for round 2 extends the initial QC instead.
+    let cmd = contexts[proposer.0].fetch().unwrap();
+    let block2 = builder.block(&mut contexts[proposer.0], Round(2), cmd, initial_hash);
+
+    // The voter learns the QC of round 1 and the proposal of round 2.
+    let voter = (0..4)
+        .map(Author)
+        .find(|author| *author != leader2 && *author != proposer)
+        .unwrap();
+    let voter_context = &mut contexts[voter.0];
+    let mut voter_node = NodeState::make_initial_state(voter_context, config, NodeTime(0));
+    voter_node.insert_network_record(epoch_id, Record::Block(block1), voter_context);
+    voter_node.insert_network_record(epoch_id, Record::QuorumCertificate(qc1), voter_context);
+    voter_node.insert_network_record(epoch_id, Record::Block(block2), voter_context);
+    // Entering round 2 synchronizes with its leader, and voting sends the vote to the author
+    // of the block: both are contacted.
+    let actions = voter_node.update_node(voter_context, NodeTime(1));
+    assert!(voter_node.record_store.current_vote(voter).is_some());
+    let mut expected = vec![leader2, proposer];
+    expected.sort();
+    assert_eq!(actions.should_send, expected);
+}
+
+#[test]
+fn test_global_highest_round() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let node = sim.simulated_node(Author(0)).node();
+    assert!(node.epoch_id() >= EpochId(1));
+    assert_eq!(
+        node.global_highest_round(),
+        (node.epoch_id(), node.record_store().current_round())
+    );
+    let previous_store = node.record_store_at(EpochId(0)).unwrap();
+    assert!(node.global_highest_round() > (EpochId(0), previous_store.current_round()));
+}
+
+#[test]
+fn test_commit_audit_log() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(500), None);
+    for index in 0..4 {
+        let simulated_node = sim.simulated_node(Author(index));
+        let (state, signers) = simulated_node.context().commit_audit_log().last().unwrap();
+        let node = simulated_node.node();
+        let certificate = node.record_store().highest_commit_certificate().unwrap();
+        assert_eq!(Some(state), certificate.value.committed_state.as_ref());
+        let authors: Vec<_> = certificate.value.votes.iter().map(|(a, _)| *a).collect();
+        assert_eq!(*signers, authors);
+    }
+}
+
+#[test]
+fn test_withhold_proposals() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_startup_mode(simulator::StartupMode::Simultaneous)
+    .build();
+    let leader = PacemakerState::leader(
+        sim.simulated_node(Author(0)).node().record_store(),
+        Round(1),
+    );
+    sim.withhold_proposals(vec![leader]);
+    sim.loop_until(simulator::GlobalTime(500), None);
+    for index in 0..4 {
+        let store = sim.simulated_node(Author(index)).node().record_store();
+        let highest_quorum_certificate = store.highest_quorum_certificate().unwrap();
+        let certified_rounds: Vec<_> = store
+            .ancestry(highest_quorum_certificate.value.certified_block_hash)
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::QuorumCertificate(qc) => Some(qc.value.round),
+                _ => None,
+            })
+            .collect();
+        // The first round was abandoned after a timeout, then other leaders made progress.
+        assert!(!certified_rounds.contains(&Round(1)));
+        assert!(store.highest_timeout_certificate_round() >= Round(1));
+        assert!(store.highest_quorum_certificate_round() > Round(1));
+    }
+}
+
+#[test]
+fn test_transaction_arrival() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_startup_mode(simulator::StartupMode::Simultaneous)
+    .with_context_setup(|context| {
+        context.with_transaction_arrival(TransactionArrival {
+            arrival_rate: 0.001,
+        })
+    })
+    .build();
+    let contexts = sim.loop_until(simulator::GlobalTime(3000), None);
+    for context in contexts {
+        // Commits still happen once transactions arrive.
+        assert!(!context.committed_history().is_empty());
+    }
+    for index in 0..4 {
+        let store = sim.simulated_node(Author(index)).node().record_store();
+        let highest_quorum_certificate = store.highest_quorum_certificate().unwrap();
+        let certified_rounds = store
+            .ancestry(highest_quorum_certificate.value.certified_block_hash)
+            .into_iter()
+            .filter(|record| matches!(record, Record::QuorumCertificate(_)))
+            .count();
+        // Most leaders had no transaction to propose and their rounds timed out.
+        assert!(2 * certified_rounds < highest_quorum_certificate.value.round.0);
+        assert!(store.highest_timeout_certificate_round() > Round(0));
+    }
+}
+
+#[test]
+fn test_observer() {
+    let builder = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 3, /* commands per epoch */ 100,
+    );
+    let mut sim = builder.build();
+    // The observer only knows the 3 validators, hence it has no voting rights.
+    sim.add_observer(Author(3), builder.initial_context(Author(3)));
+    sim.loop_until(simulator::GlobalTime(1000), None);
+
+    let validator_history = sim.simulated_node(Author(0)).context().committed_history();
+    let observer_history = sim.simulated_node(Author(3)).context().committed_history();
+    assert!(!observer_history.is_empty());
+    let common_len = std::cmp::min(validator_history.len(), observer_history.len());
+    assert!(validator_history.len() - common_len <= 3);
+    assert_eq!(
+        observer_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>(),
+        validator_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>()
+    );
+    // The observer never voted nor timed out.
+    for index in 0..4 {
+        let store = sim.simulated_node(Author(index)).node().record_store();
+        assert!(store.current_vote(Author(3)).is_none());
+        assert!(store
+            .timeouts()
+            .iter()
+            .all(|timeout| timeout.value.author != Author(3)));
+    }
+}
+
+#[test]
+fn test_late_joiner() {
+    let builder = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 10,
+    );
+    let mut sim = builder.build();
+    sim.loop_until(simulator::GlobalTime(500), None);
+    assert!(!sim
+        .simulated_node(Author(0))
+        .context()
+        .committed_history()
+        .is_empty());
+    // The new node starts from the initial state, with an empty store.
+    let context = builder.initial_context(Author(4));
+    let first_epoch = sim.add_late_joiner(Author(4), simulator::GlobalTime(600), context);
+    assert!(first_epoch > EpochId(1));
+
+    // The late joiner reaches the epoch of the network within a bounded window.
+    let caught_up = (700..=3100).step_by(100).any(|t| {
+        sim.loop_until(simulator::GlobalTime(t), None);
+        let validator = sim.simulated_node(Author(0)).node();
+        let joiner = sim.simulated_node(Author(4)).node();
+        joiner.epoch_id() > first_epoch && joiner.epoch_id() >= validator.epoch_id()
+    });
+    assert!(caught_up);
+
+    // The late joiner caught up with the committed history of the network.
+    let validator_history = sim.simulated_node(Author(0)).context().committed_history();
+    let joiner_history = sim.simulated_node(Author(4)).context().committed_history();
+    let common_len = std::cmp::min(validator_history.len(), joiner_history.len());
+    assert!(common_len > 10 * first_epoch.0);
+    assert_eq!(
+        joiner_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>(),
+        validator_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>()
+    );
+    // Past quorums are unaffected while the new node votes in later epochs.
+    let validator = sim.simulated_node(Author(0)).node();
+    assert_eq!(
+        validator
+            .record_store_at(EpochId(first_epoch.0 - 1))
+            .unwrap()
+            .voting_rights(Author(4)),
+        0
+    );
+    assert!(validator.epoch_id() > first_epoch);
+    assert_eq!(validator.record_store().voting_rights(Author(4)), 1);
+}
+
+#[test]
+fn test_leader_histogram() {
+    let config = NodeConfig {
+        leader_election: LeaderElectionPolicy::RoundRobin,
+        ..NodeConfig::default()
+    };
+    // Round-robin elections start over at each epoch, hence the run stays in the first epoch.
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 100000,
+    )
+    .with_config(config)
+    .build();
+    sim.loop_until(simulator::GlobalTime(5000), None);
+    let node = sim.simulated_node(Author(0)).node();
+    assert_eq!(node.epoch_id(), EpochId(0));
+    let leaders = simulator::CommittedLeaders::committed_leaders(node);
+    let context = sim.simulated_node(Author(0)).context();
+    assert!(leaders.len() >= context.committed_history().len());
+    // Each committed block comes from the leader elected for its round.
+    for (epoch_id, round, author) in &leaders {
+        let store = node.record_store_at(*epoch_id).unwrap();
+        assert_eq!(*author, PacemakerState::leader(store, *round));
+    }
+    // Leaders take turns, hence each node drives the commits of the rounds that it leads, up
+    // to the rounds that were not committed (e.g. after a timeout).
+    let histogram = sim.leader_histogram();
+    assert_eq!(histogram.len(), 4);
+    let total: usize = histogram.values().sum();
+    assert!(total >= leaders.len());
+    let highest_committed_round = (0..4)
+        .map(|index| {
+            simulator::ActiveRound::highest_committed_round(
+                sim.simulated_node(Author(index)).node(),
+            )
+        })
+        .max()
+        .unwrap();
+    let uncommitted_rounds = highest_committed_round.0 - total;
+    for (author, count) in histogram {
+        let led_rounds = (1..=highest_committed_round.0)
+            .filter(|round| round % 4 == author.0)
+            .count();
+        assert!(count > 0);
+        assert!(count <= led_rounds);
+        assert!(led_rounds - count <= uncommitted_rounds);
+    }
+}
+
+#[test]
+fn test_epoch_chaining() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let simulated_node = sim.simulated_node(Author(0));
+    let (node, context) = (simulated_node.node(), simulated_node.context());
+    assert!(node.epoch_id() >= EpochId(2));
+    // Each epoch descends from the last commit of the previous one.
+    for (epoch_id, store) in &node.past_record_stores {
+        if let Some(previous_epoch) = epoch_id.previous() {
+            let previous_store = node.record_store_at(previous_epoch).unwrap();
+            assert!(store.follows_epoch(previous_store, context));
+        }
+    }
+    let previous_store = node
+        .record_store_at(node.epoch_id().previous().unwrap())
+        .unwrap();
+    assert!(node.record_store.follows_epoch(previous_store, context));
+    let previous_store = node.record_store_at(EpochId(0)).unwrap();
+    let make_store = |state: State| {
+        RecordStoreState::new(
+            QuorumCertificateHash(context.hash(&EpochId(1))),
+            state.clone(),
+            EpochId(1),
+            context.configuration(&state),
+            &NodeConfig::default(),
+        )
+    };
+    // The actual transition is consistent.
+    let store = make_store(context.epoch_boundary_state(EpochId(1)).unwrap());
+    assert!(store.follows_epoch(previous_store, context));
+    // A fabricated initial state is not.
+    let store = make_store(context.epoch_boundary_state(EpochId(2)).unwrap());
+    assert!(!store.follows_epoch(previous_store, context));
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn test_rewind_to() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 100,
+    );
+    let mut node = NodeState::make_initial_state(&context, NodeConfig::default(), NodeTime(0));
+    // As the single node of the committee, we form a QC at each update.
+    for clock in 0..6 {
+        node.update_node(&mut context, NodeTime(clock));
+    }
+    let committed_round = node.record_store.highest_committed_round();
+    assert!(committed_round > Round(0));
+    let final_round = node
+        .record_store
+        .highest_commit_certificate()
+        .unwrap()
+        .value
+        .round;
+    // Propose a block at the current round without certifying it.
+    let known_hash_count = node.record_store.known_hashes().count();
+    let previous_qc_hash = node.record_store.highest_quorum_certificate_hash();
+    node.record_store
+        .propose_block(&mut context, previous_qc_hash, NodeTime(6));
+    assert_eq!(
+        node.record_store.known_hashes().count(),
+        known_hash_count + 1
+    );
+
+    let error = node.rewind_to(committed_round).unwrap_err();
+    assert!(error.to_string().contains("Refusing to rewind"));
+    node.rewind_to(final_round).unwrap();
+    let store = &node.record_store;
+    assert_eq!(store.known_hashes().count(), known_hash_count);
+    assert_eq!(store.highest_quorum_certificate_round(), final_round);
+    assert_eq!(store.current_round(), final_round + 1);
+    assert_eq!(store.highest_committed_round(), committed_round);
+}
+
+#[test]
+fn test_commit_logging() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 100,
+    );
+    let mut node = NodeState::make_initial_state(&context, NodeConfig::default(), NodeTime(0));
+    let logs = capture_logs(|| {
+        // As the single node of the committee, we form a QC at each update.
+        for clock in 0..3 {
+            node.update_node(&mut context, NodeTime(clock));
+        }
+    });
+    // The QC of round 3 completes the 3-chain that commits round 1.
+    assert_eq!(node.record_store().highest_committed_round(), Round(1));
+    assert!(logs.iter().any(|line| line.contains(
+        "at round Round(3) commits round Round(1) (3-chain rounds: Round(1), Round(2), Round(3))"
+    )));
+}
+
+fn highest_quorum_certificate_round_after_update(
+    propose_when_empty: ProposeWhenEmpty,
+    clock: NodeTime,
+) -> Round {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    )
+    .with_empty_mempool(true);
+    let config = NodeConfig {
+        delta: Duration(20),
+        propose_when_empty,
+        ..NodeConfig::default()
+    };
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    node.update_node(&mut context, NodeTime(0));
+    node.update_node(&mut context, clock);
+    node.record_store().highest_quorum_certificate_round()
+}
+
+#[test]
+fn test_propose_when_empty() {
+    // One block is proposed and certified at each update.
+    assert_eq!(
+        highest_quorum_certificate_round_after_update(ProposeWhenEmpty::Always, NodeTime(10)),
+        Round(2)
+    );
+    assert_eq!(
+        highest_quorum_certificate_round_after_update(ProposeWhenEmpty::Never, NodeTime(10)),
+        Round(0)
+    );
+    let policy = ProposeWhenEmpty::AfterTimeout(Duration(10));
+    assert_eq!(
+        highest_quorum_certificate_round_after_update(policy, NodeTime(5)),
+        Round(0)
+    );
+    assert_eq!(
+        highest_quorum_certificate_round_after_update(policy, NodeTime(10)),
+        Round(1)
+    );
+}
+
+thread_local! {
+    static CAPTURED_LOGS: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Number of threads currently capturing logs.
+static ACTIVE_CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Logger capturing the messages emitted by the current thread, if requested.
+struct TestLogger;
+
+impl log::Log for TestLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        CAPTURED_LOGS.with(|logs| logs.borrow().is_some())
+    }
+
+    fn log(&self, record: &log::Record) {
+        CAPTURED_LOGS.with(|logs| {
+            if let Some(logs) = logs.borrow_mut().as_mut() {
+                logs.push(record.args().to_string());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Return the messages logged by the current thread while running `f`. Other tests are not
+/// affected: logging is only enabled while a capture is active, and only for the capturing
+/// threads.
+fn capture_logs<F: FnOnce()>(f: F) -> Vec<String> {
+    // Another logger may have been installed already.
+    let _ = log::set_logger(&TestLogger);
+    ACTIVE_CAPTURES.fetch_add(1, Ordering::SeqCst);
+    log::set_max_level(log::LevelFilter::Trace);
+    CAPTURED_LOGS.with(|logs| *logs.borrow_mut() = Some(Vec::new()));
+    f();
+    let logs = CAPTURED_LOGS.with(|logs| logs.borrow_mut().take().unwrap());
+    if ACTIVE_CAPTURES.fetch_sub(1, Ordering::SeqCst) == 1 {
+        log::set_max_level(log::LevelFilter::Off);
+        // Another capture may have started in the meantime.
+        if ACTIVE_CAPTURES.load(Ordering::SeqCst) > 0 {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+    }
+    logs
+}
+
+#[test]
+fn test_backward_clock() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    );
+    let config = NodeConfig {
+        delta: Duration(20),
+        ..NodeConfig::default()
+    };
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    node.update_node(&mut context, NodeTime(100));
+    let logs = capture_logs(|| {
+        node.update_node(&mut context, NodeTime(50));
+    });
+    assert!(logs
+        .iter()
+        .any(|line| line.contains("Clock moved backward from @100 to @50")));
+    // The node keeps using the highest clock seen so far.
+    assert_eq!(node.last_seen_clock, NodeTime(100));
+    assert!(node.latest_query_all_time <= NodeTime(100));
+    node.update_node(&mut context, NodeTime(150));
+    assert_eq!(node.last_seen_clock, NodeTime(150));
+}
+
+#[test]
+fn test_update_actions_logging() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    );
+    let config = NodeConfig {
+        delta: Duration(20),
+        ..NodeConfig::default()
+    };
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    let logs = capture_logs(|| {
+        node.update_node(&mut context, NodeTime(0));
+    });
+    // As the single node of the committee, we propose, vote, and form a QC right away.
+    assert_eq!(
+        node.record_store().highest_quorum_certificate_round(),
+        Round(1)
+    );
+    assert!(logs.iter().any(|line| line.contains(
+        "Update actions: pacemaker (broadcast: true, query_all: false), new QC (broadcast: true)"
+    )));
+}
+
+#[test]
+fn test_rejected_votes() {
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    );
+    let config = NodeConfig {
+        delta: Duration(20),
+        ..NodeConfig::default()
+    };
+    let mut node = NodeState::make_initial_state(&context, config.clone(), NodeTime(0));
+    // Pretend that we are locked on round 1 already: our own proposal for round 1 extends the
+    // initial QC of round 0, hence violates the lock.
+    node.locked_round = Round(1);
+    node.update_node(&mut context, NodeTime(0));
+    node.update_node(&mut context, NodeTime(1));
+    assert_eq!(
+        node.rejected_votes(),
+        RejectedVotes {
+            locked_round: 1,
+            already_voted: 0,
+        }
+    );
+    // We did not vote, hence no QC was formed.
+    assert!(node.record_store().current_vote(Author(0)).is_none());
+    assert_eq!(
+        node.record_store().highest_quorum_certificate_round(),
+        Round(0)
+    );
+
+    // Same with a vote at round 1 already.
+    let mut context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    );
+    let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
+    node.latest_voted_round = Round(1);
+    node.update_node(&mut context, NodeTime(0));
+    assert_eq!(
+        node.rejected_votes(),
+        RejectedVotes {
+            locked_round: 0,
+            already_voted: 1,
+        }
+    );
+    assert!(node.record_store().current_vote(Author(0)).is_none());
+}
+
+/// Run 7 nodes whose broadcasts only reach 4 other nodes and return how many times (out of
+/// regular checkpoints) all nodes agreed on the highest QC round, together with the logs.
+fn run_partial_broadcast(echo_quorum_certificates: bool) -> (usize, Vec<String>) {
+    let config = NodeConfig {
+        echo_quorum_certificates,
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 7, /* commands per epoch */ 1000,
+    )
+    .with_config(config)
+    .with_startup_mode(simulator::StartupMode::Simultaneous)
+    .build()
+    .with_message_schedule(simulator::MessageSchedule::PartialBroadcast { fanout: 4 });
+    let mut agreements = 0;
+    let logs = capture_logs(|| {
+        for t in (50..400).step_by(5) {
+            sim.loop_until(simulator::GlobalTime(t), None);
+            let rounds: BTreeSet<_> = (0..7)
+                .map(|index| {
+                    let node = sim.simulated_node(Author(index)).node();
+                    node.record_store().highest_quorum_certificate_round()
+                })
+                .collect();
+            if rounds.len() == 1 {
+                agreements += 1;
+            }
+        }
+    });
+    (agreements, logs)
+}
+
+#[test]
+fn test_echo_quorum_certificates() {
+    let (agreements_without_echo, logs) = run_partial_broadcast(false);
+    assert!(!logs.iter().any(|line| line.contains("Echoing QC")));
+
+    let (agreements, logs) = run_partial_broadcast(true);
+    // Relayed QCs reach the nodes that the leader did not contact directly.
+    assert!(agreements > agreements_without_echo);
+    let echoes: Vec<_> = logs
+        .iter()
+        .filter(|line| line.contains("Echoing QC"))
+        .collect();
+    assert!(!echoes.is_empty());
+    // Each node echoes a QC at most once.
+    let distinct_echoes: BTreeSet<_> = echoes.iter().collect();
+    assert_eq!(distinct_echoes.len(), echoes.len());
+}
+
+#[test]
+fn test_reconfiguration() {
+    // Author 4 joins the committee in epoch 1 and author 0 leaves it in epoch 2.
+    let members = |authors: std::ops::Range<usize>| -> Vec<(Author, usize)> {
+        authors.map(|index| (Author(index), 1)).collect()
+    };
+    let builder = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 10,
+    )
+    .with_context_setup(move |context| {
+        context
+            .with_reconfiguration(EpochId(1), members(0..5))
+            .with_reconfiguration(EpochId(2), members(1..5))
+    });
+    let mut sim = builder.build();
+    sim.loop_until(simulator::GlobalTime(500), None);
+    // The new node starts from the initial state, with an empty store, and catches up through
+    // data sync.
+    sim.add_observer(Author(4), builder.initial_context(Author(4)));
+    sim.loop_until(simulator::GlobalTime(3000), None);
+
+    let validator = sim.simulated_node(Author(1)).node();
+    assert!(validator.epoch_id() > EpochId(2));
+    let voting_rights = |epoch_id, author| {
+        validator
+            .record_store_at(epoch_id)
+            .unwrap()
+            .voting_rights(author)
+    };
+    assert_eq!(voting_rights(EpochId(0), Author(4)), 0);
+    assert_eq!(voting_rights(EpochId(1), Author(4)), 1);
+    assert_eq!(voting_rights(EpochId(1), Author(0)), 1);
+    assert_eq!(voting_rights(EpochId(2), Author(0)), 0);
+    // The new node caught up with the committed history of the network.
+    let validator_history = sim.simulated_node(Author(1)).context().committed_history();
+    let joiner_history = sim.simulated_node(Author(4)).context().committed_history();
+    let common_len = std::cmp::min(validator_history.len(), joiner_history.len());
+    assert!(common_len > 20);
+    assert_eq!(
+        joiner_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>(),
+        validator_history[..common_len]
+            .iter()
+            .map(|(command, _)| command)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        sim.simulated_node(Author(4)).node().epoch_id(),
+        validator.epoch_id()
+    );
+    // Leaders are only elected among the members of each epoch.
+    let leaders = simulator::CommittedLeaders::committed_leaders(validator);
+    assert!(leaders.iter().any(|(_, _, author)| *author == Author(4)));
+    for (epoch_id, _, author) in leaders {
+        if epoch_id == EpochId(0) {
+            assert_ne!(author, Author(4));
+        }
+        if epoch_id >= EpochId(2) {
+            assert_ne!(author, Author(0));
+        }
+    }
+}
@@ -1,2 +1,75 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 // SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::node::NodeConfig;
+use bft_lib::{simulated_context::*, smr_context::*};
+
+#[test]
+fn test_leader_votes_on_proposal_right_away() {
+    let context = SimulatedContext::new(
+        Author(0),
+        /* num_nodes */ 1,
+        /* max commands per epoch */ 10,
+    );
+    let epoch_id = EpochId(0);
+    let state = context.last_committed_state();
+    let record_store = RecordStoreState::new(
+        QuorumCertificateHash(context.hash(&epoch_id)),
+        state.clone(),
+        epoch_id,
+        context.configuration(&state),
+        &NodeConfig::default(),
+    );
+    let mut pacemaker = PacemakerState::<SimulatedContext>::new(
+        epoch_id,
+        NodeTime(0),
+        Duration(20),
+        /* gamma */ 2.0,
+        /* lambda */ 0.5,
+        ProposeWhenEmpty::Always,
+    );
+    // The only node is the leader of the first round.
+    let clock = NodeTime(5);
+    let actions = pacemaker.update_pacemaker(
+        Author(0),
+        epoch_id,
+        &record_store,
+        NodeTime(0),
+        /* has_pending_commands */ true,
+        clock,
+    );
+    assert_eq!(pacemaker.active_leader(), Some(Author(0)));
+    assert!(actions.should_propose_block.is_some());
+    assert_eq!(actions.next_scheduled_update, clock);
+    assert!(actions.schedules_vote_on_proposal(clock));
+
+    // A proposal without an immediate update breaks the invariant.
+    let delayed_actions = PacemakerUpdateActions::<SimulatedContext> {
+        next_scheduled_update: clock + Duration(1),
+        ..actions
+    };
+    assert!(!delayed_actions.schedules_vote_on_proposal(clock));
+}
+
+#[test]
+fn test_adaptive_timeout_average() {
+    let mut adaptive_timeout = AdaptiveTimeout::new(0.5);
+    assert_eq!(adaptive_timeout.average_latency(), None);
+    assert_eq!(adaptive_timeout.base_timeout(Duration(10)), Duration(10));
+    adaptive_timeout.observe(Round(1), NodeTime(100));
+    assert_eq!(adaptive_timeout.average_latency(), None);
+    // Two rounds in 40 units of time.
+    adaptive_timeout.observe(Round(3), NodeTime(140));
+    assert_eq!(adaptive_timeout.average_latency(), Some(Duration(20)));
+    assert_eq!(adaptive_timeout.base_timeout(Duration(10)), Duration(40));
+    assert_eq!(adaptive_timeout.base_timeout(Duration(50)), Duration(50));
+    // Latencies are measured from the first time a QC was observed.
+    adaptive_timeout.observe(Round(3), NodeTime(200));
+    adaptive_timeout.observe(Round(4), NodeTime(220));
+    assert_eq!(adaptive_timeout.average_latency(), Some(Duration(50)));
+    // A new epoch restarts the rounds.
+    adaptive_timeout.observe(Round(1), NodeTime(300));
+    adaptive_timeout.observe(Round(2), NodeTime(310));
+    assert_eq!(adaptive_timeout.average_latency(), Some(Duration(30)));
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
+use crate::pacemaker::PacemakerState;
 use bft_lib::{simulated_context::*, smr_context::*};
 
 struct SharedRecordStore {
@@ -27,11 +28,22 @@ impl SharedRecordStore {
                 state.clone(),
                 epoch_id,
                 contexts.get(&Author(0)).unwrap().configuration(&state),
+                &NodeConfig::default(),
             ),
             contexts,
         }
     }
 
+    fn with_leader_election(mut self, leader_election: LeaderElectionPolicy) -> Self {
+        self.store.leader_election = leader_election;
+        self
+    }
+
+    fn with_pruning_depth(mut self, pruning_depth: Option<usize>) -> Self {
+        self.store.pruning_depth = pruning_depth;
+        self
+    }
+
     fn create_timeout(&mut self, author_id: usize, round: Round) {
         let author = Author(author_id);
         self.store
@@ -41,7 +53,7 @@ impl SharedRecordStore {
     fn propose_block(
         &mut self,
         author_id: usize,
-        previous_qc_hash: QuorumCertificateHash<u64>,
+        previous_qc_hash: QuorumCertificateHash<SimulatedHashValue>,
         clock: NodeTime,
     ) {
         let author = Author(author_id);
@@ -52,7 +64,7 @@ impl SharedRecordStore {
         );
     }
 
-    fn create_vote(&mut self, author_id: usize, block_hash: BlockHash<u64>) -> bool {
+    fn create_vote(&mut self, author_id: usize, block_hash: BlockHash<SimulatedHashValue>) -> bool {
         let author = Author(author_id);
         self.store
             .create_vote(self.contexts.get_mut(&author).unwrap(), block_hash)
@@ -164,6 +176,25 @@ fn test_vote_with_quorum() {
     assert_eq!(store.current_timeouts.len(), 0);
 }
 
+#[test]
+fn test_records_of_non_members() {
+    let mut shared_store = SharedRecordStore::new(3, 20);
+    // Author 3 is not a member of the epoch.
+    shared_store.contexts.insert(
+        Author(3),
+        SimulatedContext::new(Author(3), /* num_nodes */ 3, /* epoch_ttl */ 20),
+    );
+    shared_store.propose_block(3, QuorumCertificateHash(0), NodeTime(1));
+    assert!(shared_store.store.blocks.is_empty());
+    let author = shared_store.leader(Round(1));
+    shared_store.propose_block(author.0, QuorumCertificateHash(0), NodeTime(2));
+    let proposed_hash = shared_store.store.current_proposed_block.unwrap();
+    shared_store.create_vote(3, proposed_hash);
+    assert!(shared_store.store.current_votes.is_empty());
+    shared_store.create_timeout(3, Round(1));
+    assert!(shared_store.store.current_timeouts.is_empty());
+}
+
 #[test]
 fn test_timeouts_no_tc() {
     let mut shared_store = SharedRecordStore::new(2, 20);
@@ -216,6 +247,98 @@ fn test_timeouts_with_tc() {
     assert_eq!(store.current_timeouts.len(), 0);
 }
 
+#[test]
+fn test_preview_proposal() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.make_round(NodeTime(10));
+    let leader = shared_store.leader(Round(2));
+    let previous_qc_hash = shared_store.store.highest_quorum_certificate_hash();
+    let (command, preview_qc_hash, round) = shared_store
+        .store
+        .preview_proposal(shared_store.contexts.get(&leader).unwrap())
+        .unwrap();
+    assert_eq!(preview_qc_hash, previous_qc_hash);
+    assert_eq!(round, Round(2));
+    // Previewing has no side effects.
+    assert_eq!(
+        shared_store
+            .store
+            .preview_proposal(shared_store.contexts.get(&leader).unwrap())
+            .unwrap()
+            .0,
+        command
+    );
+    assert!(shared_store.store.current_proposed_block.is_none());
+
+    shared_store.propose_block(leader.0, previous_qc_hash, NodeTime(20));
+    let block_hash = shared_store.store.current_proposed_block.unwrap();
+    let block = shared_store.store.block(block_hash).unwrap();
+    assert_eq!(block.value.command, command);
+    assert_eq!(
+        block.value.previous_quorum_certificate_hash,
+        preview_qc_hash
+    );
+    assert_eq!(block.value.round, round);
+}
+
+#[test]
+fn test_propose_on_stale_quorum_certificate() {
+    for strict_proposals in &[false, true] {
+        let mut shared_store = SharedRecordStore::new(4, 20);
+        shared_store.store.strict_proposals = *strict_proposals;
+        shared_store.make_round(NodeTime(1));
+        assert_eq!(
+            shared_store.store.highest_quorum_certificate_round(),
+            Round(1)
+        );
+        // The leader of round 2 proposes on top of the initial QC.
+        let leader = shared_store.leader(Round(2));
+        shared_store.propose_block(leader.0, QuorumCertificateHash(0), NodeTime(2));
+        // Only strict stores refuse the proposal.
+        assert_eq!(
+            shared_store.store.current_proposed_block.is_some(),
+            !*strict_proposals
+        );
+    }
+}
+
+#[test]
+fn test_late_quorum_certificate() {
+    for vote_retention in &[0, 2] {
+        let mut shared_store = SharedRecordStore::new(4, 20);
+        shared_store.store.vote_retention = *vote_retention;
+        let leader = shared_store.leader(Round(1));
+        shared_store.propose_block(leader.0, QuorumCertificateHash(0), NodeTime(1));
+        let proposed_hash = shared_store.store.current_proposed_block.unwrap();
+        // A quorum votes but the QC is not formed before a TC moves everyone to round 2.
+        for i in 0..3 {
+            assert!(shared_store.create_vote(i, proposed_hash));
+        }
+        shared_store.make_tc();
+        assert_eq!(shared_store.store.current_round(), Round(2));
+        assert_eq!(
+            shared_store.store.highest_quorum_certificate_round(),
+            Round(0)
+        );
+
+        let has_new_quorum_certificate = shared_store
+            .store
+            .check_for_new_quorum_certificate(shared_store.contexts.get_mut(&leader).unwrap());
+        // Only retained votes allow forming a QC for round 1.
+        assert_eq!(has_new_quorum_certificate, *vote_retention > 0);
+        let expected_round = if *vote_retention > 0 {
+            Round(1)
+        } else {
+            Round(0)
+        };
+        assert_eq!(
+            shared_store.store.highest_quorum_certificate_round(),
+            expected_round
+        );
+        assert_eq!(shared_store.store.current_round(), Round(2));
+    }
+}
+
 #[test]
 fn test_non_contiguous_qcs() {
     let mut shared_store = SharedRecordStore::new(2, 20);
@@ -290,3 +413,535 @@ fn test_commit() {
             .as_ref()
     );
 }
+
+#[test]
+fn test_committed_states_without_commit() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    assert!(shared_store
+        .store
+        .committed_states_after(Round(0))
+        .is_empty());
+    // Two QCs are not enough to commit anything.
+    shared_store.make_round(NodeTime(10));
+    shared_store.make_round(NodeTime(20));
+    assert!(shared_store.store.highest_commit_certificate().is_none());
+    assert!(shared_store
+        .store
+        .committed_states_after(Round(0))
+        .is_empty());
+}
+
+#[test]
+fn test_commit_gap() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    assert_eq!(shared_store.store.commit_gap(), 0);
+    shared_store.make_round(NodeTime(10));
+    shared_store.make_round(NodeTime(20));
+    shared_store.make_round(NodeTime(30));
+    // The 3-chain commits the first round.
+    assert_eq!(shared_store.store.highest_committed_round(), Round(1));
+    assert_eq!(shared_store.store.commit_gap(), 2);
+
+    // Timeouts between QCs prevent further commits.
+    shared_store.make_tc();
+    shared_store.make_round(NodeTime(50));
+    shared_store.make_tc();
+    shared_store.make_round(NodeTime(70));
+    assert_eq!(shared_store.store.highest_committed_round(), Round(1));
+    assert_eq!(
+        shared_store.store.highest_quorum_certificate_round(),
+        Round(7)
+    );
+    assert_eq!(shared_store.store.commit_gap(), 6);
+}
+
+#[test]
+fn test_view_change_latencies() {
+    let mut shared_store = SharedRecordStore::new(3, 20);
+    shared_store.make_round(NodeTime(10));
+    // Round 2 starts.
+    shared_store.store.update_clock(NodeTime(10));
+    shared_store.create_timeout(0, Round(2));
+    shared_store.store.update_clock(NodeTime(20));
+    shared_store.create_timeout(1, Round(2));
+    shared_store.store.update_clock(NodeTime(30));
+    assert!(shared_store.store.view_change_latencies().is_empty());
+    // The third timeout completes the quorum.
+    shared_store.create_timeout(2, Round(2));
+    shared_store.store.update_clock(NodeTime(45));
+    assert_eq!(
+        shared_store.store.highest_timeout_certificate_round(),
+        Round(2)
+    );
+    assert_eq!(
+        shared_store.store.view_change_latencies(),
+        vec![(Round(2), Duration(35))]
+    );
+
+    shared_store.make_tc();
+    shared_store.store.update_clock(NodeTime(50));
+    assert_eq!(shared_store.store.view_change_latencies().len(), 2);
+    // Round 5 is entered and abandoned between two clock updates, hence not measured.
+    shared_store.make_tc();
+    shared_store.make_tc();
+    shared_store.store.update_clock(NodeTime(60));
+    assert_eq!(
+        shared_store.store.view_change_latencies(),
+        vec![
+            (Round(2), Duration(35)),
+            (Round(3), Duration(5)),
+            (Round(4), Duration(10))
+        ]
+    );
+}
+
+#[test]
+fn test_unknown_records_timeout_order() {
+    let mut shared_store = SharedRecordStore::new(4, 20);
+    shared_store.make_tc();
+    shared_store.create_timeout(3, Round(2));
+    shared_store.create_timeout(1, Round(2));
+    let timeout_keys = |store: &RecordStoreState<SimulatedContext>| {
+        store
+            .unknown_records(BTreeSet::new(), BTreeSet::new())
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Timeout(timeout) => Some((timeout.value.round, timeout.value.author)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let keys = timeout_keys(&shared_store.store);
+    assert_eq!(keys.len(), 5);
+    assert_eq!(keys, timeout_keys(&shared_store.store));
+    let mut sorted_keys = keys.clone();
+    sorted_keys.sort();
+    assert_eq!(keys, sorted_keys);
+}
+
+#[test]
+fn test_unknown_records_skip_known_blocks() {
+    let mut shared_store = SharedRecordStore::new(3, 1000);
+    let mut other_store = SharedRecordStore::new(3, 1000);
+    for i in 0..3 {
+        shared_store.make_round(NodeTime(10 * i + 10));
+        other_store.make_round(NodeTime(10 * i + 10));
+    }
+    // The other store only receives the next proposal, not its QC.
+    shared_store.make_round(NodeTime(40));
+    let block_hash = shared_store
+        .store
+        .highest_quorum_certificate()
+        .unwrap()
+        .value
+        .certified_block_hash;
+    let block = shared_store.store.block(block_hash).unwrap().clone();
+    let block_round = block.value.round;
+    let context = other_store.contexts.get_mut(&Author(0)).unwrap();
+    other_store
+        .store
+        .insert_network_record(Record::Block(block), context);
+    let known_blocks = other_store.store.known_block_hashes();
+    assert!(known_blocks.contains(&block_hash));
+
+    // The block is not sent again.
+    let known_rounds = other_store.store.known_quorum_certificate_rounds();
+    let all_records = shared_store
+        .store
+        .unknown_records(known_rounds.clone(), BTreeSet::new());
+    let records = shared_store
+        .store
+        .unknown_records(known_rounds, known_blocks);
+    assert!(records.len() < all_records.len());
+    assert!(records.iter().all(|record| match record {
+        Record::Block(block) => block.value.round != block_round,
+        _ => true,
+    }));
+    for record in records {
+        other_store.store.insert_network_record(record, context);
+    }
+    assert_eq!(
+        other_store.store.highest_quorum_certificate_hash(),
+        shared_store.store.highest_quorum_certificate_hash()
+    );
+}
+
+#[test]
+fn test_quorum_certificate_vote_order() {
+    let make_quorum_certificate = |voters: &[usize]| {
+        let mut shared_store = SharedRecordStore::new(4, 20);
+        let author = shared_store.leader(Round(1));
+        shared_store.propose_block(author.0, QuorumCertificateHash(0), NodeTime(1));
+        let proposed_hash = shared_store.store.current_proposed_block.unwrap();
+        for voter in voters {
+            assert!(shared_store.create_vote(*voter, proposed_hash));
+        }
+        assert!(shared_store.check_for_new_quorum_certificate());
+        shared_store
+            .store
+            .highest_quorum_certificate()
+            .unwrap()
+            .value
+            .votes
+            .clone()
+    };
+    let votes = make_quorum_certificate(&[2, 0, 1]);
+    assert_eq!(votes, make_quorum_certificate(&[1, 2, 0]));
+    let authors: Vec<_> = votes.iter().map(|(author, _)| *author).collect();
+    assert_eq!(authors, vec![Author(0), Author(1), Author(2)]);
+}
+
+#[test]
+fn test_ancestry() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.make_round(NodeTime(10));
+    shared_store.make_round(NodeTime(20));
+    shared_store.make_round(NodeTime(30));
+    let store = &shared_store.store;
+    let tip_hash = store
+        .highest_quorum_certificate()
+        .unwrap()
+        .value
+        .certified_block_hash;
+    let records = store.ancestry(tip_hash);
+    // Blocks and QCs at rounds 1 and 2, then the block at round 3.
+    assert_eq!(records.len(), 5);
+    assert!(store.ancestry(BlockHash(0)).is_empty());
+
+    let mut fresh_store = SharedRecordStore::new(2, 20);
+    let context = fresh_store.contexts.get_mut(&Author(0)).unwrap();
+    for record in records {
+        fresh_store.store.insert_network_record(record, context);
+    }
+    assert!(fresh_store.store.block(tip_hash).is_some());
+    assert_eq!(
+        fresh_store.store.highest_quorum_certificate_round(),
+        Round(2)
+    );
+    assert_eq!(fresh_store.store.current_round(), Round(3));
+}
+
+fn command_after_timeout(reproposal: bool) -> (Command, Command) {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.store.reproposal = reproposal;
+    let leader = shared_store.leader(Round(1));
+    shared_store.propose_block(leader.0, QuorumCertificateHash(0), NodeTime(1));
+    let first_hash = shared_store.store.current_proposed_block.unwrap();
+    let first_command = shared_store
+        .store
+        .block(first_hash)
+        .unwrap()
+        .value
+        .command
+        .clone();
+    // Time out rounds until the same leader is elected again.
+    shared_store.make_tc();
+    while shared_store.leader(shared_store.store.current_round()) != leader {
+        shared_store.make_tc();
+    }
+    shared_store.propose_block(leader.0, QuorumCertificateHash(0), NodeTime(2));
+    let second_hash = shared_store.store.current_proposed_block.unwrap();
+    assert_ne!(first_hash, second_hash);
+    let second_command = shared_store
+        .store
+        .block(second_hash)
+        .unwrap()
+        .value
+        .command
+        .clone();
+    (first_command, second_command)
+}
+
+#[test]
+fn test_reproposal() {
+    let (first_command, second_command) = command_after_timeout(true);
+    assert_eq!(first_command, second_command);
+    let (first_command, second_command) = command_after_timeout(false);
+    assert_ne!(first_command, second_command);
+}
+
+#[test]
+fn test_replayed_records() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.make_round(NodeTime(10));
+    let qc = shared_store
+        .store
+        .highest_quorum_certificate()
+        .unwrap()
+        .clone();
+    let block = shared_store
+        .store
+        .block(qc.value.certified_block_hash)
+        .unwrap()
+        .clone();
+
+    let mut fresh_store = SharedRecordStore::new(2, 20);
+    let context = fresh_store.contexts.get_mut(&Author(0)).unwrap();
+    fresh_store
+        .store
+        .insert_network_record(Record::Block(block), context);
+    for _ in 0..100 {
+        fresh_store
+            .store
+            .insert_network_record(Record::QuorumCertificate(qc.clone()), context);
+    }
+    assert_eq!(
+        fresh_store.store.highest_quorum_certificate_round(),
+        Round(1)
+    );
+    assert_eq!(fresh_store.store.replayed_record_count(), 99);
+
+    // Replayed records are dropped before verifying signatures.
+    let mut forged_qc = qc;
+    forged_qc.signature = Signature(0, 0);
+    assert!(fresh_store
+        .store
+        .try_insert_network_record(Record::QuorumCertificate(forged_qc), context)
+        .is_err());
+    assert_eq!(fresh_store.store.replayed_record_count(), 100);
+}
+
+#[test]
+fn test_stale_quorum_certificate() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.make_round(NodeTime(10));
+    let qc = shared_store
+        .store
+        .highest_quorum_certificate()
+        .unwrap()
+        .clone();
+    let block = shared_store
+        .store
+        .block(qc.value.certified_block_hash)
+        .unwrap()
+        .clone();
+
+    // Another store advances to round 4 with timeouts only.
+    let mut other_store = SharedRecordStore::new(2, 20);
+    for _ in 0..3 {
+        other_store.make_tc();
+    }
+    assert_eq!(other_store.store.current_round(), Round(4));
+    let context = other_store.contexts.get_mut(&Author(0)).unwrap();
+    other_store
+        .store
+        .insert_network_record(Record::Block(block), context);
+    other_store
+        .store
+        .insert_network_record(Record::QuorumCertificate(qc), context);
+    // The QC of round 1 is accepted but does not move the current round backward.
+    assert_eq!(
+        other_store.store.highest_quorum_certificate_round(),
+        Round(1)
+    );
+    assert_eq!(other_store.store.current_round(), Round(4));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "Hash collision")]
+fn test_hash_collision() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    shared_store.make_round(NodeTime(10));
+    let qc = shared_store.store.highest_quorum_certificate().unwrap();
+    let block = shared_store
+        .store
+        .block(qc.value.certified_block_hash)
+        .unwrap()
+        .clone();
+    let mut other_block = block.clone();
+    other_block.value.time = NodeTime(11);
+
+    // Force the hash of the other block to collide with the known block.
+    let context = shared_store.contexts.get_mut(&Author(0)).unwrap();
+    let other_hash = context.hash(&other_block.value);
+    shared_store
+        .store
+        .blocks
+        .insert(BlockHash(other_hash), block);
+    shared_store
+        .store
+        .insert_network_record(Record::Block(other_block), context);
+}
+
+#[test]
+fn test_discard_abandoned_proposal() {
+    let mut shared_store = SharedRecordStore::new(2, 20);
+    let leader = shared_store.leader(Round(1));
+    shared_store.propose_block(leader.0, QuorumCertificateHash(0), NodeTime(1));
+    let proposed_hash = shared_store.store.current_proposed_block.unwrap();
+    assert!(shared_store.create_vote(0, proposed_hash));
+    let state = shared_store
+        .store
+        .current_vote(Author(0))
+        .unwrap()
+        .value
+        .state
+        .clone();
+    assert!(shared_store
+        .contexts
+        .get(&Author(0))
+        .unwrap()
+        .knows_state(&state));
+    // The last timeout completes a TC and advances the round from the point of view of node 0.
+    shared_store.create_timeout(1, Round(1));
+    shared_store.create_timeout(0, Round(1));
+    assert_eq!(shared_store.store.current_round(), Round(2));
+    assert!(!shared_store
+        .contexts
+        .get(&Author(0))
+        .unwrap()
+        .knows_state(&state));
+}
+
+#[test]
+fn test_vrf_leaders_after_timeout() {
+    let mut shared_store =
+        SharedRecordStore::new(4, 20).with_leader_election(LeaderElectionPolicy::Vrf);
+    shared_store.make_round(NodeTime(1));
+    let qc_hash = shared_store.store.highest_quorum_certificate_hash();
+    // After a TC, the next block extends a QC that is not from the previous round.
+    shared_store.make_tc();
+    let round = shared_store.store.current_round();
+    assert_eq!(round, Round(3));
+    assert_eq!(
+        shared_store.store.highest_quorum_certificate_round(),
+        Round(1)
+    );
+
+    // The leader is derived from the QC extended by the block.
+    let leader = shared_store.leader(round);
+    assert_eq!(leader, shared_store.store.leader(round, qc_hash));
+    // Blocks from other authors are rejected.
+    let block_count = shared_store.store.blocks.len();
+    let other = (leader.0 + 1) % 4;
+    shared_store.propose_block(other, qc_hash, NodeTime(3));
+    assert_eq!(shared_store.store.blocks.len(), block_count);
+    assert!(shared_store.store.current_proposed_block.is_none());
+    shared_store.propose_block(leader.0, qc_hash, NodeTime(3));
+    assert_eq!(shared_store.store.blocks.len(), block_count + 1);
+    let proposed_hash = shared_store.store.current_proposed_block.unwrap();
+    assert_eq!(
+        shared_store
+            .store
+            .block(proposed_hash)
+            .unwrap()
+            .value
+            .author,
+        leader
+    );
+
+    // Leaders of the same rounds differ depending on the QC.
+    let initial_hash = shared_store.store.initial_hash;
+    assert!((1..20).any(|round| {
+        shared_store.store.leader(Round(round), qc_hash)
+            != shared_store.store.leader(Round(round), initial_hash)
+    }));
+}
+
+#[test]
+fn test_pruning() {
+    let mut shared_store = SharedRecordStore::new(3, 1000).with_pruning_depth(Some(2));
+    // The proposal of round 1 is abandoned after a TC.
+    let author = shared_store.leader(Round(1));
+    shared_store.propose_block(author.0, QuorumCertificateHash(0), NodeTime(1));
+    let abandoned_hash = shared_store.store.current_proposed_block.unwrap();
+    shared_store.make_tc();
+    for i in 0..100 {
+        shared_store.make_round(NodeTime(10 * i + 10));
+        let committed_round = shared_store.store.highest_committed_round();
+        shared_store.store.set_delivered_round(committed_round);
+        assert!(shared_store.store.blocks.len() <= 6);
+        assert!(shared_store.store.quorum_certificates.len() <= 6);
+    }
+    let store = &shared_store.store;
+    let committed_round = store.highest_committed_round();
+    assert_eq!(
+        committed_round + 2,
+        store.highest_quorum_certificate_round()
+    );
+    assert!(committed_round > Round(90));
+    assert!(!store.blocks.contains_key(&abandoned_hash));
+    // The committed state of the highest commit certificate is still available.
+    assert_eq!(
+        store.committed_states_after(Round(98)).len(),
+        1,
+        "The latest commit should remain deliverable"
+    );
+    // Data sync only refers to the remaining records.
+    let known_rounds = store.known_quorum_certificate_rounds();
+    assert!(known_rounds
+        .iter()
+        .all(|round| *round + 2 >= committed_round));
+    // The latest pruning happened before the delivery of the latest commit.
+    let records = store.unknown_records(BTreeSet::new(), BTreeSet::new());
+    match &records[0] {
+        Record::Block(block) => assert_eq!(block.value.round + 3, committed_round),
+        record => panic!("Unexpected record {:?}", record),
+    }
+}
+
+#[test]
+fn test_committed_leaders_are_bounded() {
+    let mut shared_store =
+        SharedRecordStore::new(3, 2 * MAX_COMMITTED_LEADERS).with_pruning_depth(Some(2));
+    for i in 0..MAX_COMMITTED_LEADERS + 10 {
+        shared_store.make_round(NodeTime(10 * i as i64 + 10));
+        let committed_round = shared_store.store.highest_committed_round();
+        shared_store.store.set_delivered_round(committed_round);
+    }
+    let store = &shared_store.store;
+    let leaders = store.committed_leaders();
+    assert_eq!(leaders.len(), MAX_COMMITTED_LEADERS);
+    // The latest commits are kept.
+    assert_eq!(leaders.last().unwrap().0, store.highest_committed_round());
+    for (round, author) in leaders {
+        assert_eq!(author, shared_store.leader(round));
+    }
+}
+
+#[test]
+fn test_pruning_keeps_ancestors_of_uncommitted_blocks() {
+    let mut shared_store = SharedRecordStore::new(3, 1000).with_pruning_depth(Some(0));
+    for i in 0..5 {
+        shared_store.make_round(NodeTime(10 * i + 10));
+    }
+    assert_eq!(shared_store.store.highest_committed_round(), Round(3));
+    shared_store.store.set_delivered_round(Round(3));
+    // The leader of round 6 proposes on top of the QC of round 3, then on top of the highest QC.
+    let stale_qc_hash = *shared_store
+        .store
+        .quorum_certificates
+        .iter()
+        .find(|(_, qc)| qc.value.round == Round(3))
+        .unwrap()
+        .0;
+    let author = shared_store.leader(Round(6));
+    shared_store.propose_block(author.0, stale_qc_hash, NodeTime(60));
+    let fork_hash = shared_store.store.current_proposed_block.unwrap();
+    shared_store.make_round(NodeTime(70));
+    shared_store.store.set_delivered_round(Round(4));
+    shared_store.make_round(NodeTime(80));
+    let store = &shared_store.store;
+    assert_eq!(store.highest_committed_round(), Round(5));
+    // The pending fork at round 6 can still be evaluated.
+    assert!(store.blocks.contains_key(&fork_hash));
+    assert_eq!(store.previous_round(fork_hash), Round(3));
+    assert_eq!(store.second_previous_round(fork_hash), Round(2));
+}
+
+#[test]
+fn test_pruning_waits_for_delivery() {
+    let mut shared_store = SharedRecordStore::new(3, 1000).with_pruning_depth(Some(0));
+    for i in 0..10 {
+        shared_store.make_round(NodeTime(10 * i + 10));
+    }
+    // Nothing was delivered yet, hence nothing was pruned.
+    assert_eq!(shared_store.store.blocks.len(), 10);
+    assert_eq!(shared_store.store.committed_states_after(Round(0)).len(), 8);
+    shared_store.store.set_delivered_round(Round(8));
+    // Round 9 is committed: only the delivered round 8 and the following ones are kept.
+    shared_store.make_round(NodeTime(110));
+    assert_eq!(shared_store.store.highest_committed_round(), Round(9));
+    assert_eq!(shared_store.store.blocks.len(), 4);
+}
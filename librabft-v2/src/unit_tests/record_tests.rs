@@ -2,18 +2,22 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use bft_lib::{simulated_context::*, smr_context::CryptographicModule};
+use bft_lib::{
+    simulated_context::*,
+    smr_context::{CryptographicModule, StateFinalizer},
+};
 
 #[test]
 fn test_block_signing() {
     let mut context =
         SimulatedContext::new(Author(2), /* not used */ 0, /* not used */ 0);
-    let b = SignedValue::make(
+    let b = sign_record(
         &mut context,
         Block_::<SimulatedContext> {
             command: Command {
                 proposer: Author(1),
                 index: 2,
+                payload: Vec::new(),
             },
             time: NodeTime(2),
             previous_quorum_certificate_hash: QuorumCertificateHash(47),
@@ -26,12 +30,13 @@ fn test_block_signing() {
         .verify(Author(1), context.hash(&b.value), b.signature)
         .is_err());
 
-    let b2 = SignedValue::make(
+    let b2 = sign_record(
         &mut context,
         Block_::<SimulatedContext> {
             command: Command {
                 proposer: Author(3),
                 index: 2,
+                payload: Vec::new(),
             },
             time: NodeTime(2),
             previous_quorum_certificate_hash: QuorumCertificateHash(47),
@@ -44,3 +49,39 @@ fn test_block_signing() {
         .verify(Author(2), context.hash(&b.value), b2.signature)
         .is_err());
 }
+
+#[test]
+fn test_vote_reconstruction() {
+    let mut context =
+        SimulatedContext::new(Author(2), /* not used */ 0, /* not used */ 0);
+    let state = context.last_committed_state();
+    let vote = sign_record(
+        &mut context,
+        Vote_::<SimulatedContext> {
+            epoch_id: EpochId(1),
+            round: Round(3),
+            certified_block_hash: BlockHash(47),
+            state: state.clone(),
+            committed_state: Some(state.clone()),
+            author: Author(2),
+        },
+    );
+    let quorum_certificate = QuorumCertificate_::<SimulatedContext> {
+        epoch_id: EpochId(1),
+        round: Round(3),
+        certified_block_hash: BlockHash(47),
+        state: state.clone(),
+        committed_state: Some(state),
+        votes: vec![(Author(2), vote.signature)],
+        author: Author(2),
+    };
+    let reconstructed = Vote_::from_quorum_certificate(&quorum_certificate, Author(2));
+    assert_eq!(reconstructed, vote.value);
+    assert_eq!(
+        reconstructed.canonical_signing_bytes(),
+        vote.value.canonical_signing_bytes()
+    );
+    let hash = context.hash(&reconstructed);
+    assert_eq!(hash, context.hash(&vote.value));
+    assert!(context.verify(Author(2), hash, vote.signature).is_ok());
+}
@@ -4,42 +4,29 @@
 #![cfg(feature = "simulator")]
 
 use bft_lib::{
-    base_types::*,
-    interfaces::ConsensusNode,
-    simulated_context::{SimulatedContext, State},
+    simulated_context::{Author, SimulatedContext},
     simulator,
-    smr_context::StateFinalizer,
+    smr_context::{EpochReader, StateFinalizer},
 };
-use futures::executor::block_on;
 use librabft_v2::{
-    data_sync::*,
-    node::{NodeConfig, NodeState},
+    node::dump_dag,
+    simulation::{Simulator, SimulatorBuilder},
 };
 
-fn make_simulator(
-    seed: u64,
-    nodes: usize,
-) -> simulator::Simulator<
-    NodeState<SimulatedContext>,
-    SimulatedContext,
-    DataSyncNotification<SimulatedContext>,
-    DataSyncRequest,
-    DataSyncResponse<SimulatedContext>,
-> {
-    let context_factory = |author, num_nodes| {
-        let mut context = SimulatedContext::new(author, num_nodes, 30000);
-        let config = NodeConfig {
-            target_commit_interval: Duration(100000),
-            delta: Duration(20),
-            gamma: 2.0,
-            lambda: 0.5,
-        };
-        let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
-        block_on(node.save_node(&mut context)).unwrap();
-        context
-    };
-    let delay_distribution = simulator::RandomDelay::new(10.0, 4.0);
-    simulator::Simulator::new(seed, nodes, delay_distribution, context_factory)
+fn make_simulator(seed: u64, nodes: usize) -> Simulator {
+    SimulatorBuilder::new(seed, nodes, /* commands per epoch */ 30000).build()
+}
+
+/// Same as `make_simulator` but the node `i` has `weights[i]` votes.
+fn make_simulator_with_weights(seed: u64, weights: Vec<usize>) -> Simulator {
+    let voting_rights: Vec<_> = weights
+        .iter()
+        .enumerate()
+        .map(|(index, weight)| (Author(index), *weight))
+        .collect();
+    SimulatorBuilder::new(seed, weights.len(), /* commands per epoch */ 30000)
+        .with_context_setup(move |context| context.with_weights(voting_rights.clone()))
+        .build()
 }
 
 #[test]
@@ -50,19 +37,28 @@ fn test_simulated_run_3_nodes() {
         .iter()
         .map(|context| context.committed_history().len())
         .collect::<Vec<_>>();
-    assert_eq!(num_commits, [27, 27, 27]);
-    let last_committed_states = contexts
+    let digests = contexts
         .iter()
-        .map(|context| context.last_committed_state())
+        .map(|context| context.history_digest())
         .collect::<Vec<_>>();
-    assert_eq!(
-        last_committed_states,
-        [
-            State(11134312813757838303),
-            State(11134312813757838303),
-            State(11134312813757838303)
-        ],
-    );
+    // Hash values, hence leader elections, depend on the width of the hash.
+    #[cfg(not(feature = "wide-hash"))]
+    {
+        assert_eq!(num_commits, [27, 27, 27]);
+        assert_eq!(digests, [2596842545928049966; 3]);
+    }
+    #[cfg(feature = "wide-hash")]
+    {
+        assert_eq!(num_commits, [28, 29, 29]);
+        assert_eq!(
+            digests,
+            [
+                7992224937580547019,
+                11528940659060514847,
+                11528940659060514847
+            ]
+        );
+    }
 }
 
 #[test]
@@ -73,22 +69,84 @@ fn test_simulated_run_8_nodes() {
         .iter()
         .map(|context| context.committed_history().len())
         .collect::<Vec<_>>();
-    assert_eq!(num_commits, [28, 28, 28, 28, 28, 28, 28, 30]);
-    let last_committed_states = contexts
+    let digests = contexts
         .iter()
-        .map(|context| context.last_committed_state())
+        .map(|context| context.history_digest())
         .collect::<Vec<_>>();
-    assert_eq!(
-        last_committed_states,
-        [
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(12785928431398617538),
-            State(4890275890002623733)
-        ]
-    );
+    #[cfg(not(feature = "wide-hash"))]
+    {
+        assert_eq!(num_commits, [29; 8]);
+        assert_eq!(digests, [15387247914307170997; 8]);
+    }
+    #[cfg(feature = "wide-hash")]
+    {
+        assert_eq!(num_commits, [31; 8]);
+        assert_eq!(digests, [15869166167118475732; 8]);
+    }
+}
+
+#[test]
+fn test_history_digest() {
+    let run = || {
+        let mut sim = make_simulator(/* seed */ 52, /* nodes */ 3);
+        let contexts = sim.loop_until(simulator::GlobalTime(1000), None);
+        contexts
+            .iter()
+            .map(|context| (context.committed_history().len(), context.history_digest()))
+            .collect::<Vec<_>>()
+    };
+    let digests = run();
+    // Nodes that committed as many commands have committed the same history.
+    for (count, digest) in &digests {
+        assert!(digests
+            .iter()
+            .all(|(other_count, other_digest)| other_count != count || other_digest == digest));
+    }
+    // Runs are reproducible.
+    assert_eq!(run(), digests);
+    // An empty history has a digest too, distinct from the digests of the run.
+    let context = SimulatedContext::new(Author(0), 3, 30000);
+    assert!(digests
+        .iter()
+        .all(|(_, digest)| *digest != context.history_digest()));
+}
+
+#[test]
+fn test_dump_dag() {
+    let mut sim = make_simulator(/* seed */ 52, /* nodes */ 3);
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let directory = std::env::temp_dir().join(format!("librabft_dump_dag_{}", std::process::id()));
+    let nodes = (0..3).map(|index| sim.simulated_node(Author(index)).node());
+    dump_dag(nodes, &directory).unwrap();
+    for index in 0..3 {
+        let content =
+            std::fs::read_to_string(directory.join(format!("node_{}.dot", index))).unwrap();
+        assert!(content.starts_with("digraph {"));
+        assert!(content.contains("->"));
+    }
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_simulated_run_weighted_committee() {
+    // Node 0 holds 40% of the stake.
+    let mut sim = make_simulator_with_weights(/* seed */ 52, vec![4, 2, 2, 2]);
+    let contexts = sim.loop_until(simulator::GlobalTime(3000), None);
+    let configuration = contexts[0].configuration(&contexts[0].last_committed_state());
+    assert_eq!(configuration.quorum_threshold(), 7);
+    // The heavy node and any two other nodes form a quorum, but not the three other nodes.
+    assert!(configuration.is_quorum(configuration.count_votes(&[Author(0), Author(1), Author(2)])));
+    assert!(!configuration.is_quorum(6));
+    for context in &contexts {
+        assert!(context.committed_history().len() > 50);
+    }
+    let leaders =
+        simulator::CommittedLeaders::committed_leaders(sim.simulated_node(Author(0)).node());
+    let mut counts = [0; 4];
+    for (_, _, author) in leaders {
+        counts[author.0] += 1;
+    }
+    for count in &counts[1..] {
+        assert!(counts[0] > *count * 3 / 2);
+    }
 }
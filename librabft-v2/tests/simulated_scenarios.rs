@@ -0,0 +1,1153 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+// SPDX-License-Identifier: Apache-2.0
+
+#![cfg(feature = "simulator")]
+
+use bft_lib::{
+    base_types::*,
+    simulated_context::*,
+    simulator::{
+        self, ActiveEpoch, ActiveRound, ApproximateMemory, CommitGap, ForkRate, KnownRecords,
+        SafetyMonitor,
+    },
+    smr_context::*,
+};
+use librabft_v2::{
+    node::{CommitDelivery, NodeConfig, NodeSnapshot},
+    pacemaker::{LeaderElectionPolicy, ProposeWhenEmpty},
+    simulation::{Simulator, SimulatorBuilder},
+};
+use std::collections::{BTreeSet, HashMap};
+
+#[test]
+fn test_active_epoch() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    assert_eq!(sim.simulated_node(Author(0)).active_epoch(), EpochId(0));
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    for index in 0..3 {
+        let simulated_node = sim.simulated_node(Author(index));
+        let node = simulated_node.node();
+        assert!(node.active_epoch() >= EpochId(1));
+        assert_eq!(node.active_epoch(), node.snapshot().epoch_id);
+        assert_eq!(simulated_node.active_epoch(), node.active_epoch());
+    }
+}
+
+#[test]
+fn test_known_epochs() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    let node = sim.simulated_node(Author(0)).node();
+    assert_eq!(node.known_epochs(), vec![EpochId(0)]);
+    // Stop as soon as the node enters the second epoch.
+    let mut clock = 0;
+    while sim.simulated_node(Author(0)).node().snapshot().epoch_id == EpochId(0) {
+        clock += 10;
+        sim.loop_until(simulator::GlobalTime(clock), None);
+    }
+    let node = sim.simulated_node(Author(0)).node();
+    assert_eq!(node.snapshot().epoch_id, EpochId(1));
+    assert_eq!(node.known_epochs(), vec![EpochId(0), EpochId(1)]);
+}
+
+#[test]
+fn test_memory_report() {
+    // Sample the memory used by the record stores of each node over time.
+    let run = |pruning_depth| {
+        let config = NodeConfig {
+            pruning_depth,
+            ..NodeConfig::default()
+        };
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 54, /* nodes */ 3, /* commands per epoch */ 1000,
+        )
+        .with_config(config)
+        .build();
+        let mut samples = Vec::new();
+        for t in (1000..=3000).step_by(500) {
+            sim.loop_until(simulator::GlobalTime(t), None);
+            let report = sim.memory_report();
+            assert_eq!(report.len(), 3);
+            let sample: Vec<_> = report
+                .into_iter()
+                .map(|(author, memory)| {
+                    let node_memory = sim.simulated_node(author).node().approximate_memory();
+                    // The report also accounts for the memory of the context.
+                    assert!(memory > node_memory);
+                    node_memory
+                })
+                .collect();
+            samples.push(sample);
+        }
+        samples
+    };
+    let unpruned = run(None);
+    let pruned = run(Some(5));
+    for index in 0..3 {
+        let first = unpruned[0][index];
+        let last = unpruned.last().unwrap()[index];
+        // Without pruning, record stores keep growing.
+        assert!(last > 2 * first);
+        // With pruning, they reach a plateau.
+        let first = pruned[0][index];
+        assert!(pruned.iter().all(|sample| sample[index] < 2 * first));
+    }
+}
+
+#[test]
+fn test_sync_completeness() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    // Isolate the last node from the network for a while.
+    sim.disconnect_until(Author(3), simulator::GlobalTime(500));
+    sim.loop_until(simulator::GlobalTime(500), None);
+    let (author, completeness) = sim.sync_completeness()[3];
+    assert_eq!(author, Author(3));
+    assert!(completeness < 1.0);
+    // Data synchronization catches up shortly after the partition. (Completeness may dip again
+    // while new records are in flight.)
+    let caught_up = (510..=600).step_by(10).any(|t| {
+        sim.loop_until(simulator::GlobalTime(t), None);
+        sim.sync_completeness()[3] == (Author(3), 1.0)
+    });
+    assert!(caught_up);
+}
+
+#[test]
+fn test_resumed_simulation() {
+    // Running a simulation in several steps gives the same result as running it at once.
+    let committed_states = |stops: &[i64]| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build();
+        for stop in stops {
+            sim.loop_until(simulator::GlobalTime(*stop), None);
+        }
+        (0..4)
+            .map(|index| {
+                let context = sim.simulated_node(Author(index)).context();
+                (
+                    context.committed_history().len(),
+                    context.last_committed_state(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        committed_states(&[250, 500, 1000]),
+        committed_states(&[1000])
+    );
+}
+
+#[test]
+fn test_gst() {
+    let gst = simulator::GlobalTime(1000);
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_gst(gst, /* delta bound */ 12);
+    // Without a quorum of connected nodes, no progress is made before GST.
+    sim.disconnect_until(Author(2), gst);
+    sim.disconnect_until(Author(3), gst);
+    for context in sim.loop_until(gst, None) {
+        assert!(context.committed_history().is_empty());
+    }
+    assert_eq!(sim.max_network_delay_after_gst(), None);
+    // Commits resume promptly once the network is synchronous.
+    for context in sim.loop_until(gst + Duration(200), None) {
+        assert!(!context.committed_history().is_empty());
+    }
+    assert!(sim.max_network_delay_after_gst().unwrap() <= Duration(12));
+}
+
+#[test]
+fn test_skip_signature_verification() {
+    let run = |signature_verification| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 10,
+        )
+        .with_context_setup(move |context| {
+            context.with_signature_verification(signature_verification)
+        })
+        .build();
+        sim.loop_until(simulator::GlobalTime(1000), None)
+            .iter()
+            .map(|context| {
+                (
+                    context.last_committed_state(),
+                    context.verified_signature_count(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let eager = run(SignatureVerification::Eager);
+    let skip = run(SignatureVerification::Skip);
+    for ((eager_state, eager_count), (skip_state, skip_count)) in eager.iter().zip(&skip) {
+        // Honest runs are not affected but no verification work is done.
+        assert_eq!(eager_state, skip_state);
+        assert!(*eager_count > 0);
+        assert_eq!(*skip_count, 0);
+    }
+}
+
+#[test]
+fn test_commit_certificate_verification() {
+    // Commit certificates span several epochs and are checked against their committee.
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 10,
+    )
+    .with_context_setup(|context| context.with_commit_certificate_verification(true))
+    .build();
+    for context in sim.loop_until(simulator::GlobalTime(1000), None) {
+        assert!(context.committed_history().len() > 10);
+        assert!(!context.commit_audit_log().is_empty());
+    }
+}
+
+/// Times at which the active round of each node changes, sampled at every tick.
+fn round_switch_times(sim: &mut Simulator, nodes: usize, max_clock: i64) -> Vec<Vec<i64>> {
+    let mut rounds = vec![Round(0); nodes];
+    let mut times = vec![Vec::new(); nodes];
+    for clock in 0..=max_clock {
+        sim.loop_until(simulator::GlobalTime(clock), None);
+        for index in 0..nodes {
+            let round = sim.simulated_node(Author(index)).node().active_round();
+            if round != rounds[index] {
+                rounds[index] = round;
+                times[index].push(clock);
+            }
+        }
+    }
+    times
+}
+
+fn variance(values: &[i64]) -> f64 {
+    let mean = values.iter().sum::<i64>() as f64 / values.len() as f64;
+    values
+        .iter()
+        .map(|v| (*v as f64 - mean) * (*v as f64 - mean))
+        .sum::<f64>()
+        / values.len() as f64
+}
+
+#[test]
+fn test_clock_jitter() {
+    let commit_counts = |max_jitter| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build()
+        .with_clock_jitter(Duration(max_jitter), /* seed */ 7);
+        sim.loop_until(simulator::GlobalTime(3000), None)
+            .iter()
+            .map(|context| context.committed_history().len())
+            .collect::<Vec<_>>()
+    };
+    let timeout_intervals = |max_jitter| {
+        // Without commands to propose, rounds only advance after timeouts.
+        let config = NodeConfig {
+            // Constant timeouts.
+            gamma: 0.0,
+            propose_when_empty: ProposeWhenEmpty::Never,
+            ..NodeConfig::default()
+        };
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 10,
+        )
+        .with_config(config)
+        .with_context_setup(|context| context.with_frozen_commands(true))
+        .build()
+        .with_clock_jitter(Duration(max_jitter), /* seed */ 7);
+        round_switch_times(&mut sim, /* nodes */ 4, /* max clock */ 3000)
+            .iter()
+            .flat_map(|times| times.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+
+    // Small jitter does not change the commit rate much.
+    let baseline = commit_counts(0);
+    for (count, baseline_count) in commit_counts(1).iter().zip(&baseline) {
+        assert!(*baseline_count > 0);
+        assert!(*count * 10 >= *baseline_count * 9);
+    }
+    // Larger jitter makes timeouts noisier.
+    let steady = timeout_intervals(0);
+    let noisy = timeout_intervals(10);
+    assert!(variance(&noisy) > 2.0 * variance(&steady));
+}
+
+#[test]
+fn test_fork_rate() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    // Random delays and timeouts make leaders of successive rounds propose competing blocks.
+    let contexts = sim.loop_until(simulator::GlobalTime(1000), None);
+    for context in contexts {
+        assert!(context.discarded_state_count() > 0);
+        assert!(context.fork_rate() > 0.0);
+        assert_eq!(
+            context.fork_rate(),
+            context.discarded_state_count() as f64 / context.committed_history().len() as f64
+        );
+    }
+}
+
+#[test]
+fn test_two_phase_message_schedule() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    assert!(sim.follower_to_follower_message_count() > 0);
+
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_message_schedule(simulator::MessageSchedule::TwoPhase);
+    let contexts = sim.loop_until(simulator::GlobalTime(1000), None);
+    for context in contexts {
+        assert!(!context.committed_history().is_empty());
+    }
+    // Followers never message each other directly.
+    assert_eq!(sim.follower_to_follower_message_count(), 0);
+    assert!(sim.edge_message_counts().values().sum::<usize>() > 0);
+}
+
+#[test]
+fn test_frozen_commands() {
+    let config = NodeConfig {
+        propose_when_empty: ProposeWhenEmpty::Never,
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 10,
+    )
+    .with_config(config)
+    .with_context_setup(|context| context.with_frozen_commands(true))
+    .build();
+    let mut previous_rounds = [Round(0); 4];
+    for t in &[500, 1000] {
+        sim.loop_until(simulator::GlobalTime(*t), None);
+        for (index, previous_round) in previous_rounds.iter_mut().enumerate() {
+            let snapshot = sim.simulated_node(Author(index)).node().snapshot();
+            // Rounds only advance through timeout certificates.
+            assert_eq!(snapshot.highest_quorum_certificate_round, Round(0));
+            assert!(snapshot.current_round > *previous_round);
+            *previous_round = snapshot.current_round;
+        }
+    }
+}
+
+#[test]
+fn test_batched_commit_delivery() {
+    let config = NodeConfig {
+        commit_delivery: CommitDelivery::Batched,
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_config(config)
+    .build();
+    // Isolate the last node from the network for a while.
+    sim.disconnect_until(Author(3), simulator::GlobalTime(500));
+    sim.loop_until(simulator::GlobalTime(500), None);
+    let context = sim.simulated_node(Author(3)).context();
+    assert!(context.committed_history().is_empty());
+    assert!(context.commit_batch_sizes().is_empty());
+    let contexts = sim.loop_until(simulator::GlobalTime(600), None);
+    let sizes = contexts[3].commit_batch_sizes();
+    // The commits missed during the partition are delivered at once.
+    assert!(sizes[0] > 1);
+    assert_eq!(
+        sizes.iter().sum::<usize>(),
+        contexts[3].committed_history().len()
+    );
+}
+
+#[test]
+fn test_fault_report() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 100,
+    )
+    .build();
+    let report = sim.fault_report();
+    assert_eq!(report.tolerated_f, 1);
+    assert_eq!(report.actual_faulty_weight, 0);
+    assert!(report.safe);
+
+    sim.disconnect_until(Author(3), simulator::GlobalTime(500));
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let report = sim.fault_report();
+    assert_eq!(report.actual_faulty_weight, 1);
+    assert!(report.safe);
+
+    sim.withhold_proposals(vec![Author(2)]);
+    let report = sim.fault_report();
+    assert_eq!(report.actual_faulty_weight, 2);
+    assert!(!report.safe);
+}
+
+#[test]
+fn test_liveness_report() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 3, /* commands per epoch */ 100,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let report = sim.liveness_report();
+    assert!(report.committed_any);
+    assert!(report.final_round > Round(3));
+    assert!(report.stalled_nodes.is_empty());
+
+    // Without any network, no quorum is ever formed.
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 3, /* commands per epoch */ 100,
+    )
+    .build();
+    for index in 0..3 {
+        sim.disconnect_until(Author(index), simulator::GlobalTime(2000));
+    }
+    sim.loop_until(simulator::GlobalTime(1000), None);
+    let report = sim.liveness_report();
+    assert!(!report.committed_any);
+    assert_eq!(report.final_round, Round(1));
+    assert_eq!(report.stalled_nodes, vec![Author(0), Author(1), Author(2)]);
+}
+
+#[test]
+fn test_link_delays() {
+    let default_delay = simulator::RandomDelay::new(10.0, 4.0);
+    let link_delays = simulator::LinkDelayMatrix::uniform(default_delay).with_link(
+        Author(0),
+        Author(1),
+        simulator::RandomDelay::new(200.0, 100.0),
+    );
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_link_delays(link_delays);
+    let contexts = sim.loop_until(simulator::GlobalTime(2000), None);
+    for context in contexts {
+        assert!(!context.committed_history().is_empty());
+    }
+    let ranges = sim.link_delay_ranges();
+    let (slowest_min, _) = ranges[&(Author(0), Author(1))];
+    // Messages on the slow link consistently arrive later than on any other link, including
+    // the reverse link.
+    for (edge, (_, max)) in ranges {
+        if *edge != (Author(0), Author(1)) {
+            assert!(*max < slowest_min);
+        }
+    }
+    assert!(ranges.contains_key(&(Author(1), Author(0))));
+}
+
+#[test]
+fn test_commit_certificate_delay() {
+    // Sample the active rounds of all nodes and the commit gap of the first node (i.e. the
+    // number of rounds between its highest QC and its highest commit) over time.
+    let run = |extra_delay| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build();
+        sim.delay_commit_certificates(Duration(extra_delay));
+        let mut rounds = Vec::new();
+        let mut gaps = Vec::new();
+        for t in (20..3000).step_by(20) {
+            sim.loop_until(simulator::GlobalTime(t), None);
+            rounds.push(
+                (0..4)
+                    .map(|index| sim.simulated_node(Author(index)).active_round())
+                    .collect::<Vec<_>>(),
+            );
+            gaps.push(sim.simulated_node(Author(0)).node().commit_gap());
+        }
+        (rounds, gaps)
+    };
+    let (rounds, gaps) = run(0);
+    let (delayed_rounds, delayed_gaps) = run(100);
+    // Until the first commit, no notification carries a commit certificate hence round
+    // progress is the same in both runs.
+    assert_eq!(rounds[..3], delayed_rounds[..3]);
+    // Later on, commits lag further behind certified rounds.
+    let average = |gaps: &[usize]| gaps.iter().sum::<usize>() as f64 / gaps.len() as f64;
+    assert!(average(&delayed_gaps) > average(&gaps) + 0.25);
+}
+
+#[test]
+fn test_commit_certificate_delay_after_gst() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_gst(simulator::GlobalTime(0), /* delta bound */ 12);
+    sim.delay_commit_certificates(Duration(100));
+    for context in sim.loop_until(simulator::GlobalTime(1000), None) {
+        assert!(!context.committed_history().is_empty());
+    }
+    // Delayed commit certificates are still delivered within the synchrony bound.
+    assert!(sim.max_network_delay_after_gst().unwrap() <= Duration(12));
+    for (_, max) in sim.link_delay_ranges().values() {
+        assert!(*max <= Duration(12));
+    }
+}
+
+#[test]
+fn test_bytes_sent() {
+    // Bytes sent to the last node between times 600 and 1000.
+    let bytes_sent_to_last_node = |disconnected: bool| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build()
+        .with_message_size_accounting(true);
+        if disconnected {
+            sim.disconnect_until(Author(3), simulator::GlobalTime(600));
+        }
+        let received = |sim: &Simulator| -> usize {
+            sim.bytes_sent()
+                .iter()
+                .filter(|((_, destination), _)| *destination == Author(3))
+                .map(|(_, bytes)| bytes)
+                .sum()
+        };
+        sim.loop_until(simulator::GlobalTime(600), None);
+        let before = received(&sim);
+        sim.loop_until(simulator::GlobalTime(1000), None);
+        received(&sim) - before
+    };
+    let steady = bytes_sent_to_last_node(false);
+    let catch_up = bytes_sent_to_last_node(true);
+    assert!(steady > 0);
+    // Catching up requires larger responses.
+    assert!(catch_up > steady);
+
+    // Sizes are not measured by default.
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(100), None);
+    assert!(sim.bytes_sent().is_empty());
+}
+
+#[test]
+fn test_rng_state() {
+    let run = |interrupt: bool, restore: bool| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build();
+        sim.loop_until(simulator::GlobalTime(500), None);
+        let state = sim.rng_state();
+        if interrupt {
+            // Overwrite the random generator with the one of another run.
+            let other = SimulatorBuilder::new(
+                /* seed */ 7, /* nodes */ 4, /* commands per epoch */ 1000,
+            )
+            .build();
+            sim.restore_rng_state(&other.rng_state()).unwrap();
+        }
+        if restore {
+            sim.restore_rng_state(&state).unwrap();
+        }
+        sim.loop_until(simulator::GlobalTime(1000), None);
+        sim.edge_message_counts().clone()
+    };
+    let expected = run(/* interrupt */ false, /* restore */ false);
+    assert_eq!(run(/* interrupt */ true, /* restore */ true), expected);
+    assert_ne!(run(/* interrupt */ true, /* restore */ false), expected);
+}
+
+#[test]
+fn test_startup_modes() {
+    let startup_times = |startup_mode| {
+        let sim = SimulatorBuilder::new(
+            /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+        )
+        .with_startup_mode(startup_mode)
+        .build();
+        (0..3)
+            .map(|index| sim.simulated_node(Author(index)).startup_time())
+            .collect::<Vec<_>>()
+    };
+    let randomized = startup_times(simulator::StartupMode::Randomized);
+    assert!(randomized
+        .iter()
+        .all(|time| *time >= simulator::GlobalTime(1)));
+    assert!(randomized.iter().any(|time| *time != randomized[0]));
+    assert_eq!(
+        startup_times(simulator::StartupMode::Simultaneous),
+        vec![simulator::GlobalTime(1); 3]
+    );
+    assert_eq!(
+        startup_times(simulator::StartupMode::Sequential {
+            spacing: Duration(10)
+        }),
+        vec![
+            simulator::GlobalTime(1),
+            simulator::GlobalTime(11),
+            simulator::GlobalTime(21)
+        ]
+    );
+}
+
+#[test]
+fn test_run_metadata() {
+    let config = NodeConfig::default();
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_pacemaker_parameters(config.delta, config.gamma, config.lambda);
+    let path = std::env::temp_dir().join(format!("librabft_run_metadata_{}", std::process::id()));
+    sim.loop_until(
+        simulator::GlobalTime(200),
+        Some(path.to_str().unwrap().to_string()),
+    );
+    let file = std::fs::File::open(path.join("run_metadata.json")).unwrap();
+    let metadata: simulator::RunMetadata = serde_json::from_reader(file).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    assert_eq!(
+        metadata,
+        simulator::RunMetadata {
+            seed: 52,
+            nodes: 3,
+            delay_mean: 10.0,
+            delay_variance: 4.0,
+            delta: Some(Duration(20)),
+            gamma: Some(2.0),
+            lambda: Some(0.5),
+        }
+    );
+    assert_eq!(&metadata, sim.run_metadata());
+}
+
+#[test]
+fn test_commit_latency_file() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 1000,
+    )
+    .build();
+    let path = std::env::temp_dir().join(format!("librabft_commit_latency_{}", std::process::id()));
+    let contexts = sim.loop_until(
+        simulator::GlobalTime(1000),
+        Some(path.to_str().unwrap().to_string()),
+    );
+    assert!(contexts
+        .iter()
+        .all(|context| !context.committed_history().is_empty()));
+    let content = std::fs::read_to_string(path.join("commit_latency.csv")).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    let mut lines = content.lines();
+    assert_eq!(lines.next(), Some("epoch,round,node 0,node 1,node 2"));
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    assert!(!rows.is_empty());
+    // Committed rounds are increasing and nodes deliver commits at different times.
+    let rounds: Vec<usize> = rows.iter().map(|row| row[1].parse().unwrap()).collect();
+    assert!(rounds.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(rows.iter().any(|row| {
+        let times: BTreeSet<_> = row[2..].iter().filter(|time| !time.is_empty()).collect();
+        times.len() > 1
+    }));
+}
+
+#[test]
+fn test_commit_latency_file_across_epochs() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    let path = std::env::temp_dir().join(format!(
+        "librabft_commit_latency_epochs_{}",
+        std::process::id()
+    ));
+    sim.loop_until(
+        simulator::GlobalTime(1000),
+        Some(path.to_str().unwrap().to_string()),
+    );
+    let content = std::fs::read_to_string(path.join("commit_latency.csv")).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    let keys: Vec<(usize, usize)> = content
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let row: Vec<&str> = line.split(',').collect();
+            (row[0].parse().unwrap(), row[1].parse().unwrap())
+        })
+        .collect();
+    // Rows are sorted by epoch, then by round, and rounds start over at each epoch.
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    let epochs: BTreeSet<_> = keys.iter().map(|(epoch, _)| *epoch).collect();
+    assert!(epochs.len() > 2);
+    assert!(keys.windows(2).any(|pair| pair[0].1 > pair[1].1));
+}
+
+#[test]
+fn test_throughput_file() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_throughput_bucket_size(simulator::GlobalTime(50));
+    let path = std::env::temp_dir().join(format!("librabft_throughput_{}", std::process::id()));
+    sim.loop_until(
+        simulator::GlobalTime(999),
+        Some(path.to_str().unwrap().to_string()),
+    );
+    let content = std::fs::read_to_string(path.join("throughput.csv")).unwrap();
+    let messages = std::fs::read_to_string(path.join("number_of_messages.txt")).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    let mut lines = content.lines();
+    assert_eq!(
+        lines.next(),
+        Some("time,notifications,requests,responses,committed commands")
+    );
+    let rows: Vec<Vec<usize>> = lines
+        .map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+        .collect();
+    // One row per interval of 50 units of time.
+    assert_eq!(rows.len(), 20);
+    for (index, row) in rows.iter().enumerate() {
+        assert_eq!(row[0], 50 * index);
+    }
+    // The message types add up to the total number of messages.
+    let total: usize = rows.iter().map(|row| row[1] + row[2] + row[3]).sum();
+    assert_eq!(total.to_string(), messages.trim());
+    assert!(rows.iter().all(|row| row[1] > 0));
+    // Committed commands only increase.
+    assert!(rows.windows(2).all(|pair| pair[0][4] <= pair[1][4]));
+    assert!(rows.last().unwrap()[4] > 0);
+}
+
+#[test]
+fn test_round_switches_file() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 52, /* nodes */ 3, /* commands per epoch */ 5,
+    )
+    .build();
+    let path = std::env::temp_dir().join(format!("librabft_round_switches_{}", std::process::id()));
+    sim.loop_until(
+        simulator::GlobalTime(1000),
+        Some(path.to_str().unwrap().to_string()),
+    );
+    let content = std::fs::read_to_string(path.join("round_switches.txt")).unwrap();
+    std::fs::remove_dir_all(&path).unwrap();
+    let mut lines = content.lines();
+    assert_eq!(lines.next(), Some("epoch,round,node 0,node 1,node 2"));
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    let keys: Vec<(usize, usize)> = rows
+        .iter()
+        .map(|row| (row[0].parse().unwrap(), row[1].parse().unwrap()))
+        .collect();
+    // Rows are sorted by epoch, then by round, and cover several epochs.
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    let epochs: BTreeSet<_> = keys.iter().map(|(epoch, _)| *epoch).collect();
+    assert!(epochs.len() > 2);
+    // Rounds start over when a new epoch begins.
+    assert!(keys.windows(2).any(|pair| pair[0].1 > pair[1].1));
+    // Every row records at least one switch.
+    assert!(rows
+        .iter()
+        .all(|row| row[2..].iter().any(|time| !time.is_empty())));
+}
+
+#[test]
+fn test_equivocating_leader() {
+    let mut behaviors = HashMap::new();
+    behaviors.insert(Author(3), simulator::ByzantineBehavior::Equivocate);
+    let monitor = SafetyMonitor::new();
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_byzantine_behaviors(behaviors)
+    .build()
+    .with_safety_monitor(monitor.clone());
+    sim.loop_until(simulator::GlobalTime(3000), None);
+    assert!(sim.equivocation_count() > 0);
+    let report = sim.fault_report();
+    assert_eq!(report.actual_faulty_weight, 1);
+    assert!(report.safe);
+    // The equivocator was the leader of some rounds committed by honest nodes.
+    let leaders =
+        simulator::CommittedLeaders::committed_leaders(sim.simulated_node(Author(0)).node());
+    assert!(leaders.iter().any(|(_, _, author)| *author == Author(3)));
+    // Honest nodes never commit two different blocks at the same position. Conflicting blocks
+    // have different times, hence this compares the full histories.
+    let histories: Vec<_> = (0..3)
+        .map(|index| {
+            sim.simulated_node(Author(index))
+                .context()
+                .committed_history()
+        })
+        .collect();
+    for history in &histories {
+        assert!(history.len() > 10);
+        let common_len = std::cmp::min(history.len(), histories[0].len());
+        assert_eq!(history[..common_len], histories[0][..common_len]);
+    }
+    // The safety monitor checked all the commits, including those of the equivocator.
+    let commit_count: usize = (0..4)
+        .map(|index| {
+            sim.simulated_node(Author(index))
+                .context()
+                .committed_history()
+                .len()
+        })
+        .sum();
+    assert_eq!(monitor.commit_count(), commit_count);
+    assert_eq!(
+        monitor.highest_height(),
+        histories.iter().map(|history| history.len()).max().unwrap()
+    );
+}
+
+#[test]
+fn test_crash_restart() {
+    let crash_schedule = simulator::CrashSchedule::new().with_crash(
+        Author(3),
+        simulator::GlobalTime(500),
+        simulator::GlobalTime(1500),
+    );
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 5,
+    )
+    .build()
+    .with_crash_schedule(crash_schedule);
+    sim.loop_until(simulator::GlobalTime(500), None);
+    let crashed_epoch = sim.simulated_node(Author(3)).node().snapshot().epoch_id;
+    let crashed_history_len = sim
+        .simulated_node(Author(3))
+        .context()
+        .committed_history()
+        .len();
+
+    // The other nodes move to a new epoch while the node is down.
+    sim.loop_until(simulator::GlobalTime(1499), None);
+    assert_eq!(
+        sim.simulated_node(Author(3)).node().snapshot().epoch_id,
+        crashed_epoch
+    );
+    assert!(sim.simulated_node(Author(0)).node().snapshot().epoch_id > crashed_epoch);
+    assert_eq!(sim.restart_count(), 0);
+
+    // After restarting from its storage, the node catches up via data sync.
+    sim.loop_until(simulator::GlobalTime(3000), None);
+    assert_eq!(sim.restart_count(), 1);
+    let node = sim.simulated_node(Author(3));
+    assert_eq!(
+        node.node().snapshot().epoch_id,
+        sim.simulated_node(Author(0)).node().snapshot().epoch_id
+    );
+    assert!(node.context().committed_history().len() > crashed_history_len);
+    assert!(sim.fault_report().safe);
+}
+
+#[test]
+fn test_message_loss() {
+    // Query all other nodes when commits stall for a while.
+    let config = NodeConfig {
+        target_commit_interval: Duration(200),
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_config(config)
+    .with_network_delay(simulator::RandomDelay::with_loss(
+        10.0, 4.0, /* loss probability */ 0.3,
+    ))
+    .build();
+    let contexts = sim.loop_until(simulator::GlobalTime(5000), None);
+    // Lost messages are eventually compensated by query-all requests.
+    for context in contexts {
+        assert!(!context.committed_history().is_empty());
+    }
+    let sent: usize = sim.edge_message_counts().values().sum();
+    assert!(sim.lost_message_count() * 4 > sent);
+    assert!(sim.lost_message_count() * 3 < sent);
+}
+
+#[test]
+fn test_healing_partition() {
+    let partition = simulator::Partition::new().with_split(
+        vec![Author(3)].into_iter().collect(),
+        simulator::GlobalTime(500),
+        simulator::GlobalTime(1500),
+    );
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build()
+    .with_partition(partition);
+    let history_len = |sim: &Simulator, index| {
+        sim.simulated_node(Author(index))
+            .context()
+            .committed_history()
+            .len()
+    };
+    // Let the messages sent before the split be delivered.
+    sim.loop_until(simulator::GlobalTime(700), None);
+    let minority_len = history_len(&sim, 3);
+    let majority_len = history_len(&sim, 0);
+
+    // Only the majority makes progress during the partition.
+    sim.loop_until(simulator::GlobalTime(1499), None);
+    assert_eq!(history_len(&sim, 3), minority_len);
+    assert!(history_len(&sim, 0) > majority_len);
+    let majority_len = history_len(&sim, 0);
+
+    // Both sides reconcile after healing.
+    sim.loop_until(simulator::GlobalTime(3000), None);
+    assert!(history_len(&sim, 3) > majority_len);
+    let minority_history = sim.simulated_node(Author(3)).context().committed_history();
+    let majority_history = sim.simulated_node(Author(0)).context().committed_history();
+    let common_len = std::cmp::min(minority_history.len(), majority_history.len());
+    assert_eq!(
+        minority_history[..common_len],
+        majority_history[..common_len]
+    );
+}
+
+#[test]
+fn test_trace_replay() {
+    let make_simulator = |network_delay| {
+        SimulatorBuilder::new(
+            /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .with_network_delay(network_delay)
+        .with_startup_mode(simulator::StartupMode::Simultaneous)
+        .build()
+    };
+    let path = std::env::temp_dir().join(format!("librabft_trace_{}.json", std::process::id()));
+    let mut sim = make_simulator(simulator::RandomDelay::new(10.0, 4.0));
+    sim.record_trace(&path);
+    let recorded: Vec<_> = sim
+        .loop_until(simulator::GlobalTime(1000), None)
+        .into_iter()
+        .map(|context| context.committed_history().clone())
+        .collect();
+
+    // Replayed events follow the recorded times rather than the (much slower) delays sampled
+    // from the distribution.
+    let mut sim = make_simulator(simulator::RandomDelay::new(50.0, 4.0));
+    sim.replay_trace(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let replayed: Vec<_> = sim
+        .loop_until(simulator::GlobalTime(1000), None)
+        .into_iter()
+        .map(|context| context.committed_history().clone())
+        .collect();
+    assert!(sim.trace_divergence().is_none());
+    assert!(!recorded[0].is_empty());
+    assert_eq!(
+        bcs::to_bytes(&recorded).unwrap(),
+        bcs::to_bytes(&replayed).unwrap()
+    );
+}
+
+#[test]
+fn test_round_robin_leaders() {
+    let config = NodeConfig {
+        leader_election: LeaderElectionPolicy::RoundRobin,
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .with_config(config)
+    .build();
+    sim.loop_until(simulator::GlobalTime(2000), None);
+    let node = sim.simulated_node(Author(0)).node();
+    let leaders = simulator::CommittedLeaders::committed_leaders(node);
+    assert!(leaders.len() > 10);
+    // Proposers are predictable: authors take turns in the order of the committee.
+    for (_, round, author) in leaders {
+        assert_eq!(author, Author(round.0 % 4));
+    }
+}
+
+#[test]
+fn test_vrf_leaders() {
+    let config = NodeConfig {
+        leader_election: LeaderElectionPolicy::Vrf,
+        ..NodeConfig::default()
+    };
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 10,
+    )
+    .with_config(config)
+    .build();
+    let contexts = sim.loop_until(simulator::GlobalTime(2000), None);
+    for context in contexts {
+        assert!(context.committed_history().len() > 10);
+    }
+    let histogram = sim.leader_histogram();
+    assert_eq!(histogram.len(), 4);
+}
+
+#[test]
+fn test_adaptive_timeout() {
+    // Count the timeouts that occur after the network suddenly becomes slower.
+    let run = |adaptive_timeout| {
+        let config = NodeConfig {
+            adaptive_timeout,
+            ..NodeConfig::default()
+        };
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 53, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .with_config(config)
+        .build();
+        sim.loop_until(simulator::GlobalTime(1000), None);
+        let view_changes = |sim: &Simulator| {
+            (0..4)
+                .map(|index| {
+                    simulator::ViewChangeLatencies::view_change_latencies(
+                        sim.simulated_node(Author(index)).node(),
+                    )
+                    .len()
+                })
+                .sum::<usize>()
+        };
+        let before = view_changes(&sim);
+        let mut sim = sim.with_link_delays(simulator::LinkDelayMatrix::uniform(
+            simulator::RandomDelay::new(40.0, 4.0),
+        ));
+        let contexts = sim.loop_until(simulator::GlobalTime(4000), None);
+        for context in contexts {
+            assert!(!context.committed_history().is_empty());
+        }
+        let after = view_changes(&sim);
+        if adaptive_timeout.is_some() {
+            for index in 0..4 {
+                let node = sim.simulated_node(Author(index)).node();
+                let average = node.adaptive_timeout().unwrap().average_latency();
+                assert!(average.unwrap() > Duration(40));
+            }
+        }
+        after - before
+    };
+    let fixed = run(None);
+    let adaptive = run(Some(0.3));
+    assert!(adaptive < fixed);
+}
+
+#[test]
+fn test_snapshot() {
+    let mut sim = SimulatorBuilder::new(
+        /* seed */ 41, /* nodes */ 4, /* commands per epoch */ 1000,
+    )
+    .build();
+    sim.loop_until(simulator::GlobalTime(500), None);
+    for index in 0..4 {
+        let node = sim.simulated_node(Author(index)).node();
+        let snapshot = node.snapshot();
+        assert_eq!(snapshot.epoch_id, node.snapshot().epoch_id);
+        assert_eq!(
+            snapshot.highest_committed_round,
+            ActiveRound::highest_committed_round(node)
+        );
+        assert!(snapshot.highest_committed_round > Round(0));
+        assert!(snapshot.highest_committed_round < snapshot.highest_quorum_certificate_round);
+        assert!(snapshot.highest_quorum_certificate_round < snapshot.current_round);
+        assert!(snapshot.active_leader.is_some());
+        assert!(snapshot.pending_vote_count <= 4);
+        assert!(snapshot.timeout_weight <= 4);
+        let value = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            serde_json::from_str::<NodeSnapshot<Author>>(&value).unwrap(),
+            snapshot
+        );
+    }
+}
+
+#[test]
+fn test_signing_delay() {
+    // Slow signatures delay every vote and proposal, hence the commits.
+    let run = |signing_delay| {
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 37, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .build()
+        .with_signing_delay(signing_delay);
+        let contexts = sim.loop_until(simulator::GlobalTime(2000), None);
+        for context in &contexts {
+            assert!(simulator::SignatureCount::signature_count(*context) > 0);
+        }
+        contexts
+            .iter()
+            .map(|context| context.committed_history().len())
+            .min()
+            .unwrap()
+    };
+    let fast = run(Duration(0));
+    let slow = run(Duration(10));
+    assert!(slow > 0);
+    assert!(slow < fast);
+}
+
+#[test]
+fn test_pruned_records() {
+    let run = |pruning_depth| {
+        let config = NodeConfig {
+            pruning_depth,
+            ..NodeConfig::default()
+        };
+        let mut sim = SimulatorBuilder::new(
+            /* seed */ 54, /* nodes */ 4, /* commands per epoch */ 1000,
+        )
+        .with_config(config)
+        .build();
+        sim.loop_until(simulator::GlobalTime(3000), None);
+        let max_records = (0..4)
+            .map(|index| {
+                sim.simulated_node(Author(index))
+                    .node()
+                    .known_records()
+                    .len()
+            })
+            .max()
+            .unwrap();
+        let histories: Vec<_> = (0..4)
+            .map(|index| {
+                sim.simulated_node(Author(index))
+                    .context()
+                    .committed_history()
+                    .clone()
+            })
+            .collect();
+        (max_records, histories)
+    };
+    let (unpruned_records, unpruned_histories) = run(None);
+    let (pruned_records, pruned_histories) = run(Some(5));
+    // Pruning changes the content of data-sync responses, hence the order of simultaneous
+    // events. Still, nodes keep committing the same commands.
+    for histories in &[unpruned_histories, pruned_histories] {
+        for history in histories {
+            assert!(history.len() > 50);
+            for other in histories {
+                let length = std::cmp::min(history.len(), other.len());
+                assert_eq!(history[..length], other[..length]);
+            }
+        }
+    }
+    assert!(unpruned_records > 100);
+    assert!(pruned_records < 30);
+}
@@ -8,7 +8,9 @@ use ed25519_dalek::{Digest as _, Sha512};
 use log::info;
 #[cfg(feature = "benchmark")]
 use std::convert::TryInto as _;
+use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration, Instant};
 
 #[cfg(test)]
@@ -24,8 +26,8 @@ pub struct BatchMaker {
     batch_size: usize,
     /// The maximum delay after which to seal the batch (in ms).
     max_batch_delay: u64,
-    /// Channel to receive transactions from the network.
-    rx_transaction: Receiver<Transaction>,
+    /// Channel to receive transactions from the network, shared with the other workers.
+    rx_transaction: Arc<Mutex<Receiver<Transaction>>>,
     /// Output channel to deliver sealed batches to the `QuorumWaiter`.
     tx_message: Sender<SerializedBatch>,
     /// Holds the current batch.
@@ -35,24 +37,33 @@ pub struct BatchMaker {
 }
 
 impl BatchMaker {
+    /// Spawn `workers` instances assembling batches in parallel. Idle workers take the next
+    /// transaction from the shared receiver. Each batch keeps the order of arrival of its
+    /// transactions, but the batches of different workers may interleave.
     pub fn spawn(
+        workers: usize,
         batch_size: usize,
         max_batch_delay: u64,
         rx_transaction: Receiver<Transaction>,
         tx_message: Sender<SerializedBatch>,
     ) {
-        tokio::spawn(async move {
-            Self {
-                batch_size,
-                max_batch_delay,
-                rx_transaction,
-                tx_message,
-                current_batch: Batch::with_capacity(batch_size * 2),
-                current_batch_size: 0,
-            }
-            .run()
-            .await;
-        });
+        let rx_transaction = Arc::new(Mutex::new(rx_transaction));
+        for _ in 0..std::cmp::max(workers, 1) {
+            let rx_transaction = rx_transaction.clone();
+            let tx_message = tx_message.clone();
+            tokio::spawn(async move {
+                Self {
+                    batch_size,
+                    max_batch_delay,
+                    rx_transaction,
+                    tx_message,
+                    current_batch: Batch::with_capacity(batch_size * 2),
+                    current_batch_size: 0,
+                }
+                .run()
+                .await;
+            });
+        }
     }
 
     /// Main loop receiving incoming transactions and creating batches.
@@ -63,7 +74,7 @@ impl BatchMaker {
         loop {
             tokio::select! {
                 // Assemble client transactions into batches of preset size.
-                Some(transaction) = self.rx_transaction.recv() => {
+                Some(transaction) = Self::receive(&self.rx_transaction) => {
                     self.current_batch_size += transaction.len();
                     self.current_batch.push(transaction);
                     if self.current_batch_size >= self.batch_size {
@@ -86,6 +97,11 @@ impl BatchMaker {
         }
     }
 
+    /// Wait for the next transaction not taken by another worker.
+    async fn receive(rx_transaction: &Mutex<Receiver<Transaction>>) -> Option<Transaction> {
+        rx_transaction.lock().await.recv().await
+    }
+
     /// Seal and broadcast the current batch.
     async fn seal(&mut self) {
         #[cfg(feature = "benchmark")]
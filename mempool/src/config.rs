@@ -10,6 +10,15 @@ pub type EpochNumber = u128;
 pub struct Parameters {
     pub batch_size: usize,
     pub max_batch_delay: u64,
+    /// Number of tasks assembling batches in parallel.
+    #[serde(default = "Parameters::default_batch_workers")]
+    pub batch_workers: usize,
+}
+
+impl Parameters {
+    fn default_batch_workers() -> usize {
+        1
+    }
 }
 
 impl Default for Parameters {
@@ -17,6 +26,7 @@ impl Default for Parameters {
         Self {
             batch_size: 500_000,
             max_batch_delay: 200,
+            batch_workers: Self::default_batch_workers(),
         }
     }
 }
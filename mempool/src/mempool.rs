@@ -45,8 +45,9 @@ impl Mempool {
             /* handler */ TxReceiverHandler { tx_batch_maker },
         );
 
-        // The transactions are sent to the `BatchMaker` that assembles them into batches.
+        // The transactions are sent to the `BatchMaker` workers that assemble them into batches.
         BatchMaker::spawn(
+            parameters.batch_workers,
             parameters.batch_size,
             parameters.max_batch_delay,
             /* rx_transaction */ rx_batch_maker,
@@ -10,6 +10,7 @@ async fn make_batch() {
 
     // Spawn a `BatchMaker` instance.
     BatchMaker::spawn(
+        /* workers */ 1,
         /* max_batch_size */ 200,
         /* max_batch_delay */ 1_000_000, // Ensure the timer is not triggered.
         rx_transaction,
@@ -34,6 +35,7 @@ async fn batch_timeout() {
 
     // Spawn a `BatchMaker` instance.
     BatchMaker::spawn(
+        /* workers */ 1,
         /* max_batch_size */ 200,
         /* max_batch_delay */ 50, // Ensure the timer is triggered.
         rx_transaction,
@@ -49,3 +51,65 @@ async fn batch_timeout() {
     let deserialized: Batch = bincode::deserialize(&serialized).unwrap();
     assert_eq!(deserialized, expected_batch)
 }
+
+#[tokio::test]
+async fn parallel_workers() {
+    let (tx_transaction, rx_transaction) = channel(100);
+    let (tx_message, mut rx_message) = channel(100);
+
+    // Spawn 4 `BatchMaker` workers sharing the transactions.
+    BatchMaker::spawn(
+        /* workers */ 4,
+        /* max_batch_size */ 300,
+        /* max_batch_delay */ 50,
+        rx_transaction,
+        tx_message,
+    );
+
+    // Send distinct transactions.
+    let transactions: Vec<Transaction> = (0..100u8).map(|i| vec![i; 100]).collect();
+    for transaction in &transactions {
+        tx_transaction.send(transaction.clone()).await.unwrap();
+    }
+
+    // Ensure that every transaction appears in exactly one batch.
+    let mut received = Vec::new();
+    while received.len() < transactions.len() {
+        let serialized = rx_message.recv().await.unwrap();
+        let batch: Batch = bincode::deserialize(&serialized).unwrap();
+        received.extend(batch);
+    }
+    received.sort();
+    assert_eq!(received, transactions);
+}
+
+#[tokio::test]
+async fn ordered_batches_across_workers() {
+    let (tx_transaction, rx_transaction) = channel(100);
+    let (tx_message, mut rx_message) = channel(100);
+
+    // Spawn 4 `BatchMaker` workers sharing the transactions.
+    BatchMaker::spawn(
+        /* workers */ 4,
+        /* max_batch_size */ 300,
+        /* max_batch_delay */ 50,
+        rx_transaction,
+        tx_message,
+    );
+
+    // Send transactions tagged with their order of submission.
+    for i in 0..100u8 {
+        tx_transaction.send(vec![i; 100]).await.unwrap();
+    }
+
+    // Ensure that each batch keeps the order of submission of its transactions, even though the
+    // batches of different workers may interleave.
+    let mut received = 0;
+    while received < 100 {
+        let serialized = rx_message.recv().await.unwrap();
+        let batch: Batch = bincode::deserialize(&serialized).unwrap();
+        let tags: Vec<_> = batch.iter().map(|transaction| transaction[0]).collect();
+        assert!(tags.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", tags);
+        received += batch.len();
+    }
+}
@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use std::fs::{self, OpenOptions};
 use std::io::BufWriter;
 use std::io::Write as _;
+use std::net::SocketAddr;
 
 pub trait Export: Serialize + DeserializeOwned {
     fn read(path: &str) -> Result<Self, NodeError> {
@@ -40,6 +41,9 @@ pub trait Export: Serialize + DeserializeOwned {
 pub struct Parameters {
     pub consensus: ConsensusParameters,
     pub mempool: MempoolParameters,
+    /// Where to serve JSON-RPC clients (requires the `rpc` feature).
+    #[serde(default)]
+    pub rpc: Option<SocketAddr>,
 }
 
 impl Export for Parameters {}
@@ -1,5 +1,7 @@
 mod config;
 mod node;
+#[cfg(feature = "rpc")]
+mod rpc;
 
 use crate::config::Export as _;
 use crate::config::{Committee, Secret};
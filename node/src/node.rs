@@ -1,20 +1,30 @@
 use crate::config::Export as _;
 use crate::config::{Committee, Parameters, Secret};
-use bft_driver::{Consensus, Context};
+#[cfg(feature = "rpc")]
+use crate::rpc::RpcServer;
+use bft_driver::{Consensus, Context, Parameters as ConsensusParameters};
 use bft_lib::base_types::NodeTime;
 use bft_lib::interfaces::ConsensusNode;
+use bft_lib::smr_context::Storage;
 use crypto::SignatureService;
 use futures::executor::block_on;
 use librabft_v2::{
     data_sync::{DataSyncNotification, DataSyncRequest, DataSyncResponse},
     node::{NodeConfig, NodeState},
+    pacemaker::LeaderElectionPolicy,
 };
 use log::info;
-use mempool::Mempool;
+#[cfg(not(feature = "rpc"))]
+use log::warn;
+use mempool::{Mempool, Payload};
 use store::{Store, StoreError};
 use thiserror::Error;
 use tokio::sync::mpsc::{channel, Receiver};
 
+#[cfg(test)]
+#[path = "tests/node_tests.rs"]
+mod node_tests;
+
 /// The default channel capacity for each channel of the node.
 pub const CHANNEL_CAPACITY: usize = 1_000;
 
@@ -31,7 +41,7 @@ pub enum NodeError {
 }
 
 pub struct LibraBftV2Node {
-    pub commit: Receiver<()>, // TODO: Should be a commit certificate.
+    pub commit: Receiver<Payload>, // TODO: Should be a commit certificate.
 }
 
 impl LibraBftV2Node {
@@ -42,7 +52,7 @@ impl LibraBftV2Node {
         parameters: Option<&str>,
     ) -> Result<Self, NodeError> {
         let (tx_payload, rx_payload) = channel(CHANNEL_CAPACITY);
-        let (_tx_commit, rx_commit) = channel(CHANNEL_CAPACITY);
+        let (tx_commit, rx_commit) = channel(CHANNEL_CAPACITY);
 
         // Read the committee and secret key from file.
         let committee = Committee::read(committee_file)?;
@@ -59,6 +69,29 @@ impl LibraBftV2Node {
         // Make the data store.
         let store = Store::new(store_path)?;
 
+        // Spawn the RPC server, if any. It observes the commands committed by the consensus
+        // and the progress of the node.
+        #[cfg(feature = "rpc")]
+        let (tx_commit, rx_commit, observer) = match parameters.rpc {
+            Some(address) => {
+                let (tx_rpc, rx_rpc) = channel(CHANNEL_CAPACITY);
+                let mempool_address = committee
+                    .mempool
+                    .address(&name)
+                    .expect("Our public key is not in the committee");
+                let observer = RpcServer::spawn(address, mempool_address, rx_rpc, tx_commit);
+                (tx_rpc, rx_commit, Some(observer))
+            }
+            None => (tx_commit, rx_commit, None),
+        };
+        #[cfg(not(feature = "rpc"))]
+        let observer = {
+            if parameters.rpc.is_some() {
+                warn!("Ignoring the RPC address: the node was built without the `rpc` feature");
+            }
+            None
+        };
+
         // Spawn the mempool.
         Mempool::spawn(
             name,
@@ -71,7 +104,7 @@ impl LibraBftV2Node {
         // The `SignatureService` is used to require signatures on specific digests.
         let signature_service = SignatureService::new(secret_key);
 
-        // Initialize the node state.
+        // Initialize the node state, unless the store holds the state of a previous run.
         {
             let mut context = Context::new(
                 name,
@@ -79,28 +112,24 @@ impl LibraBftV2Node {
                 store.clone(),
                 signature_service.clone(),
             );
-            let config = NodeConfig {
-                target_commit_interval: parameters.consensus.target_commit_interval,
-                delta: parameters.consensus.delta,
-                gamma: parameters.consensus.gamma,
-                lambda: parameters.consensus.lambda,
-            };
-            let mut node = NodeState::make_initial_state(&context, config, NodeTime(0));
-            block_on(node.save_node(&mut context)).expect("Failed to save initial node state");
+            Self::initialize_node_state(&mut context, &parameters.consensus);
         }
 
         // Spawn the consensus.
         Consensus::spawn::<
             NodeState<Context>,
             DataSyncNotification<Context>,
-            DataSyncRequest,
+            DataSyncRequest<Context>,
             DataSyncResponse<Context>,
         >(
             name,
             committee.consensus.clone(),
+            parameters.consensus,
             signature_service,
             store,
-            /* rx_mempool */ rx_payload, //tx_commit,
+            /* rx_mempool */ rx_payload,
+            tx_commit,
+            observer,
         );
 
         info!(
@@ -115,12 +144,34 @@ impl LibraBftV2Node {
         Ok(Self { commit: rx_commit })
     }
 
+    /// Save the initial state of the consensus node, unless the store already holds the state
+    /// saved by a previous run, in which case the consensus recovers from it.
+    fn initialize_node_state(context: &mut Context, parameters: &ConsensusParameters) {
+        let previous_state = block_on(context.read_value("node_state".to_string()))
+            .expect("Failed to read node state");
+        if previous_state.is_some() {
+            info!("Recovering the node state saved by a previous run");
+            return;
+        }
+        let config = NodeConfig {
+            target_commit_interval: parameters.target_commit_interval,
+            delta: parameters.delta,
+            gamma: parameters.gamma,
+            lambda: parameters.lambda,
+            // The committee is read from a hash map, hence leaders must not depend on its order.
+            leader_election: LeaderElectionPolicy::ContentAddressed,
+            ..Default::default()
+        };
+        let mut node = NodeState::make_initial_state(context, config, NodeTime(0));
+        block_on(node.save_node(context)).expect("Failed to save initial node state");
+    }
+
     pub fn print_key_file(filename: &str) -> Result<(), NodeError> {
         Secret::new().write(filename)
     }
 
     pub async fn analyze_block(&mut self) {
-        while let Some(_certificate) = self.commit.recv().await {
+        while let Some(_command) = self.commit.recv().await {
             // This is where we can further process committed block.
         }
     }
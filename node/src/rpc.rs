@@ -0,0 +1,213 @@
+use bft_driver::{Context, NodeObserver};
+use bytes::Bytes;
+use futures::sink::SinkExt as _;
+use futures::stream::StreamExt as _;
+use librabft_v2::node::NodeState;
+use log::{info, warn};
+use mempool::Payload;
+use network::SimpleSender;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Mutex;
+use tokio_util::codec::{Framed, LinesCodec};
+
+#[cfg(test)]
+#[path = "tests/rpc_tests.rs"]
+mod rpc_tests;
+
+/// Error code of the JSON-RPC 2.0 specification for unparsable requests.
+const PARSE_ERROR: i64 = -32700;
+/// Error code of the JSON-RPC 2.0 specification for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// Error code of the JSON-RPC 2.0 specification for invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+
+/// A JSON-RPC 2.0 request, sent as a single line of JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// A JSON-RPC 2.0 response, sent as a single line of JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// Parameters of the `submit_transaction` method.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubmitTransaction {
+    pub transaction: Vec<u8>,
+}
+
+/// Result of the `get_status` method.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct NodeStatus {
+    pub epoch: usize,
+    pub round: usize,
+    pub commit_count: usize,
+}
+
+/// Serves JSON-RPC requests of standard clients, one connection per task.
+#[derive(Clone)]
+pub struct RpcServer {
+    /// The address of our mempool, where to forward client transactions.
+    mempool_address: SocketAddr,
+    /// Sender of client transactions to the mempool.
+    network: Arc<Mutex<SimpleSender>>,
+    /// Epoch and round of the node after its latest update.
+    epoch: Arc<AtomicUsize>,
+    round: Arc<AtomicUsize>,
+    /// Number of committed commands so far.
+    commit_count: Arc<AtomicUsize>,
+}
+
+impl RpcServer {
+    /// Spawn a server listening on `address`. The commands committed by the consensus are
+    /// counted on their way from `rx_commit` to `tx_commit`. Return the observer through which
+    /// the consensus reports the progress of the node.
+    pub fn spawn(
+        address: SocketAddr,
+        mempool_address: SocketAddr,
+        mut rx_commit: Receiver<Payload>,
+        tx_commit: Sender<Payload>,
+    ) -> NodeObserver<NodeState<Context>> {
+        let server = Self {
+            mempool_address,
+            network: Arc::new(Mutex::new(SimpleSender::new())),
+            epoch: Arc::new(AtomicUsize::new(0)),
+            round: Arc::new(AtomicUsize::new(0)),
+            commit_count: Arc::new(AtomicUsize::new(0)),
+        };
+        let (epoch, round) = (server.epoch.clone(), server.round.clone());
+        let observer = Box::new(move |node: &NodeState<Context>| {
+            let (node_epoch, node_round) = node.global_highest_round();
+            epoch.store(node_epoch.0, Ordering::SeqCst);
+            round.store(node_round.0, Ordering::SeqCst);
+        });
+
+        let commit_count = server.commit_count.clone();
+        tokio::spawn(async move {
+            while let Some(command) = rx_commit.recv().await {
+                commit_count.fetch_add(1, Ordering::SeqCst);
+                if tx_commit.send(command).await.is_err() {
+                    warn!("Failed to deliver committed command");
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            let listener = TcpListener::bind(&address)
+                .await
+                .expect("Failed to bind RPC port");
+            info!("Listening to RPC clients on {}", address);
+            loop {
+                match listener.accept().await {
+                    Ok((socket, peer)) => {
+                        let server = server.clone();
+                        tokio::spawn(async move {
+                            server.serve(socket, peer).await;
+                        });
+                    }
+                    Err(e) => warn!("Failed to accept RPC connection: {}", e),
+                }
+            }
+        });
+        observer
+    }
+
+    async fn serve(&self, socket: TcpStream, peer: SocketAddr) {
+        let mut transport = Framed::new(socket, LinesCodec::new());
+        while let Some(line) = transport.next().await {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("Failed to read RPC request from {}: {}", peer, e);
+                    return;
+                }
+            };
+            let response = match serde_json::from_str::<RpcRequest>(&line) {
+                Ok(request) => self.handle_request(request).await,
+                Err(e) => Self::error(Value::Null, PARSE_ERROR, e.to_string()),
+            };
+            let response = serde_json::to_string(&response).expect("Failed to serialize response");
+            if let Err(e) = transport.send(response).await {
+                warn!("Failed to send RPC response to {}: {}", peer, e);
+                return;
+            }
+        }
+    }
+
+    async fn handle_request(&self, request: RpcRequest) -> RpcResponse {
+        match request.method.as_str() {
+            "submit_transaction" => {
+                match serde_json::from_value::<SubmitTransaction>(request.params) {
+                    Ok(params) => {
+                        self.network
+                            .lock()
+                            .await
+                            .send(self.mempool_address, Bytes::from(params.transaction))
+                            .await;
+                        Self::success(request.id, Value::Bool(true))
+                    }
+                    Err(e) => Self::error(request.id, INVALID_PARAMS, e.to_string()),
+                }
+            }
+            "get_status" => Self::success(
+                request.id,
+                serde_json::to_value(self.status()).expect("Failed to serialize status"),
+            ),
+            method => Self::error(
+                request.id,
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            ),
+        }
+    }
+
+    fn status(&self) -> NodeStatus {
+        NodeStatus {
+            epoch: self.epoch.load(Ordering::SeqCst),
+            round: self.round.load(Ordering::SeqCst),
+            commit_count: self.commit_count.load(Ordering::SeqCst),
+        }
+    }
+
+    fn success(id: Value, result: Value) -> RpcResponse {
+        RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Value, code: i64, message: String) -> RpcResponse {
+        RpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+        }
+    }
+}
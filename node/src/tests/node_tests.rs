@@ -0,0 +1,51 @@
+use super::*;
+use bft_driver::Committee as ConsensusCommittee;
+use std::fs;
+
+// Signing blocks a runtime thread while waiting on the signature service.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn recover_node_state_after_restart() {
+    let path = ".db_test_recover_node_state_after_restart";
+    let _ = fs::remove_dir_all(path);
+    let store = Store::new(path).unwrap();
+    let secret = Secret::new();
+    let name = secret.name;
+    let committee = ConsensusCommittee::new(vec![(name, 1, "127.0.0.1:0".parse().unwrap())], 1);
+    let signature_service = SignatureService::new(secret.secret);
+    let parameters = ConsensusParameters {
+        gamma: 2.0,
+        lambda: 0.5,
+        ..ConsensusParameters::default()
+    };
+    let make_context = || {
+        Context::new(
+            name,
+            committee.clone(),
+            store.clone(),
+            signature_service.clone(),
+        )
+    };
+
+    // Boot the node and let it make progress on its own.
+    let mut context = make_context();
+    LibraBftV2Node::initialize_node_state(&mut context, &parameters);
+    let mut node: NodeState<Context> =
+        block_on(NodeState::load_node(&mut context, NodeTime(0))).unwrap();
+    let initial_round = node.global_highest_round();
+    for time in 1..10 {
+        node.update_node(&mut context, NodeTime(time * 1000));
+        block_on(node.save_node(&mut context)).unwrap();
+    }
+    assert!(node.global_highest_round() > initial_round);
+
+    // Crash the node, then restart it on the same store.
+    drop(context);
+    let mut context = make_context();
+    LibraBftV2Node::initialize_node_state(&mut context, &parameters);
+    let restored: NodeState<Context> =
+        block_on(NodeState::load_node(&mut context, NodeTime(10_000))).unwrap();
+    // Only the persisted part of the state (e.g. the record store) is expected to be restored.
+    let expected: NodeState<Context> =
+        bincode::deserialize(&bincode::serialize(&node).unwrap()).unwrap();
+    assert!(restored == expected);
+}
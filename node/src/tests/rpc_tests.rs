@@ -0,0 +1,147 @@
+use super::*;
+use crate::config::Export as _;
+use crate::config::{Committee, Parameters, Secret};
+use crate::node::LibraBftV2Node;
+use bft_driver::{Committee as ConsensusCommittee, Parameters as ConsensusParameters};
+use mempool::Committee as MempoolCommittee;
+use std::fs;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+/// A local address on a free port chosen by the OS.
+fn free_address() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+async fn call(
+    transport: &mut Framed<TcpStream, LinesCodec>,
+    id: u64,
+    method: &str,
+    params: Value,
+) -> RpcResponse {
+    let request = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: Value::from(id),
+        method: method.to_string(),
+        params,
+    };
+    transport
+        .send(serde_json::to_string(&request).unwrap())
+        .await
+        .unwrap();
+    let line = transport.next().await.unwrap().unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+// Each consensus blocks a runtime thread while waiting on the store and the signature service,
+// hence the runtime needs more threads than nodes.
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn submit_transaction_and_commit() {
+    let directory = ".test_rpc_submit_transaction_and_commit";
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all(directory).unwrap();
+
+    // Write the committee and the key files of 4 nodes.
+    let keys: Vec<_> = (0..4).map(|_| Secret::new()).collect();
+    let committee = Committee {
+        mempool: MempoolCommittee::new(
+            keys.iter()
+                .map(|key| (key.name, 1, free_address()))
+                .collect(),
+            1,
+        ),
+        consensus: ConsensusCommittee::new(
+            keys.iter()
+                .map(|key| (key.name, 1, free_address()))
+                .collect(),
+            1,
+        ),
+    };
+    let committee_file = format!("{}/committee.json", directory);
+    committee.write(&committee_file).unwrap();
+
+    // Round durations grow slowly enough for the test to commit quickly.
+    let make_parameters = |rpc| Parameters {
+        consensus: ConsensusParameters {
+            gamma: 2.0,
+            lambda: 0.5,
+            ..ConsensusParameters::default()
+        },
+        rpc,
+        ..Parameters::default()
+    };
+    // Only the first node serves RPC clients.
+    let rpc_address = free_address();
+    let rpc_parameters_file = format!("{}/rpc_parameters.json", directory);
+    make_parameters(Some(rpc_address))
+        .write(&rpc_parameters_file)
+        .unwrap();
+    let parameters_file = format!("{}/parameters.json", directory);
+    make_parameters(None).write(&parameters_file).unwrap();
+
+    let mut nodes = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        let key_file = format!("{}/node_{}.json", directory, i);
+        key.write(&key_file).unwrap();
+        let store_path = format!("{}/db_{}", directory, i);
+        let parameters = if i == 0 {
+            &rpc_parameters_file
+        } else {
+            &parameters_file
+        };
+        let node = LibraBftV2Node::new(&committee_file, &key_file, &store_path, Some(parameters))
+            .await
+            .unwrap();
+        nodes.push(node);
+    }
+    let mut node = nodes.remove(0);
+    for mut other in nodes {
+        // Sink the commit channel.
+        tokio::spawn(async move { while other.commit.recv().await.is_some() {} });
+    }
+
+    // Submit a transaction through the RPC server.
+    sleep(Duration::from_millis(100)).await;
+    let stream = TcpStream::connect(rpc_address).await.unwrap();
+    let mut transport = Framed::new(stream, LinesCodec::new());
+    let transaction = vec![1u8, 2, 3, 4, 5, 6, 7, 8, 9];
+    let response = call(
+        &mut transport,
+        1,
+        "submit_transaction",
+        serde_json::json!({ "transaction": transaction }),
+    )
+    .await;
+    assert_eq!(response.id, Value::from(1));
+    assert_eq!(response.result, Some(Value::Bool(true)));
+
+    // Wait for the transaction to appear in a committed batch.
+    timeout(Duration::from_secs(30), async {
+        loop {
+            let command = node.commit.recv().await.unwrap();
+            if let Ok(batch) = bincode::deserialize::<Vec<Vec<u8>>>(&command) {
+                if batch.contains(&transaction) {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .expect("The transaction was not committed");
+
+    // The status reflects the commit.
+    let response = call(&mut transport, 2, "get_status", Value::Null).await;
+    let status: NodeStatus = serde_json::from_value(response.result.unwrap()).unwrap();
+    assert_eq!(status.epoch, 1);
+    assert!(status.round > 0);
+    assert!(status.commit_count > 0);
+
+    // Unknown methods are rejected.
+    let response = call(&mut transport, 3, "unknown", Value::Null).await;
+    assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+
+    let _ = fs::remove_dir_all(directory);
+}